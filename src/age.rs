@@ -0,0 +1,135 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::meta::Meta;
+
+/// Seconds since the unix epoch, which is how timestamps are stored in metadata
+pub fn now() -> u64 {
+    to_unix(SystemTime::now())
+}
+
+pub fn to_unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|i| i.as_secs()).unwrap_or(0)
+}
+
+pub fn from_unix(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// When the tempdir at `entry` was created.
+///
+/// Filesystems disagree on what timestamps they keep (ext4 only reports birth time through statx,
+/// some network filesystems not at all), so the time recorded in the metadata when we created
+/// the tempdir is authoritative. Tempdirs created before that was recorded fall back to the
+/// birth time of the entry, and then to its modification time. For symlinks both of those
+/// are the time the tempdir was created, since nobody modifies a symlink.
+pub fn created(entry: &Path) -> Result<SystemTime> {
+    if let Some(created) = Meta::load(entry)?.created {
+        return Ok(from_unix(created));
+    }
+
+    let meta = std::fs::symlink_metadata(entry).wrap_err(format!("get metadata of {entry:?}"))?;
+    match meta.created() {
+        Ok(created) => Ok(created),
+        Err(_) => meta.modified().wrap_err(format!("get modification time of {entry:?}")),
+    }
+}
+
+/// When the tempdir at `entry` was last entered or operated on, or otherwise when it was created
+pub fn last_used(entry: &Path) -> Result<SystemTime> {
+    match Meta::load(entry)?.last_used {
+        Some(last_used) => Ok(from_unix(last_used)),
+        None => created(entry),
+    }
+}
+
+/// How long ago the tempdir at `entry` was created
+pub fn age(entry: &Path) -> Result<Duration> {
+    Ok(SystemTime::now().duration_since(created(entry)?).unwrap_or_default())
+}
+
+/// A rough human-readable version of a duration, like `3 days` or `5 minutes`
+pub fn human(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (amount, unit) = match secs {
+        0..=59 => return "less than a minute".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86_399 => (secs / 3600, "hour"),
+        86_400..=1_209_599 => (secs / 86_400, "day"),
+        _ => (secs / 604_800, "week"),
+    };
+
+    if amount == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{amount} {unit}s")
+    }
+}
+
+/// Parse durations like `30s`, `15m`, `12h`, `7d` or `2w`. A bare number is in days.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a duration like `7d` or `12h`, got `{s}`"))?;
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        other => return Err(format!("unknown unit `{other}`, expected one of s, m, h, d or w")),
+    };
+
+    Ok(Duration::from_secs(amount * seconds))
+}
+
+/// Parse a point in time, either a date like `2023-07-14`, a date and time like
+/// `2023-07-14 13:37`, or a duration ago like `3d`. A bare date means the end of that day.
+pub fn parse_time(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if let Ok(ago) = parse_duration(s) {
+        return Ok(now().saturating_sub(ago.as_secs()));
+    }
+
+    let naive = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+        .ok()
+        .or_else(|| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_hms_opt(23, 59, 59))
+        .ok_or_else(|| format!("expected a date like `2023-07-14`, `2023-07-14 13:37` or a duration like `3d`, got `{s}`"))?;
+    let local = naive
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .ok_or_else(|| format!("`{s}` doesn't exist in the local timezone"))?;
+
+    Ok(local.timestamp().max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("7"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn human_durations() {
+        assert_eq!(human(Duration::from_secs(5)), "less than a minute");
+        assert_eq!(human(Duration::from_secs(60)), "1 minute");
+        assert_eq!(human(Duration::from_secs(3 * 60 * 60 + 10)), "3 hours");
+        assert_eq!(human(Duration::from_secs(3 * 24 * 60 * 60)), "3 days");
+        assert_eq!(human(Duration::from_secs(15 * 24 * 60 * 60)), "2 weeks");
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use color_eyre::eyre::{bail, Context, ContextCompat};
+use color_eyre::Result;
+use crate::config::{self, Config};
+use crate::{progress, trash};
+
+pub const EXTENSION: &str = ".tar.zst";
+
+/// Where `t archive` puts archives: `archive_dir` from the config, or `~/.local/share/t-rs/archive`
+pub fn dir(home: &Path, config: &Config) -> PathBuf {
+    match config.archive_dir {
+        Some(ref i) => config::expand_home(i, home),
+        None => trash::data_dir(home).join("t-rs").join("archive"),
+    }
+}
+
+/// The name of the tempdir an archive was made from, `foo` for `foo.tar.zst`
+pub fn name_of(file: &Path) -> Option<String> {
+    let name = file.file_name()?.to_string_lossy();
+    let name = name.strip_suffix(EXTENSION).unwrap_or(&name);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Pack the contents of `dir` into `file`
+pub fn create(dir: &Path, file: &Path) -> Result<()> {
+    if file.exists() {
+        bail!("{file:?} already exists");
+    }
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+    }
+
+    // tar writes to us so we can show how much it wrote, there's no telling how well it compresses
+    let mut child = Command::new("tar")
+        .arg("--zstd").arg("-cf").arg("-").arg("-C").arg(dir).arg(".")
+        .stdout(Stdio::piped())
+        .spawn()
+        .wrap_err("run tar")?;
+    let mut stdout = child.stdout.take().wrap_err("tar has no stdout")?;
+    let mut out = File::create(file).wrap_err(format!("create {file:?}"))?;
+
+    let progress = progress::spinner()?;
+    let res = std::io::copy(&mut stdout, &mut progress.wrap_write(&mut out));
+    progress.finish_and_clear();
+    drop(out);
+
+    let status = child.wait().wrap_err("wait for tar")?;
+    if res.is_err() || !status.success() {
+        let _ = std::fs::remove_file(file);
+    }
+    res.wrap_err(format!("write {file:?}"))?;
+    check(status, file)
+}
+
+/// Unpack `file` into `dir`
+pub fn extract(file: &Path, dir: &Path) -> Result<()> {
+    let mut input = File::open(file).wrap_err(format!("open {file:?}"))?;
+    let len = input.metadata().wrap_err(format!("get metadata of {file:?}"))?.len();
+
+    let mut child = Command::new("tar")
+        .arg("--zstd").arg("-xf").arg("-").arg("-C").arg(dir)
+        .stdin(Stdio::piped())
+        .spawn()
+        .wrap_err("run tar")?;
+    let mut stdin = child.stdin.take().wrap_err("tar has no stdin")?;
+
+    let progress = progress::bytes(len)?;
+    let res = std::io::copy(&mut progress.wrap_read(&mut input), &mut stdin);
+    progress.finish_and_clear();
+    // so tar sees the end of the archive
+    drop(stdin);
+
+    let status = child.wait().wrap_err("wait for tar")?;
+    check(status, file)?;
+    res.wrap_err(format!("read {file:?}")).map(|_| ())
+}
+
+fn check(status: ExitStatus, file: &Path) -> Result<()> {
+    if !status.success() {
+        bail!("tar failed on {file:?} ({status})");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(name_of(Path::new("/a/foo.tar.zst")).as_deref(), Some("foo"));
+        assert_eq!(name_of(Path::new("bar.tar.gz")).as_deref(), Some("bar.tar.gz"));
+        assert_eq!(name_of(Path::new(".tar.zst")), None);
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Component, Path, PathBuf};
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+use crate::journal::{Journal, Op};
+use crate::layout::Layout;
+use crate::lock::Lock;
+use crate::meta::{self, Meta};
+use crate::warnings::{warn, Kind};
+use crate::{archive, info, platform, Backing, STATE_DIR};
+
+/// What's in a backup besides an archive per tempdir
+const MANIFEST: &str = "manifest.json";
+
+/// A tempdir in a backup
+#[derive(Serialize, Deserialize, Debug)]
+struct Saved {
+    /// its entry, relative to the tempdirs root
+    entry: PathBuf,
+    /// the archive of its files in the backup
+    archive: String,
+    /// whether the entry was a symlink to a backing directory, otherwise it was a real (persistent) directory
+    symlink: bool,
+    meta: Meta,
+}
+
+/// A directory in `near` to put a backup together in or take one apart, removed when it's dropped.
+/// It holds a copy of every tempdir, which might not fit in the system's temp dir (often a tmpfs) but does fit
+/// where the backup goes or the tempdirs come back. Only we can read it, the tempdirs might be private
+fn staging(near: &Path, purpose: &str) -> Result<TempDir> {
+    std::fs::create_dir_all(near).wrap_err(format!("create {near:?}"))?;
+    let dir = TempDir::new_in(near, &format!(".t-rs-{purpose}")).wrap_err("create staging dir")?;
+    platform::set_mode(dir.path(), 0o700).wrap_err(format!("set permissions of {:?}", dir.path()))?;
+    Ok(dir)
+}
+
+/// Whether `path` stays inside whatever it's joined to, the manifest could come from anywhere
+fn contained(path: &Path) -> bool {
+    path.components().next().is_some() && path.components().all(|i| matches!(i, Component::Normal(_)))
+}
+
+/// Pack every tempdir in `tempdirs` with its metadata into `file`, returning how many there were.
+/// Tempdirs whose files aren't here, like ones from other machines, are left out
+pub fn backup(tempdirs: &Path, layout: Layout, file: &Path) -> Result<usize> {
+    if file.exists() {
+        bail!("{file:?} already exists");
+    }
+    let dest = file.parent().filter(|i| !i.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let staging = staging(dest, "backup")?;
+
+    let mut saved = Vec::new();
+    for (n, i) in layout.entries(tempdirs)?.into_iter().enumerate() {
+        let name = i.strip_prefix(tempdirs).unwrap_or(&i).to_path_buf();
+        if !i.exists() {
+            warn(Kind::Skipped, format!("{} has no files here", name.to_string_lossy()));
+            continue;
+        }
+
+        info!("packing {}", name.to_string_lossy());
+        let archive = format!("{n}{}", archive::EXTENSION);
+        // tar doesn't follow the symlink of non-persistent tempdirs
+        let dir = i.canonicalize().wrap_err(format!("canonicalize {i:?}"))?;
+        archive::create(&dir, &staging.path().join(&archive))?;
+        saved.push(Saved { entry: name, archive, symlink: i.is_symlink(), meta: Meta::load(&i)? });
+    }
+
+    let manifest = serde_json::to_string_pretty(&saved).wrap_err("serialize manifest")?;
+    std::fs::write(staging.path().join(MANIFEST), manifest).wrap_err("write manifest")?;
+    archive::create(staging.path(), file)?;
+    Ok(saved.len())
+}
+
+/// Bring back the tempdirs in the backup `file` in `tempdirs`, with their files in new backing directories made
+/// like `backing`. Tempdirs that exist already are skipped. Returns how many were restored
+pub fn restore(tempdirs: &Path, file: &Path, backing: &Backing) -> Result<usize> {
+    // with our bookkeeping, so it's not mistaken for a tempdir
+    let staging = staging(&tempdirs.join(STATE_DIR), "restore")?;
+    archive::extract(file, staging.path())?;
+    let manifest = std::fs::read_to_string(staging.path().join(MANIFEST)).wrap_err(format!("{file:?} isn't a backup made by `t backup`"))?;
+    let saved: Vec<Saved> = serde_json::from_str(&manifest).wrap_err("parse manifest")?;
+    if let Some(i) = saved.iter().find(|i| !contained(&i.entry) || !contained(Path::new(&i.archive))) {
+        bail!("{file:?} has {:?}, which is outside the tempdirs root", i.entry);
+    }
+
+    let _lock = Lock::acquire(tempdirs)?;
+    let journal = Journal::new(tempdirs);
+    let mut restored = 0;
+    for i in saved {
+        let entry = tempdirs.join(&i.entry);
+        if entry.exists() || entry.is_symlink() {
+            warn(Kind::Skipped, format!("{} exists already", i.entry.to_string_lossy()));
+            continue;
+        }
+        if let Some(parent) = entry.parent() {
+            std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+        }
+
+        info!("unpacking {}", i.entry.to_string_lossy());
+        let dir = if i.symlink {
+            let dir = backing.create()?;
+            platform::link_dir(&dir, &entry).wrap_err("create symlink")?;
+            dir
+        } else {
+            std::fs::create_dir(&entry).wrap_err(format!("create {entry:?}"))?;
+            entry.clone()
+        };
+        archive::extract(&staging.path().join(&i.archive), &dir)?;
+
+        // it's this machine's tempdir now
+        Meta { host: meta::hostname(), ..i.meta.clone() }.save(&entry)?;
+        journal.record(Op::Create { entry, origin: i.meta.origin })?;
+        restored += 1;
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_paths_stay_inside() {
+        assert!(contained(Path::new("foo")));
+        assert!(contained(Path::new("group/foo")));
+        assert!(!contained(Path::new("")));
+        assert!(!contained(Path::new("/etc/foo")));
+        assert!(!contained(Path::new("../foo")));
+        assert!(!contained(Path::new("group/../../foo")));
+        assert!(!contained(Path::new("./foo")));
+    }
+}
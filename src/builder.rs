@@ -0,0 +1,379 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use fs_extra::dir::{CopyOptions, TransitProcessResult};
+use crate::hooks::{Hook, Hooks};
+use crate::journal::{Journal, Op};
+use crate::layout::Layout;
+use crate::lock::Lock;
+use crate::meta::{self, Meta};
+use crate::registry::Registry;
+use crate::trash::Trash;
+use crate::{age, direnv, info, notice, output, pipeline, progress, prompt, Backing};
+
+/// Everything that goes into making a new tempdir, so every command creates them the same way:
+///
+/// ```ignore
+/// let dir = TempdirBuilder::new(&tempdirs, layout, "scratch", &backing)
+///     .seed(vec![PathBuf::from("notes.txt")], false)
+///     .ttl(Some(Duration::from_secs(60 * 60)))
+///     .create()?;
+/// ```
+pub struct TempdirBuilder<'a> {
+    tempdirs: &'a Path,
+    layout: Layout,
+    name: String,
+    backing: &'a Backing,
+    group: Option<&'a str>,
+    origin: Option<PathBuf>,
+    hidden: bool,
+    ttl: Option<Duration>,
+    seed: Vec<PathBuf>,
+    move_seed: bool,
+    template: Option<(&'a str, &'a Path)>,
+    scaffold: Option<(&'a str, &'a str)>,
+    hooks: Option<&'a Hooks>,
+    direnv: Option<&'a [String]>,
+    on_collision: OnCollision,
+    max_size: Option<u64>,
+    trash: Option<&'a Trash>,
+}
+
+/// What to do when a tempdir with the name exists already
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnCollision {
+    /// say so and stay where we are
+    #[default]
+    Fail,
+    /// add `-2`, `-3`, ... to the name until it's free
+    Unique,
+    /// cd into the existing one
+    Reuse,
+}
+
+/// What [`TempdirBuilder::create`] did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Created {
+    /// a new tempdir, at this entry
+    New(PathBuf),
+    /// the name was taken, and [`OnCollision::Reuse`] said to use that tempdir
+    Reused(PathBuf),
+    /// a deleted tempdir with the name came back from the trash
+    Restored(PathBuf),
+    /// the name is taken by the tempdir at this entry, and [`OnCollision::Fail`] said to leave it at that
+    Taken(PathBuf),
+}
+
+impl Created {
+    /// The tempdir to cd into, unless the name was taken
+    pub fn entry(self) -> Option<PathBuf> {
+        match self {
+            Created::New(i) | Created::Reused(i) | Created::Restored(i) => Some(i),
+            Created::Taken(_) => None,
+        }
+    }
+}
+
+impl OnCollision {
+    /// What to do when the name is taken. `--force` and `--unique` say so explicitly, and names t-rs picked itself
+    /// are made unique. `unique_names` from the config only applies to commands that have to create a tempdir
+    /// (`must_create`), a bare `t foo` goes to the existing `foo` instead
+    pub fn choose(force: bool, unique: bool, named: bool, unique_names: bool, must_create: bool) -> Self {
+        if force {
+            OnCollision::Reuse
+        } else if unique || !named || (unique_names && must_create) {
+            OnCollision::Unique
+        } else {
+            OnCollision::Fail
+        }
+    }
+}
+
+impl<'a> TempdirBuilder<'a> {
+    pub fn new(tempdirs: &'a Path, layout: Layout, name: &str, backing: &'a Backing) -> Self {
+        Self {
+            tempdirs,
+            layout,
+            name: name.to_string(),
+            backing,
+            group: None,
+            origin: None,
+            hidden: false,
+            ttl: None,
+            seed: Vec::new(),
+            move_seed: false,
+            template: None,
+            scaffold: None,
+            hooks: None,
+            direnv: None,
+            on_collision: OnCollision::Fail,
+            max_size: None,
+            trash: None,
+        }
+    }
+
+    pub fn backing(mut self, backing: &'a Backing) -> Self {
+        self.backing = backing;
+        self
+    }
+
+    /// The group to put it in, with the `by-group` layout
+    pub fn group(mut self, group: Option<&'a str>) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// The directory `t` was run from, the current directory by default
+    pub fn origin(mut self, origin: &Path) -> Self {
+        self.origin = Some(origin.to_path_buf());
+        self
+    }
+
+    /// Don't link it from the tempdirs root, like `t hidden`
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Let `t gc` delete it once it's older than this, whatever `gc_older_than` says
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Files or directories to copy into it, or move with `r#move`
+    pub fn seed(mut self, paths: Vec<PathBuf>, r#move: bool) -> Self {
+        self.seed = paths;
+        self.move_seed = r#move;
+        self
+    }
+
+    /// The template from `~/.config/t-rs/templates` to copy into it
+    pub fn template(mut self, template: Option<&'a str>, home: &'a Path) -> Self {
+        self.template = template.map(|i| (i, home));
+        self
+    }
+
+    /// The name and command of a scaffold to run in it, after copying the template
+    pub fn scaffold(mut self, scaffold: Option<(&'a str, &'a str)>) -> Self {
+        self.scaffold = scaffold;
+        self
+    }
+
+    pub fn hooks(mut self, hooks: &'a Hooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Have `t status` and `t gc` point it out once it holds more than this many bytes
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Give it a `.envrc` with these extra lines, and `direnv allow` it
+    pub fn direnv(mut self, snippets: Option<&'a [String]>) -> Self {
+        self.direnv = snippets;
+        self
+    }
+
+    /// What to do if the name is taken. Names t-rs picked itself should be made unique,
+    /// another t-rs could have picked the same one before either created the tempdir
+    pub fn on_collision(mut self, on_collision: OnCollision) -> Self {
+        self.on_collision = on_collision;
+        self
+    }
+
+    /// Offer to restore a deleted tempdir with the same name from `trash` instead,
+    /// so it isn't quietly shadowed by a new, empty one
+    pub fn restore_from(mut self, trash: Option<&'a Trash>) -> Self {
+        self.trash = trash;
+        self
+    }
+
+    /// Make the tempdir, returning what happened.
+    /// If a tempdir with the name exists already, that's [`Created::Taken`] and nothing else happens,
+    /// unless [`on_collision`](Self::on_collision) says otherwise
+    pub fn create(mut self) -> Result<Created> {
+        let origin = match self.origin.take() {
+            Some(origin) => origin,
+            None => std::env::current_dir().wrap_err("get current dir")?,
+        };
+
+        if let Some(existing) = self.claim_name()? {
+            return self.existing(existing);
+        }
+
+        if let Some(restored) = self.offer_restore()? {
+            self.copy_seed(&restored)?;
+            return Ok(Created::Restored(restored));
+        }
+
+        // asking for a passphrase or a sudo password can take a while, so that happens before taking the lock
+        let dir = self.backing.create()?;
+
+        // so `t delete --all` doesn't run halfway through. Another t could have taken the name while the
+        // backing directory was made, so it's checked again
+        let lock = Lock::acquire(self.tempdirs)?;
+        if let Some(existing) = self.claim_name()? {
+            drop(lock);
+            crate::release_backing(&dir)?;
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir).wrap_err(format!("remove {dir:?}"))?;
+            }
+            return self.existing(existing);
+        }
+
+        let res = if self.hidden {
+            info!("cding into {dir:?}");
+            // nothing in the tempdirs root points to it, so this is the only place that knows about it
+            if let Some(home) = home::home_dir() {
+                Registry::new(&home).add_hidden(self.tempdirs, &dir, self.ttl.map(|i| age::now() + i.as_secs()))?;
+            }
+
+            dir.clone()
+        } else {
+            let parent = self.layout.dir(self.tempdirs, self.group);
+            std::fs::create_dir_all(&parent).wrap_err(format!("create {parent:?}"))?;
+            let symlink_path = parent.join(&self.name);
+
+            info!("cding into {symlink_path:?}");
+            crate::platform::link_dir(&dir, &symlink_path).wrap_err("create symlink")?;
+            Meta {
+                created: Some(age::now()),
+                origin: Some(origin.clone()),
+                host: meta::hostname(),
+                expires: self.ttl.map(|i| age::now() + i.as_secs()),
+                max_size: self.max_size,
+                ..Default::default()
+            }.save(&symlink_path)?;
+            Journal::new(self.tempdirs).record(Op::Create {
+                entry: symlink_path.clone(),
+                origin: Some(origin),
+            })?;
+
+            symlink_path
+        };
+        drop(lock);
+
+        if let Some((template, home)) = self.template {
+            pipeline::apply_template(&res, template, home)?;
+        }
+
+        if let Some((name, command)) = self.scaffold {
+            pipeline::run_scaffold(&res, name, command)?;
+        }
+
+        self.copy_seed(&res)?;
+
+        if let Some(snippets) = self.direnv {
+            direnv::setup(&res, snippets)?;
+        }
+
+        if let Some(hooks) = self.hooks {
+            hooks.run(Hook::Create, &res)?;
+        }
+        output::record(&["created", &res.to_string_lossy(), &dir.to_string_lossy()]);
+        Ok(Created::New(res))
+    }
+
+    /// Check whether the name is taken, and deal with it like [`on_collision`](Self::on_collision) says.
+    /// `None` if the name is free (maybe after making it unique)
+    fn claim_name(&mut self) -> Result<Option<Created>> {
+        let Some(existing) = self.layout.find(self.tempdirs, &self.name)? else {
+            return Ok(None);
+        };
+        match self.on_collision {
+            OnCollision::Fail => Ok(Some(Created::Taken(existing))),
+            OnCollision::Reuse => Ok(Some(Created::Reused(existing))),
+            OnCollision::Unique => {
+                let name = unique_name(self.tempdirs, self.layout, &self.name)?;
+                info!("{:?} is taken, creating {name:?} instead", self.name);
+                self.name = name;
+                Ok(None)
+            }
+        }
+    }
+
+    /// If a deleted tempdir with the name is still in the trash, offer to restore it instead of creating a new one
+    fn offer_restore(&self) -> Result<Option<PathBuf>> {
+        let Some(trash) = self.trash.filter(|_| !self.hidden) else {
+            return Ok(None);
+        };
+        let Some(trashed) = trash.find(&self.name)? else {
+            return Ok(None);
+        };
+        let name = &self.name;
+        let ago = age::human(SystemTime::now().duration_since(age::from_unix(trashed.deleted)).unwrap_or_default());
+
+        let question = format!("a tempdir called {name:?} was deleted {ago} ago and is still in the trash, restore it instead?");
+        if prompt::confirm(&question, true)? {
+            Ok(Some(trash.restore(name)?))
+        } else {
+            if !prompt::can_ask() {
+                notice!("a tempdir called {name:?} that was deleted {ago} ago is in the trash, `t restore {name}` brings it back");
+            }
+            Ok(None)
+        }
+    }
+
+    /// Finish up when [`claim_name`](Self::claim_name) found the name taken
+    fn existing(&self, existing: Created) -> Result<Created> {
+        if let Created::Reused(ref existing) = existing {
+            info!("{existing:?} already exists, using it");
+            // `t with --force foo a.txt` still puts a.txt in foo
+            self.copy_seed(existing)?;
+        }
+        Ok(existing)
+    }
+
+    fn copy_seed(&self, res: &Path) -> Result<()> {
+        if self.seed.is_empty() {
+            return Ok(());
+        }
+
+        // what's put in explicitly wins from what's in the template
+        let options = CopyOptions { overwrite: true, ..Default::default() };
+        let progress = progress::bytes(0)?;
+        let handler = |i: fs_extra::TransitProcess| {
+            progress.set_length(i.total_bytes);
+            progress.set_position(i.copied_bytes);
+            TransitProcessResult::ContinueOrAbort
+        };
+        if self.move_seed {
+            fs_extra::move_items_with_progress(&self.seed, res, &options, handler).wrap_err("move files to tempdir")?;
+        } else {
+            fs_extra::copy_items_with_progress(&self.seed, res, &options, handler).wrap_err("copy files to tempdir")?;
+        }
+        progress.finish_and_clear();
+        Ok(())
+    }
+}
+
+/// `name` with the first free `-2`, `-3`, ... after it, for [`OnCollision::Unique`]
+pub fn unique_name(tempdirs: &Path, layout: Layout, name: &str) -> Result<String> {
+    let mut counter = 2;
+    while layout.find(tempdirs, &format!("{name}-{counter}"))?.is_some() {
+        counter += 1;
+    }
+    Ok(format!("{name}-{counter}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_names_dont_stop_jumping() {
+        // `t foo` with `unique_names = true` still goes to foo
+        assert_eq!(OnCollision::choose(false, false, true, true, false), OnCollision::Fail);
+        // `t shell foo` makes foo-2
+        assert_eq!(OnCollision::choose(false, false, true, true, true), OnCollision::Unique);
+        // `t --unique foo` asks for a new one
+        assert_eq!(OnCollision::choose(false, true, true, false, false), OnCollision::Unique);
+        assert_eq!(OnCollision::choose(false, false, false, false, true), OnCollision::Unique);
+        assert_eq!(OnCollision::choose(true, false, true, true, true), OnCollision::Reuse);
+        assert_eq!(OnCollision::choose(false, false, true, false, true), OnCollision::Fail);
+    }
+}
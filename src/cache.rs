@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{age, downloads, info};
+
+const OBJECTS: &str = "objects";
+const INDEX: &str = "urls.toml";
+
+/// Downloads of `t dl --url`, kept so the same url doesn't have to be downloaded again.
+///
+/// Files are stored by the sha256 of their contents in `<cache>/objects`,
+/// and `<cache>/urls.toml` maps urls to them.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    urls: BTreeMap<String, Cached>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Cached {
+    sha256: String,
+    /// when the url was last downloaded or taken from the cache, in seconds since the unix epoch
+    used: u64,
+}
+
+/// `$XDG_CACHE_HOME`, falling back to `~/.cache`
+pub fn cache_dir(home: &Path) -> PathBuf {
+    match std::env::var_os("XDG_CACHE_HOME") {
+        Some(i) if !i.is_empty() => PathBuf::from(i),
+        _ => home.join(".cache"),
+    }
+}
+
+impl Cache {
+    pub fn new(home: &Path) -> Self {
+        Self {
+            dir: cache_dir(home).join("t-rs").join("downloads"),
+        }
+    }
+
+    fn load_index(&self) -> Result<Index> {
+        let path = self.dir.join(INDEX);
+        if !path.exists() {
+            return Ok(Index::default());
+        }
+
+        let contents = std::fs::read_to_string(&path).wrap_err(format!("read {path:?}"))?;
+        toml::from_str(&contents).wrap_err(format!("parse {path:?}"))
+    }
+
+    fn save_index(&self, index: &Index) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).wrap_err(format!("create {:?}", self.dir))?;
+        let contents = toml::to_string(index).wrap_err("serialize download cache index")?;
+        std::fs::write(self.dir.join(INDEX), contents).wrap_err("write download cache index")
+    }
+
+    fn object(&self, sha256: &str) -> PathBuf {
+        self.dir.join(OBJECTS).join(sha256)
+    }
+
+    /// Put the file at `url` in `dir`, from the cache if we downloaded it before
+    pub fn fetch(&self, url: &str, dir: &Path) -> Result<PathBuf> {
+        let mut index = self.load_index()?;
+        let path = dir.join(downloads::file_name_from_url(url));
+
+        if let Some(cached) = index.urls.get_mut(url) {
+            let object = self.object(&cached.sha256);
+            if object.exists() {
+                info!("using cached download of {url} (use --no-cache to download it again)");
+                std::fs::copy(&object, &path).wrap_err(format!("copy {object:?} to {path:?}"))?;
+                cached.used = age::now();
+                self.save_index(&index)?;
+
+                return Ok(path);
+            }
+        }
+
+        let path = downloads::fetch(url, dir)?;
+        let sha256 = hash(&path)?;
+        let object = self.object(&sha256);
+        if !object.exists() {
+            std::fs::create_dir_all(self.dir.join(OBJECTS)).wrap_err("create download cache")?;
+            std::fs::copy(&path, &object).wrap_err(format!("copy {path:?} to the download cache"))?;
+        }
+
+        index.urls.insert(url.to_string(), Cached { sha256, used: age::now() });
+        self.save_index(&index)?;
+
+        Ok(path)
+    }
+
+    /// Forget downloads that haven't been used for `older_than`, or all of them
+    pub fn prune(&self, older_than: Option<Duration>) -> Result<()> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+
+        let mut index = self.load_index()?;
+        let now = age::now();
+        index.urls.retain(|url, cached| {
+            let keep = older_than.is_some_and(|i| now.saturating_sub(cached.used) < i.as_secs());
+            if !keep {
+                info!("removing cached download of {url}");
+            }
+            keep
+        });
+
+        // objects can be shared by several urls, so only remove the ones nothing refers to anymore
+        let objects = self.dir.join(OBJECTS);
+        if objects.exists() {
+            for i in std::fs::read_dir(&objects).wrap_err(format!("read {objects:?}"))? {
+                let i = i.wrap_err("read direntry")?;
+                let name = i.file_name().to_string_lossy().to_string();
+                if !index.urls.values().any(|cached| cached.sha256 == name) {
+                    std::fs::remove_file(i.path()).wrap_err(format!("remove {:?}", i.path()))?;
+                }
+            }
+        }
+
+        self.save_index(&index)
+    }
+}
+
+fn hash(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = File::open(path).wrap_err(format!("open {path:?}"))?;
+    std::io::copy(&mut file, &mut hasher).wrap_err(format!("hash {path:?}"))?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
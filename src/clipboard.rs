@@ -0,0 +1,53 @@
+use arboard::{Clipboard, ImageData};
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+
+/// What's on the clipboard
+pub enum Contents {
+    Text(Vec<u8>),
+    /// a png image
+    Image(Vec<u8>),
+}
+
+impl Contents {
+    /// The file to put it in
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Contents::Text(_) => "clipboard.txt",
+            Contents::Image(_) => "clipboard.png",
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Contents::Text(i) | Contents::Image(i) => i,
+        }
+    }
+}
+
+/// Read the clipboard, preferring images over text
+pub fn read() -> Result<Contents> {
+    let mut clipboard = Clipboard::new().wrap_err("open the clipboard")?;
+    match clipboard.get_image() {
+        Ok(image) => return Ok(Contents::Image(png(&image)?)),
+        Err(arboard::Error::ContentNotAvailable) => {}
+        Err(e) => return Err(e).wrap_err("read an image from the clipboard"),
+    }
+    match clipboard.get_text() {
+        Ok(text) if !text.is_empty() => Ok(Contents::Text(text.into_bytes())),
+        Ok(_) | Err(arboard::Error::ContentNotAvailable) => bail!("the clipboard is empty, or has something on it that isn't text or an image"),
+        Err(e) => Err(e).wrap_err("read text from the clipboard"),
+    }
+}
+
+/// Encode an image from the clipboard, which comes as RGBA pixels, as a png
+fn png(image: &ImageData) -> Result<Vec<u8>> {
+    let mut res = Vec::new();
+    let mut encoder = png::Encoder::new(&mut res, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().wrap_err("encode the clipboard image")?;
+    writer.write_image_data(&image.bytes).wrap_err("encode the clipboard image")?;
+    writer.finish().wrap_err("encode the clipboard image")?;
+    Ok(res)
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use color_eyre::eyre::{Context, ContextCompat};
+use color_eyre::Result;
+use similar::TextDiff;
+
+/// The differences between two directory trees, as paths relative to their roots
+#[derive(Debug, Default, PartialEq)]
+pub struct Comparison {
+    /// only in the second directory
+    pub added: Vec<PathBuf>,
+    /// only in the first directory
+    pub removed: Vec<PathBuf>,
+    /// in both, but with different contents
+    pub changed: Vec<PathBuf>,
+    /// how many files are the same in both
+    pub same: usize,
+}
+
+impl Comparison {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Like `2 added, 1 removed, 3 changed, 10 the same`
+    pub fn summary(&self) -> String {
+        format!("{} added, {} removed, {} changed, {} the same", self.added.len(), self.removed.len(), self.changed.len(), self.same)
+    }
+}
+
+/// Recursively compare the files in `a` and `b`
+pub fn compare(a: &Path, b: &Path) -> Result<Comparison> {
+    let files_a = files(a)?;
+    let files_b = files(b)?;
+
+    let mut res = Comparison {
+        added: files_b.difference(&files_a).cloned().collect(),
+        removed: files_a.difference(&files_b).cloned().collect(),
+        changed: Vec::new(),
+        same: 0,
+    };
+
+    for i in files_a.intersection(&files_b) {
+        if same_contents(&a.join(i), &b.join(i))? {
+            res.same += 1;
+        } else {
+            res.changed.push(i.clone());
+        }
+    }
+
+    Ok(res)
+}
+
+/// A unified diff of a file that's in both `a` and `b`, or `None` if it's not text
+pub fn unified_diff(a: &Path, b: &Path, file: &Path) -> Result<Option<String>> {
+    let (Some(old), Some(new)) = (read_text(&a.join(file))?, read_text(&b.join(file))?) else {
+        return Ok(None);
+    };
+
+    let old_name = a.join(file).to_string_lossy().to_string();
+    let new_name = b.join(file).to_string_lossy().to_string();
+    let diff = TextDiff::from_lines(&old, &new);
+    let res = diff.unified_diff().header(&old_name, &new_name).to_string();
+
+    Ok(Some(res))
+}
+
+/// Show the differences in a file that's in both `a` and `b` with an external differ, like `difft` or `diff -u`,
+/// which gets both versions of the file as its last two arguments. Its output goes to stderr
+pub fn external_diff(differ: &str, a: &Path, b: &Path, file: &Path) -> Result<()> {
+    let mut parts = differ.split_whitespace();
+    let program = parts.next().wrap_err("the differ is empty")?;
+    // differs exit with 1 when files differ, which is what we know already
+    Command::new(program)
+        .args(parts)
+        .arg(a.join(file))
+        .arg(b.join(file))
+        .stdout(std::io::stderr())
+        .status()
+        .wrap_err(format!("run {program}"))?;
+    Ok(())
+}
+
+fn read_text(path: &Path) -> Result<Option<String>> {
+    if path.is_symlink() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read(path).wrap_err(format!("read {path:?}"))?;
+    if contents.contains(&0) {
+        return Ok(None);
+    }
+
+    Ok(String::from_utf8(contents).ok())
+}
+
+/// All files (and symlinks) under `root`, relative to it
+fn files(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    fn walk(root: &Path, dir: &Path, res: &mut BTreeSet<PathBuf>) -> Result<()> {
+        for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+            let i = i.wrap_err("read direntry")?;
+            let file_type = i.file_type().wrap_err("get file type")?;
+
+            if file_type.is_dir() {
+                walk(root, &i.path(), res)?;
+            } else {
+                res.insert(i.path().strip_prefix(root).wrap_err("strip prefix")?.to_path_buf());
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut res = BTreeSet::new();
+    walk(root, root, &mut res)?;
+    Ok(res)
+}
+
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    match (a.is_symlink(), b.is_symlink()) {
+        (true, true) => {
+            return Ok(std::fs::read_link(a).wrap_err("read link")? == std::fs::read_link(b).wrap_err("read link")?);
+        }
+        (false, false) => {}
+        _ => return Ok(false),
+    }
+
+    let meta_a = std::fs::metadata(a).wrap_err(format!("get metadata of {a:?}"))?;
+    let meta_b = std::fs::metadata(b).wrap_err(format!("get metadata of {b:?}"))?;
+    if meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+
+    Ok(std::fs::read(a).wrap_err(format!("read {a:?}"))? == std::fs::read(b).wrap_err(format!("read {b:?}"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_trees() {
+        let dir = tempdir::TempDir::new("t-rs-test-compare").unwrap();
+        let root = dir.path();
+        let a = root.join("a");
+        let b = root.join("b");
+        std::fs::create_dir_all(a.join("src")).unwrap();
+        std::fs::create_dir_all(b.join("src")).unwrap();
+
+        std::fs::write(a.join("same.txt"), "hello").unwrap();
+        std::fs::write(b.join("same.txt"), "hello").unwrap();
+        std::fs::write(a.join("src/changed.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(b.join("src/changed.txt"), "one\nthree\n").unwrap();
+        std::fs::write(a.join("removed.txt"), "").unwrap();
+        std::fs::write(b.join("src/added.txt"), "").unwrap();
+
+        let res = compare(&a, &b).unwrap();
+        assert_eq!(res, Comparison {
+            added: vec![PathBuf::from("src/added.txt")],
+            removed: vec![PathBuf::from("removed.txt")],
+            changed: vec![PathBuf::from("src/changed.txt")],
+            same: 1,
+        });
+        assert_eq!(res.summary(), "1 added, 1 removed, 1 changed, 1 the same");
+
+        let diff = unified_diff(&a, &b, Path::new("src/changed.txt")).unwrap().unwrap();
+        assert!(diff.contains("-two"));
+        assert!(diff.contains("+three"));
+    }
+}
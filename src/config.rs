@@ -0,0 +1,429 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::{age, info, notice, pipeline, push, tmux, usage};
+use crate::hooks::Hooks;
+use crate::layout::Layout;
+use crate::naming::Naming;
+use crate::trash::Backend;
+
+/// Settings read from `$XDG_CONFIG_HOME/t-rs/config.toml` (usually `~/.config/t-rs/config.toml`).
+///
+/// Every key is optional, a missing config file is the same as an empty one.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Where `t dl` looks for downloads. Takes precedence over the XDG user dirs.
+    pub downloads_dir: Option<PathBuf>,
+
+    /// Let `t dl` pick dotfiles and files listed in `.hidden` in the downloads directory, which it skips by default
+    pub dl_include_hidden: bool,
+
+    /// Where `t shot` looks for screenshots. The Desktop on macOS and `Screenshots` in the pictures directory elsewhere
+    pub screenshots_dir: Option<PathBuf>,
+
+    /// Downloads bigger than this (like `1G`, the default) don't go in a backing directory on a tmpfs,
+    /// where they'd take up memory, but in `disk_temp_dir`
+    pub dl_tmpfs_limit: Option<String>,
+
+    /// Where backing directories go when they're too big for a tmpfs, `/var/tmp` by default
+    pub disk_temp_dir: Option<PathBuf>,
+
+    /// Leave out gitignored files when persisting, as if `--gitignore` was passed
+    pub persist_gitignore: bool,
+
+    /// How old tempdirs have to be before `t gc` deletes them (like `7d` or `12h`)
+    pub gc_older_than: Option<String>,
+
+    /// How long tempdirs can go unused before `t status` calls them stale (like `14d`, the default)
+    pub stale_after: Option<String>,
+
+    /// Also have `t gc` delete empty tempdirs, like `t prune-empty`
+    pub gc_prune_empty: bool,
+
+    /// How old empty tempdirs have to be before `t prune-empty` deletes them (like `1h`, the default)
+    pub prune_empty_after: Option<String>,
+
+    /// When a command that creates a tempdir gets the name of an existing one, add `-2`, `-3`, ... to the name of
+    /// the new one, as if `--unique` was passed. A bare `t foo` still goes to an existing `foo`
+    pub unique_names: bool,
+
+    /// How entries are organized in the tempdirs root: `flat`, `by-date` or `by-group`
+    pub layout: Layout,
+
+    /// How tempdirs created without a name are named: `numeric` (`unnamed_1`), `words` (`brave-otter`) or `date`
+    pub naming: Naming,
+
+    /// A template for the names of tempdirs created without a name, like `{date}-{slug}`.
+    /// Can use `{date}`, `{counter}`, `{slug}` (the download for `t dl`) and `{origin}` (the directory `t` was run from)
+    pub name_template: Option<String>,
+
+    /// Also move the backing directories of deleted non-persistent tempdirs to the trash.
+    /// By default they're left for the OS to clean up
+    pub trash_backing_dirs: bool,
+
+    /// How long deleted tempdirs stay in the trash before `t gc` empties them (like `30d`).
+    /// Only for the portable trash, the trash of the OS is emptied by the OS
+    pub trash_retention: Option<String>,
+
+    /// Where deleted tempdirs go: `auto` (the trash of the OS), `freedesktop`, `macos`,
+    /// `recycle-bin` or `portable` (t-rs' own trash directory)
+    pub trash_backend: Backend,
+
+    /// Where the backing directories of new tempdirs go, instead of the system's temp dir.
+    /// Useful to put them on a fast scratch disk or a RAM disk
+    pub backing_dir: Option<PathBuf>,
+
+    /// The permissions of new backing directories in octal, `700` (only you can get in) by default
+    pub backing_mode: Option<String>,
+
+    /// How much space all tempdirs together may take up (like `10G`).
+    /// Creating tempdirs over it is refused, or asks first in a terminal
+    pub max_total_size: Option<String>,
+
+    /// What `t push` copies tempdirs with: `rsync` (the default) or `scp`
+    pub push_tool: push::Tool,
+
+    /// Where `t archive` puts archives, `~/.local/share/t-rs/archive` by default
+    pub archive_dir: Option<PathBuf>,
+
+    /// After commands that change something, print how the number and size of tempdirs changed
+    pub usage_summary: bool,
+
+    /// Never ask before deleting several tempdirs at once or a persistent one, as if `--yes` was always passed
+    pub skip_confirmations: bool,
+
+    /// Show a desktop notification when tempdirs are removed without you deleting them,
+    /// like by `t gc` from a timer or because their files disappeared
+    pub notify_removals: bool,
+
+    /// Commands to set up new tempdirs with, selected with `--scaffold <name>`, like
+    ///
+    /// ```toml
+    /// [scaffolds]
+    /// rust = "cargo init --name scratch"
+    /// node = "npm init -y"
+    /// ```
+    pub scaffolds: BTreeMap<String, String>,
+
+    /// The command `t open` opens tempdirs with, like `nautilus --new-window`.
+    /// xdg-open, open on macOS or explorer on Windows by default
+    pub opener: Option<String>,
+
+    /// The command `t compare` shows changed files with, like `difft` or `diff -u`, instead of its own diffs
+    pub differ: Option<String>,
+
+    /// The command `t edit` opens tempdirs with, like `code --wait`. `$VISUAL` or `$EDITOR` by default
+    pub editor: Option<String>,
+
+    /// Write what t-rs does to `$XDG_STATE_HOME/t-rs/t-rs.log` (usually `~/.local/state/t-rs/t-rs.log`),
+    /// to find out afterwards why a tempdir was deleted for example
+    pub log_file: bool,
+
+    /// Tell zoxide about tempdirs when they're created or entered, and when they're deleted, so `z` can jump to them
+    pub zoxide: bool,
+
+    /// The zoxide executable, `zoxide` by default
+    pub zoxide_command: Option<String>,
+
+    /// Give every new tempdir a `.envrc` and `direnv allow` it, as if `--direnv` was always passed
+    pub direnv: bool,
+
+    /// Lines to add to the `.envrc` of new tempdirs after `T_NAME` and `T_DIR`, like `["layout python"]`
+    pub direnv_snippets: Vec<String>,
+
+    /// What `t tmux` opens tempdirs in when it's run inside tmux: a new `window` (the default) or `session`
+    pub tmux: tmux::Target,
+
+    /// How long to wait for another t-rs that's changing the tempdirs root before giving up (like `30s`, `10s` by default)
+    pub lock_timeout: Option<String>,
+
+    /// Commands to run when tempdirs are created, entered, deleted or persisted
+    #[serde(flatten)]
+    pub hooks: Hooks,
+
+    /// Separate sets of tempdirs, selected with `--profile`, like
+    ///
+    /// ```toml
+    /// [profiles.work]
+    /// tempdirs = "~/work/tempdirs"
+    /// backing_dir = "/scratch"
+    /// ```
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Profile {
+    /// The tempdirs root of this profile, `~/tempdirs-<profile>` by default
+    pub tempdirs: Option<PathBuf>,
+
+    /// Where the backing directories of new tempdirs go, instead of `backing_dir`
+    pub backing_dir: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load(home: &Path) -> Result<Self> {
+        let path = config_path(home);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path).wrap_err(format!("read config {path:?}"))?;
+        toml::from_str(&contents).wrap_err(format!("parse config {path:?}"))
+    }
+
+    /// The command of the scaffold called `name`
+    pub fn scaffold(&self, name: &str) -> Result<&str> {
+        match self.scaffolds.get(name) {
+            Some(i) => Ok(i),
+            None if self.scaffolds.is_empty() => Err(eyre!("there's no scaffold called {name:?}, add it under [scaffolds] in the config")),
+            None => {
+                let names: Vec<_> = self.scaffolds.keys().map(String::as_str).collect();
+                Err(eyre!("there's no scaffold called {name:?}, there's {}", names.join(", ")))
+            }
+        }
+    }
+
+    pub fn gc_older_than(&self) -> Result<Duration> {
+        match self.gc_older_than {
+            Some(ref i) => age::parse_duration(i).map_err(|e| eyre!("gc_older_than in config: {e}")),
+            None => Ok(Duration::from_secs(60 * 60 * 24 * 7)),
+        }
+    }
+
+    pub fn lock_timeout(&self) -> Result<Duration> {
+        match self.lock_timeout {
+            Some(ref i) => age::parse_duration(i).map_err(|e| eyre!("lock_timeout in config: {e}")),
+            None => Ok(Duration::from_secs(10)),
+        }
+    }
+
+    pub fn stale_after(&self) -> Result<Duration> {
+        match self.stale_after {
+            Some(ref i) => age::parse_duration(i).map_err(|e| eyre!("stale_after in config: {e}")),
+            None => Ok(Duration::from_secs(60 * 60 * 24 * 14)),
+        }
+    }
+
+    pub fn prune_empty_after(&self) -> Result<Duration> {
+        match self.prune_empty_after {
+            Some(ref i) => age::parse_duration(i).map_err(|e| eyre!("prune_empty_after in config: {e}")),
+            None => Ok(Duration::from_secs(60 * 60)),
+        }
+    }
+
+    /// The tempdirs root of `profile`, or the default one without a profile
+    pub fn tempdirs(&self, home: &Path, profile: Option<&str>) -> PathBuf {
+        let Some(profile) = profile else {
+            return home.join(crate::TEMPDIRS);
+        };
+
+        match self.profiles.get(profile).and_then(|i| i.tempdirs.as_ref()) {
+            Some(i) => expand_home(i, home),
+            None => home.join(format!("{}-{profile}", crate::TEMPDIRS)),
+        }
+    }
+
+    /// Where the backing directories of new tempdirs in `profile` go
+    pub fn backing_dir(&self, home: &Path, profile: Option<&str>) -> PathBuf {
+        let configured = profile
+            .and_then(|i| self.profiles.get(i))
+            .and_then(|i| i.backing_dir.as_ref())
+            .or(self.backing_dir.as_ref());
+
+        match configured {
+            Some(i) => expand_home(i, home),
+            None => crate::platform::temp_dir(),
+        }
+    }
+
+    /// Everywhere backing directories can be: the backing dir of every profile, the system's temp dir, `disk_temp_dir`
+    /// and the [`durable_dir`]
+    pub fn backing_locations(&self, home: &Path) -> Vec<PathBuf> {
+        let mut res = vec![self.backing_dir(home, None)];
+        res.extend(self.profiles.keys().map(|i| self.backing_dir(home, Some(i))));
+        res.push(crate::platform::temp_dir());
+        res.push(self.disk_temp_dir(home));
+        res.push(durable_dir(home));
+
+        let mut seen = Vec::new();
+        res.retain(|i| {
+            let canonical = i.canonicalize().unwrap_or_else(|_| i.clone());
+            let new = !seen.contains(&canonical);
+            seen.push(canonical);
+            new
+        });
+        res
+    }
+
+    pub fn backing_mode(&self) -> Result<u32> {
+        match self.backing_mode {
+            Some(ref i) => crate::platform::parse_mode(i).map_err(|e| eyre!("backing_mode in config: {e}")),
+            None => Ok(0o700),
+        }
+    }
+
+    pub fn max_total_size(&self) -> Result<Option<u64>> {
+        self.max_total_size
+            .as_deref()
+            .map(usage::parse_size)
+            .transpose()
+            .map_err(|e| eyre!("max_total_size in config: {e}"))
+    }
+
+    pub fn dl_tmpfs_limit(&self) -> Result<u64> {
+        match self.dl_tmpfs_limit {
+            Some(ref i) => usage::parse_size(i).map_err(|e| eyre!("dl_tmpfs_limit in config: {e}")),
+            None => Ok(1024 * 1024 * 1024),
+        }
+    }
+
+    pub fn disk_temp_dir(&self, home: &Path) -> PathBuf {
+        match self.disk_temp_dir {
+            Some(ref i) => expand_home(i, home),
+            None => PathBuf::from("/var/tmp"),
+        }
+    }
+
+    pub fn trash_retention(&self) -> Result<Duration> {
+        match self.trash_retention {
+            Some(ref i) => age::parse_duration(i).map_err(|e| eyre!("trash_retention in config: {e}")),
+            None => Ok(Duration::from_secs(60 * 60 * 24 * 30)),
+        }
+    }
+}
+
+pub fn config_path(home: &Path) -> PathBuf {
+    config_dir(home).join("t-rs").join("config.toml")
+}
+
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config`
+pub fn config_dir(home: &Path) -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(i) if !i.is_empty() => PathBuf::from(i),
+        _ => home.join(".config"),
+    }
+}
+
+/// Where the backing directories of `t --durable` tempdirs go, `~/.cache/t-rs/dirs`.
+/// Unlike the system's temp dir it survives reboots, so nothing but `t` ever cleans it up
+pub fn durable_dir(home: &Path) -> PathBuf {
+    crate::cache::cache_dir(home).join("t-rs").join("dirs")
+}
+
+/// Whether `backing` is the backing directory of a durable tempdir
+pub fn is_durable(backing: &Path, home: &Path) -> bool {
+    backing.starts_with(durable_dir(home))
+}
+
+/// Expand a leading `~` so paths in the config file can be written relative to the home directory
+pub fn expand_home(path: &Path, home: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home.join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// The opposite of [`expand_home`], for showing paths
+pub fn abbreviate_home(path: &Path, home: &Path) -> PathBuf {
+    match path.strip_prefix(home) {
+        Ok(rest) => Path::new("~").join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Everything needed to set up t-rs the same way on another machine, written by `t config export`
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct Export {
+    /// the config file, as is (so comments survive)
+    config: Option<String>,
+    /// the files of every template, by template name and then path within the template
+    templates: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Write the config file and templates to `file`
+pub fn export(home: &Path, file: &Path) -> Result<()> {
+    let mut export = Export::default();
+
+    let path = config_path(home);
+    if path.exists() {
+        export.config = Some(std::fs::read_to_string(&path).wrap_err(format!("read config {path:?}"))?);
+    }
+
+    let templates = pipeline::templates_dir(home);
+    if templates.exists() {
+        for i in std::fs::read_dir(&templates).wrap_err(format!("read {templates:?}"))? {
+            let i = i.wrap_err("read direntry")?;
+            if i.path().is_dir() {
+                let mut files = BTreeMap::new();
+                template_files(&i.path(), &i.path(), &mut files)?;
+                export.templates.insert(i.file_name().to_string_lossy().to_string(), files);
+            }
+        }
+    }
+
+    let contents = toml::to_string_pretty(&export).wrap_err("serialize export")?;
+    std::fs::write(file, contents).wrap_err(format!("write {file:?}"))?;
+    info!("exported config and {} templates to {file:?}", export.templates.len());
+
+    Ok(())
+}
+
+fn template_files(root: &Path, dir: &Path, res: &mut BTreeMap<String, String>) -> Result<()> {
+    for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+        let i = i.wrap_err("read direntry")?;
+        let path = i.path();
+
+        if path.is_dir() {
+            template_files(root, &path, res)?;
+        } else if let Ok(contents) = std::fs::read_to_string(&path) {
+            let relative = path.strip_prefix(root).wrap_err("strip prefix")?;
+            res.insert(relative.to_string_lossy().to_string(), contents);
+        } else {
+            notice!("leaving out {path:?}, only text files can be exported");
+        }
+    }
+
+    Ok(())
+}
+
+/// Set up the config file and templates from a file written by [`export`].
+/// Existing files are only overwritten with `force`.
+pub fn import(home: &Path, file: &Path, force: bool) -> Result<()> {
+    let contents = std::fs::read_to_string(file).wrap_err(format!("read {file:?}"))?;
+    let export: Export = toml::from_str(&contents).wrap_err(format!("parse {file:?}"))?;
+
+    if let Some(config) = export.config {
+        toml::from_str::<Config>(&config).wrap_err("parse imported config")?;
+        write_unless_exists(&config_path(home), &config, force)?;
+    }
+
+    let templates = pipeline::templates_dir(home);
+    for (name, files) in export.templates {
+        for (path, contents) in files {
+            write_unless_exists(&templates.join(&name).join(path), &contents, force)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_unless_exists(path: &Path, contents: &str, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        notice!("not overwriting {path:?} (use --force to overwrite)");
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+    }
+    std::fs::write(path, contents).wrap_err(format!("write {path:?}"))?;
+    info!("wrote {path:?}");
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::journal::Journal;
+use crate::layout::Layout;
+use crate::meta::Meta;
+use crate::sessions::process_alive;
+use crate::trash::Trash;
+use crate::{age, bulk_candidates, cleanup, delete, delete_all, delete_hidden, hidden_candidates, info, notify, over_limit, usage, STATE_DIR};
+
+/// How long to wait after a change before cleaning up, so tempdirs that are being created are done by then
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// What a running `t daemon` is up to, kept in `.t-rs/daemon.json` so `t status` can show it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Status {
+    pub pid: u32,
+    /// in seconds since the unix epoch
+    pub started: u64,
+    pub last_pass: Option<u64>,
+    /// how many tempdirs it removed since it started
+    pub removed: usize,
+    /// whether it's notified of changes, or only looks every interval
+    pub watching: bool,
+}
+
+fn status_path(tempdirs: &Path) -> PathBuf {
+    tempdirs.join(STATE_DIR).join("daemon.json")
+}
+
+/// The status of the daemon cleaning up `tempdirs`, if one is running
+pub fn status(tempdirs: &Path) -> Result<Option<Status>> {
+    let path = status_path(tempdirs);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read_to_string(&path).wrap_err(format!("read {path:?}"))?;
+    // a daemon that was killed can't remove its status
+    Ok(serde_json::from_str::<Status>(&contents).ok().filter(|i| process_alive(i.pid)))
+}
+
+impl Status {
+    fn save(&self, tempdirs: &Path) -> Result<()> {
+        let path = status_path(tempdirs);
+        let contents = serde_json::to_string_pretty(self).wrap_err("serialize daemon status")?;
+        std::fs::write(&path, contents).wrap_err(format!("write {path:?}"))
+    }
+
+    /// A line for `t status`
+    pub fn describe(&self) -> String {
+        let how = if self.watching { "watching for changes" } else { "polling" };
+        let last = match self.last_pass {
+            Some(i) => format!("last cleaned up {} ago", age::human(Duration::from_secs(age::now().saturating_sub(i)))),
+            None => "hasn't cleaned up yet".to_string(),
+        };
+        format!("`t daemon` is running (pid {}, {how}), {last} and removed {} tempdirs so far", self.pid, self.removed)
+    }
+}
+
+/// Keep cleaning up `tempdirs` until interrupted: remove stale symlinks, delete expired tempdirs and,
+/// when they take up more than `max_total_size`, the least recently used non-persistent ones.
+/// Runs every time something in the tempdirs root or the backing dir changes, and at least every `interval`
+pub fn run(tempdirs: &Path, layout: Layout, home: &Path, config: &Config, trash: &Trash, interval: Duration) -> Result<()> {
+    if let Some(running) = status(tempdirs)? {
+        bail!("a daemon is already cleaning up {tempdirs:?} (pid {})", running.pid);
+    }
+
+    let backing_dir = config.backing_dir(home, None);
+    let mut status = Status {
+        pid: std::process::id(),
+        started: age::now(),
+        last_pass: None,
+        removed: 0,
+        watching: true,
+    };
+    info!("cleaning up {tempdirs:?} every {} and when it changes (ctrl-c to stop)", age::human(interval));
+
+    loop {
+        status.removed += pass(tempdirs, layout, home, config, trash)?;
+        status.last_pass = Some(age::now());
+        status.save(tempdirs)?;
+
+        status.watching = wait(&[tempdirs, &backing_dir], interval);
+        std::thread::sleep(SETTLE);
+    }
+}
+
+/// Clean up once, returning how many tempdirs were removed
+fn pass(tempdirs: &Path, layout: Layout, home: &Path, config: &Config, trash: &Trash) -> Result<usize> {
+    let stale = cleanup(tempdirs, layout, home, false)?;
+
+    // only expired tempdirs are older than forever
+    let before = layout.entries(tempdirs)?;
+    delete_all(tempdirs, layout, home, Some(Duration::MAX), None, &config.hooks, false)?;
+    let after = layout.entries(tempdirs)?;
+    let mut expired: Vec<_> = before.into_iter().filter(|i| !after.contains(i)).collect();
+    for i in hidden_candidates(tempdirs, home, Duration::MAX)? {
+        delete_hidden(&i, trash, home)?;
+        expired.push(i);
+    }
+
+    let mut over_quota = Vec::new();
+    if let Some(max) = config.max_total_size()? {
+        let mut used = usage::total(&after);
+        if used > max {
+            let journal = Journal::new(tempdirs);
+            let mut candidates = bulk_candidates(tempdirs, layout, Some(Duration::ZERO), None)?;
+            candidates.sort_by_key(|i| age::last_used(i).ok());
+
+            for i in candidates {
+                if used <= max {
+                    break;
+                }
+                let size = usage::size(&i);
+                delete(&i, trash, &journal)?;
+                used = used.saturating_sub(size);
+                over_quota.push(i);
+            }
+            layout.tidy(tempdirs)?;
+        }
+    }
+
+    // big tempdirs are only pointed out, they might be big on purpose
+    for i in &after {
+        if let Some(message) = over_limit(i, &Meta::load(i)?) {
+            info!("{} {message}", i.strip_prefix(tempdirs).unwrap_or(i).to_string_lossy());
+        }
+    }
+
+    if config.notify_removals {
+        notify::removed(tempdirs, &stale, "their files were gone", "Nothing to restore.");
+        notify::removed(tempdirs, &expired, "their --ttl ran out", "`t undo` brings them back while their files are still there.");
+        notify::removed(tempdirs, &over_quota, "over max_total_size", "`t restore <name>` brings them back.");
+    }
+
+    Ok(stale.len() + expired.len() + over_quota.len())
+}
+
+/// Wait until something in `dirs` changes or `interval` passes, with inotifywait or, on macOS, fswatch.
+/// Without those this just sleeps, and returns false
+fn wait(dirs: &[&Path], interval: Duration) -> bool {
+    let mut cmd = if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("fswatch");
+        cmd.arg("-1");
+        cmd
+    } else {
+        let mut cmd = Command::new("inotifywait");
+        cmd.args(["-qq", "-e", "create,delete,move"]);
+        cmd
+    };
+    cmd.args(dirs.iter().filter(|i| i.exists())).stdout(Stdio::null()).stderr(Stdio::null());
+
+    let start = Instant::now();
+    let Ok(mut child) = cmd.spawn() else {
+        std::thread::sleep(interval);
+        return false;
+    };
+
+    while start.elapsed() < interval {
+        match child.try_wait() {
+            Ok(None) => std::thread::sleep(Duration::from_millis(200)),
+            Ok(Some(status)) if status.success() => return true,
+            // the watcher gave up, so just wait for the rest of the interval
+            _ => {
+                std::thread::sleep(interval.saturating_sub(start.elapsed()));
+                return false;
+            }
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    true
+}
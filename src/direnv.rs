@@ -0,0 +1,53 @@
+use std::path::Path;
+use std::process::Command;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::{error, notice};
+
+/// The `.envrc` for the tempdir at `dir`: `$T_NAME` and `$T_DIR` like in `t shell`, then the `snippets` from the config
+fn envrc(dir: &Path, snippets: &[String]) -> String {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    let mut res = String::from("# generated by t-rs\n");
+    res.push_str(&format!("export T_NAME={}\n", quote(&name)));
+    res.push_str(&format!("export T_DIR={}\n", quote(&dir.to_string_lossy())));
+    for i in snippets {
+        res.push_str(i.trim_end());
+        res.push('\n');
+    }
+    res
+}
+
+/// `s` in single quotes for a shell
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Write a `.envrc` into the new tempdir `dir` and `direnv allow` it, so entering the tempdir sets up its environment.
+/// A `.envrc` from a template or seed is left alone, but still allowed
+pub fn setup(dir: &Path, snippets: &[String]) -> Result<()> {
+    let path = dir.join(".envrc");
+    if !path.exists() {
+        std::fs::write(&path, envrc(dir, snippets)).wrap_err(format!("write {path:?}"))?;
+    }
+
+    match Command::new("direnv").arg("allow").arg(&path).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => error!("`direnv allow` failed ({status}), run it yourself in {dir:?}"),
+        Err(_) => notice!("wrote {path:?}, but direnv isn't installed to load it"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_envrc() {
+        let envrc = envrc(Path::new("/home/me/tempdirs/it's"), &["layout python".to_string()]);
+        assert_eq!(
+            envrc,
+            "# generated by t-rs\nexport T_NAME='it'\\''s'\nexport T_DIR='/home/me/tempdirs/it'\\''s'\nlayout python\n",
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+use color_eyre::Result;
+use crate::config::Config;
+use crate::integration::{self, Shell};
+use crate::layout::Layout;
+use crate::meta::Meta;
+use crate::{orphans, platform, usage};
+
+/// Backing locations with less free space than this are reported
+const LOW_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// The outcome of one of the checks of `t doctor`
+pub struct Check {
+    pub name: &'static str,
+    /// what's wrong and how to fix it, if anything is
+    pub problem: Option<(String, String)>,
+}
+
+impl Check {
+    fn pass(name: &'static str) -> Self {
+        Self { name, problem: None }
+    }
+
+    fn fail(name: &'static str, what: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, problem: Some((what.into(), fix.into())) }
+    }
+}
+
+/// Look for everything that commonly goes wrong when setting up or using t-rs
+pub fn checks(tempdirs: &Path, layout: Layout, home: &Path, config: &Config) -> Result<Vec<Check>> {
+    let mut res = vec![wrapper(), root(tempdirs), pwd(), shell(), symlinks(tempdirs)];
+    res.extend(config.backing_locations(home).iter().map(|i| space(i)));
+    res.push(broken(tempdirs, layout)?);
+    res.push(orphaned(tempdirs, layout, home, config)?);
+    res.push(permissions(tempdirs, layout)?);
+    Ok(res)
+}
+
+fn wrapper() -> Check {
+    let fix = match Shell::detect() {
+        Some(shell) => format!("put the output of `t-rs init` in {} and open a new shell", shell.rc_file()),
+        None => "put the output of `t-rs init <shell>` in the rc file of your shell and open a new shell".to_string(),
+    };
+    match integration::wrapper_problem() {
+        None => Check::pass("the `t` wrapper function is installed"),
+        Some(problem) => Check::fail("the `t` wrapper function is installed", problem, fix),
+    }
+}
+
+fn root(tempdirs: &Path) -> Check {
+    const NAME: &str = "the tempdirs root exists and is writable";
+    if !tempdirs.is_dir() {
+        return Check::fail(NAME, format!("{tempdirs:?} isn't a directory"), format!("remove whatever is at {tempdirs:?}, t-rs makes it again"));
+    }
+
+    let probe = tempdirs.join(format!(".t-rs-doctor-{}", std::process::id()));
+    match std::fs::write(&probe, "") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            Check::pass(NAME)
+        }
+        Err(e) => Check::fail(NAME, format!("can't write to {tempdirs:?}: {e}"), format!("make {tempdirs:?} yours, like `chown -R $USER {}`", tempdirs.display())),
+    }
+}
+
+fn pwd() -> Check {
+    const NAME: &str = "$PWD is the current directory";
+    let Some(pwd) = std::env::var_os("PWD").filter(|i| !i.is_empty()).map(PathBuf::from) else {
+        return Check::fail(NAME, "$PWD isn't set", "run t-rs from a shell, which sets it, or `export PWD=\"$(pwd)\"`");
+    };
+    let same = match (pwd.canonicalize(), std::env::current_dir().and_then(|i| i.canonicalize())) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if same {
+        Check::pass(NAME)
+    } else {
+        Check::fail(NAME, format!("$PWD is {pwd:?}, which isn't where t-rs runs"), "don't set $PWD yourself, or `cd .` to have the shell update it")
+    }
+}
+
+fn shell() -> Check {
+    const NAME: &str = "$SHELL is a shell";
+    if cfg!(windows) {
+        return Check::pass(NAME);
+    }
+    match std::env::var_os("SHELL").map(PathBuf::from) {
+        Some(shell) if shell.is_file() => Check::pass(NAME),
+        Some(shell) => Check::fail(NAME, format!("$SHELL is {shell:?}, which doesn't exist"), "set $SHELL to the path of your shell, `t shell` starts it"),
+        None => Check::fail(NAME, "$SHELL isn't set", "set $SHELL to the path of your shell, `t shell` starts it"),
+    }
+}
+
+fn symlinks(tempdirs: &Path) -> Check {
+    const NAME: &str = "symlinks can be made in the tempdirs root";
+    let target = tempdirs.join(format!(".t-rs-doctor-target-{}", std::process::id()));
+    let link = tempdirs.join(format!(".t-rs-doctor-link-{}", std::process::id()));
+
+    let res = std::fs::create_dir(&target).and_then(|_| platform::link_dir(&target, &link));
+    let _ = symlink::remove_symlink_auto(&link);
+    let _ = std::fs::remove_dir(&target);
+    match res {
+        Ok(()) => Check::pass(NAME),
+        Err(e) => Check::fail(NAME, e.to_string(), "put the tempdirs root on a filesystem that supports symlinks, or turn on developer mode on Windows"),
+    }
+}
+
+fn space(location: &Path) -> Check {
+    const NAME: &str = "there's space for backing directories";
+    match platform::free_space(location) {
+        Some(free) if free < LOW_SPACE => Check::fail(
+            NAME,
+            format!("only {} free in {location:?}", usage::human_size(free)),
+            "free up space with `t gc`, or set `backing_dir` in the config to somewhere with more",
+        ),
+        _ => Check::pass(NAME),
+    }
+}
+
+fn broken(tempdirs: &Path, layout: Layout) -> Result<Check> {
+    const NAME: &str = "every tempdir has its files";
+    let mut broken = Vec::new();
+    for i in layout.entries(tempdirs)? {
+        if i.is_symlink() && !i.exists() && !Meta::load(&i)?.foreign() {
+            broken.push(i.strip_prefix(tempdirs).unwrap_or(&i).to_string_lossy().into_owned());
+        }
+    }
+    Ok(match broken.len() {
+        0 => Check::pass(NAME),
+        _ => Check::fail(NAME, format!("the files of {} are gone", broken.join(", ")), "run `t status`, which removes them"),
+    })
+}
+
+fn orphaned(tempdirs: &Path, layout: Layout, home: &Path, config: &Config) -> Result<Check> {
+    const NAME: &str = "every backing directory has a tempdir";
+    Ok(match orphans::find(tempdirs, layout, home, &config.backing_locations(home))?.len() {
+        0 => Check::pass(NAME),
+        n => Check::fail(NAME, format!("{n} backing directories have no tempdir"), "`t orphans --relink` or `t orphans --purge`"),
+    })
+}
+
+fn permissions(tempdirs: &Path, layout: Layout) -> Result<Check> {
+    const NAME: &str = "tempdirs are private";
+    let mut loose = Vec::new();
+    for i in layout.entries(tempdirs)? {
+        if let Some(mode) = std::fs::read_link(&i).ok().and_then(|target| platform::loose_mode(&target)) {
+            loose.push(format!("{} ({mode:o})", i.strip_prefix(tempdirs).unwrap_or(&i).to_string_lossy()));
+        }
+    }
+    Ok(match loose.len() {
+        0 => Check::pass(NAME),
+        _ => Check::fail(NAME, format!("other users can read {}", loose.join(", ")), "`chmod 700` their backing directories, unless that's on purpose"),
+    })
+}
@@ -0,0 +1,548 @@
+use std::collections::{HashMap, HashSet};
+use std::env::VarError;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+use crate::config::{self, Config};
+use crate::pipeline::TAR_EXTENSIONS;
+use crate::warnings::{warn, Kind};
+use crate::{info, progress};
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+
+/// Figure out which directory `t dl` should take downloads from.
+///
+/// In order of preference:
+/// 1. the `--from` argument
+/// 2. `downloads_dir` in the config file
+/// 3. the `XDG_DOWNLOAD_DIR` environment variable
+/// 4. `XDG_DOWNLOAD_DIR` in `~/.config/user-dirs.dirs`
+/// 5. the Downloads known folder, on Windows
+/// 6. `~/Downloads`, or `~/dl` if that doesn't exist
+pub fn downloads_dir(from: Option<&Path>, config: &Config, home: &Path) -> Result<PathBuf> {
+    if let Some(from) = from {
+        return Ok(from.to_path_buf());
+    }
+
+    if let Some(ref dir) = config.downloads_dir {
+        return Ok(config::expand_home(dir, home));
+    }
+
+    if let Some(dir) = user_dir("XDG_DOWNLOAD_DIR", home)? {
+        return Ok(dir);
+    }
+
+    if let Some(dir) = crate::platform::known_downloads_dir() {
+        return Ok(dir);
+    }
+
+    let fallback_dl_dir = home.join("Downloads");
+    if fallback_dl_dir.exists() {
+        Ok(fallback_dl_dir)
+    } else {
+        Ok(home.join("dl"))
+    }
+}
+
+/// Figure out which directory `t shot` should take screenshots from.
+///
+/// In order of preference:
+/// 1. the `--from` argument
+/// 2. `screenshots_dir` in the config file
+/// 3. on macOS, where screenshots are saved (`com.apple.screencapture location`), the Desktop by default
+/// 4. `Screenshots` in the XDG pictures directory (or the Pictures known folder on Windows)
+/// 5. `~/Pictures/Screenshots`
+pub fn screenshots_dir(from: Option<&Path>, config: &Config, home: &Path) -> Result<PathBuf> {
+    if let Some(from) = from {
+        return Ok(from.to_path_buf());
+    }
+
+    if let Some(ref dir) = config.screenshots_dir {
+        return Ok(config::expand_home(dir, home));
+    }
+
+    if cfg!(target_os = "macos") {
+        let configured = command_output(Command::new("defaults").args(["read", "com.apple.screencapture", "location"]))
+            .map(|i| i.trim().to_string())
+            .filter(|i| !i.is_empty());
+        return Ok(match configured {
+            Some(dir) => config::expand_home(Path::new(&dir), home),
+            None => home.join("Desktop"),
+        });
+    }
+
+    let pictures = match user_dir("XDG_PICTURES_DIR", home)? {
+        Some(dir) => dir,
+        None => crate::platform::known_pictures_dir().unwrap_or_else(|| home.join("Pictures")),
+    };
+    Ok(pictures.join("Screenshots"))
+}
+
+/// Look up one of the XDG user directories (like `XDG_DOWNLOAD_DIR`),
+/// first in the environment and then in `user-dirs.dirs`.
+pub fn user_dir(key: &str, home: &Path) -> Result<Option<PathBuf>> {
+    match std::env::var(key) {
+        Ok(i) if !i.is_empty() => return Ok(Some(PathBuf::from(i))),
+        Ok(_) | Err(VarError::NotPresent) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let user_dirs = config::config_dir(home).join("user-dirs.dirs");
+    let Ok(contents) = std::fs::read_to_string(user_dirs) else {
+        return Ok(None);
+    };
+
+    Ok(parse_user_dirs(&contents, home).remove(key))
+}
+
+/// Parse the contents of a `user-dirs.dirs` file, as written by `xdg-user-dirs-update`.
+///
+/// The format is a list of `XDG_XXX_DIR="$HOME/yyy"` lines, where the value is either
+/// an absolute path or one relative to `$HOME`. Lines starting with `#` are comments.
+pub fn parse_user_dirs(contents: &str, home: &Path) -> HashMap<String, PathBuf> {
+    let mut res = HashMap::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|i| i.strip_suffix('"'))
+            .unwrap_or(value);
+
+        let path = if value == "$HOME" {
+            home.to_path_buf()
+        } else if let Some(rest) = value.strip_prefix("$HOME/") {
+            home.join(rest)
+        } else if value.starts_with('/') {
+            PathBuf::from(value)
+        } else {
+            // not allowed by the spec, so ignore it
+            continue;
+        };
+
+        res.insert(key.trim().to_string(), path);
+    }
+
+    res
+}
+
+/// Extensions browsers give files that are still being downloaded
+const PARTIAL_EXTENSIONS: &[&str] = &["part", "crdownload", "download", "partial", "opdownload"];
+
+/// The names listed in the `.hidden` file in `dir`, which file managers on Linux hide like dotfiles
+pub fn hidden_names(dir: &Path) -> Vec<String> {
+    std::fs::read_to_string(dir.join(".hidden"))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|i| !i.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether a file called `name` is hidden, because it's a dotfile (like `.DS_Store`) or listed in `hidden`
+pub fn is_hidden(name: &str, hidden: &[String]) -> bool {
+    name.starts_with('.') || hidden.iter().any(|i| i == name)
+}
+
+/// Whether this is a download that's still in progress
+pub fn is_partial(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| PARTIAL_EXTENSIONS.iter().any(|i| ext.eq_ignore_ascii_case(i)))
+}
+
+/// Extensions of screenshots and screen recordings, to tell them apart from everything else on a Desktop
+const SCREENSHOT_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "heic", "tiff", "gif", "mov", "mp4", "webm", "mkv"];
+
+/// Whether this looks like a screenshot or screen recording
+pub fn is_screenshot(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| SCREENSHOT_EXTENSIONS.iter().any(|i| ext.eq_ignore_ascii_case(i)))
+}
+
+/// The files in `dir` that `keep` wants, most recent first with when they were created.
+/// Hidden files are skipped unless `include_hidden`, and so are downloads still in progress
+pub fn recent_files(dir: &Path, include_hidden: bool, keep: impl Fn(&Path) -> bool) -> Result<Vec<(SystemTime, PathBuf)>> {
+    let mut res = Vec::new();
+    let hidden = hidden_names(dir);
+
+    for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+        let i = i?;
+        // metadata like `.DS_Store` is never the download you want
+        if !include_hidden && is_hidden(&i.file_name().to_string_lossy(), &hidden) {
+            continue;
+        }
+        let Ok(meta) = i.metadata() else {
+            warn(Kind::Unreadable, format!("metadata of {:?}", i.path()));
+            continue;
+        };
+
+        if meta.is_file() {
+            if is_partial(&i.path()) {
+                warn(Kind::Skipped, format!("{:?} is still downloading (use --wait to wait for it)", i.path()));
+                continue;
+            }
+            if !keep(&i.path()) {
+                continue;
+            }
+
+            // not every filesystem keeps birth times, and a download isn't modified after it's done
+            let Ok(created) = meta.created().or_else(|_| meta.modified()) else {
+                warn(Kind::Unreadable, format!("creation time of {:?}", i.path()));
+                continue;
+            };
+
+            res.push((created, i.path()));
+        }
+    }
+
+    res.sort_by_key(|(created, _)| std::cmp::Reverse(*created));
+    Ok(res)
+}
+
+/// How long a partial download can stay the same before we stop waiting for it
+const STALLED_AFTER: Duration = Duration::from_secs(10);
+
+/// Block until there are no more in-progress downloads in `dir`, or `timeout` passes.
+/// Partial downloads that stopped changing don't count, browsers leave abandoned ones behind
+pub fn wait_for_partial(dir: &Path, timeout: Option<Duration>) -> Result<()> {
+    let start = Instant::now();
+    // the size and mtime of each partial download, and when we last saw those change
+    let mut seen = HashMap::new();
+    let mut announced = HashSet::new();
+
+    loop {
+        let mut active = false;
+        for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+            let i = i.wrap_err("read direntry")?;
+            let path = i.path();
+            let Ok(meta) = i.metadata() else {
+                continue;
+            };
+            if !is_partial(&path) {
+                continue;
+            }
+
+            let state = (meta.len(), meta.modified().ok());
+            let entry = seen.entry(path.clone()).or_insert_with(|| {
+                // the first time, its mtime says whether anything is still writing to it
+                let fresh = state.1.and_then(|i| i.elapsed().ok()).is_none_or(|i| i < STALLED_AFTER);
+                if !fresh {
+                    info!("ignoring {path:?}, it hasn't changed in a while");
+                }
+                (state, fresh.then(Instant::now))
+            });
+            if entry.0 != state {
+                *entry = (state, Some(Instant::now()));
+            }
+
+            if entry.1.is_some_and(|i| i.elapsed() < STALLED_AFTER) {
+                active = true;
+                if announced.insert(path.clone()) {
+                    info!("waiting for {path:?} to finish downloading");
+                }
+            }
+        }
+
+        if !active {
+            return Ok(());
+        }
+        if let Some(timeout) = timeout.filter(|i| start.elapsed() > *i) {
+            bail!("downloads in {dir:?} still in progress after {}", crate::age::human(timeout));
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// Whether the file at `path` has finished downloading: some browsers create it before the download is done
+/// and write to `<name>.part` next to it
+fn is_complete(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    !is_partial(path) && !PARTIAL_EXTENSIONS.iter().any(|i| path.with_file_name(format!("{name}.{i}")).exists())
+}
+
+/// Block until a file that wasn't in `dir` before appears there and finishes downloading, or `timeout` passes
+pub fn wait_for_new(dir: &Path, timeout: Option<Duration>) -> Result<PathBuf> {
+    let files = || -> Result<Vec<(PathBuf, u64)>> {
+        let mut res = Vec::new();
+        for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+            let i = i.wrap_err("read direntry")?;
+            // browsers also leave lock files and such, which start with a dot
+            match i.metadata() {
+                Ok(meta) if meta.is_file() && !i.file_name().to_string_lossy().starts_with('.') => res.push((i.path(), meta.len())),
+                _ => {}
+            }
+        }
+        Ok(res)
+    };
+
+    let before: Vec<_> = files()?.into_iter().map(|(path, _)| path).collect();
+    info!("waiting for a new download in {dir:?} (ctrl-c to stop)");
+
+    let start = Instant::now();
+    // a download is done when it's complete and its size didn't change since the last look
+    let mut sizes = HashMap::new();
+    loop {
+        for (path, size) in files()? {
+            if before.contains(&path) || !is_complete(&path) {
+                continue;
+            }
+            if sizes.insert(path.clone(), size) == Some(size) {
+                return Ok(path);
+            }
+        }
+
+        if let Some(timeout) = timeout.filter(|i| start.elapsed() > *i) {
+            bail!("no new download in {dir:?} after {}", crate::age::human(timeout));
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// Download `url` into `dir`, showing a progress bar on stderr. Returns the path of the downloaded file.
+pub fn fetch(url: &str, dir: &Path) -> Result<PathBuf> {
+    let response = ureq::get(url).call().wrap_err(format!("download {url}"))?;
+    let len = response
+        .header("Content-Length")
+        .and_then(|i| i.parse::<u64>().ok());
+
+    let path = dir.join(file_name_from_url(url));
+    info!("downloading {url} to {path:?}");
+
+    let progress = match len {
+        Some(len) => progress::bytes(len)?,
+        None => progress::spinner()?,
+    };
+
+    let mut file = File::create(&path).wrap_err(format!("create {path:?}"))?;
+    std::io::copy(&mut progress.wrap_read(response.into_reader()), &mut file)
+        .wrap_err(format!("download {url}"))?;
+    progress.finish_and_clear();
+
+    Ok(path)
+}
+
+/// The last path segment of a url, without query string or fragment
+pub fn file_name_from_url(url: &str) -> String {
+    let without_query = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(url);
+    let without_scheme = without_query
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(without_query);
+
+    let (host, path) = without_scheme.trim_end_matches('/').split_once('/').unwrap_or((without_scheme, ""));
+    let name = path.rsplit('/').next().map(|i| legal_file_name(&crate::share::decode(i))).unwrap_or_default();
+    if !name.is_empty() {
+        return name;
+    }
+
+    // nothing after the host, like https://example.com/?q=x
+    let host = host.rsplit('@').next().unwrap_or(host).split(':').next().unwrap_or(host);
+    match legal_file_name(host) {
+        host if host.is_empty() => "download".to_string(),
+        host => host,
+    }
+}
+
+/// `name` without characters that can't be in a file name on some platform, empty if nothing usable is left
+fn legal_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect();
+    // Windows doesn't like trailing dots, `.` and `..` aren't names at all and leading dots would hide it
+    name.trim().trim_matches('.').to_string()
+}
+
+/// A better name for a tempdir than the file name of the download at `path`, based on what's in it.
+///
+/// In order of preference: the single top-level directory of an archive, the title of a pdf,
+/// the comment of a zip file. Uses `unzip`, `tar` and `pdfinfo` when they're installed.
+pub fn name_from_content(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+
+    if name.ends_with(".zip") {
+        let listing = command_output(Command::new("unzip").arg("-Z1").arg(path));
+        listing.as_deref().and_then(top_level_dir)
+            .or_else(|| command_output(Command::new("unzip").arg("-zq").arg(path)).as_deref().and_then(sanitize_name))
+    } else if TAR_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) {
+        command_output(Command::new("tar").arg("-tf").arg(path)).as_deref().and_then(top_level_dir)
+    } else if name.ends_with(".pdf") {
+        let info = command_output(Command::new("pdfinfo").arg(path))?;
+        info.lines()
+            .find_map(|i| i.strip_prefix("Title:"))
+            .and_then(sanitize_name)
+    } else {
+        None
+    }
+}
+
+fn command_output(cmd: &mut Command) -> Option<String> {
+    let output = cmd.stderr(std::process::Stdio::null()).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The directory everything in an archive listing (one path per line) is in, if there's exactly one
+fn top_level_dir(listing: &str) -> Option<String> {
+    let mut top = None;
+    for line in listing.lines().map(|i| i.trim_start_matches("./")).filter(|i| !i.is_empty()) {
+        let (first, rest) = line.split_once('/').unwrap_or((line, ""));
+        // a file at the top level, next to whatever else is there
+        if rest.is_empty() && !line.ends_with('/') {
+            return None;
+        }
+        match top {
+            None => top = Some(first),
+            Some(top) if top != first => return None,
+            Some(_) => {}
+        }
+    }
+
+    top.and_then(sanitize_name)
+}
+
+/// Turn some text into something usable as a tempdir name, like `Quarterly Report` into `quarterly-report`
+pub fn sanitize_name(text: &str) -> Option<String> {
+    let words = text
+        .split(|c: char| !c.is_alphanumeric() && c != '.' && c != '_' && c != '-')
+        .filter(|i| !i.chars().all(|c| c == '.'))
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+
+    let name = words.join("-");
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshots() {
+        assert!(is_screenshot(Path::new("Screenshot from 2024-05-01 12-00-00.png")));
+        assert!(is_screenshot(Path::new("Screen Recording.MOV")));
+        assert!(!is_screenshot(Path::new("report.pdf")));
+    }
+
+    #[test]
+    fn hidden_files() {
+        let hidden = vec!["secret.pdf".to_string()];
+        assert!(is_hidden(".DS_Store", &hidden));
+        assert!(is_hidden("secret.pdf", &hidden));
+        assert!(!is_hidden("report.pdf", &hidden));
+        assert!(!is_hidden("report.pdf", &[]));
+    }
+
+    #[test]
+    fn parses_user_dirs() {
+        let home = Path::new("/home/t");
+        let dirs = parse_user_dirs(r#"
+# This file is written by xdg-user-dirs-update
+# If you want to change or add directories, just edit the line you're
+# interested in.
+XDG_DESKTOP_DIR="$HOME/Desktop"
+XDG_DOWNLOAD_DIR="$HOME/dl"
+XDG_MUSIC_DIR="/mnt/music"
+XDG_PUBLICSHARE_DIR="$HOME"
+XDG_TEMPLATES_DIR="relative/is/invalid"
+"#, home);
+
+        assert_eq!(dirs.get("XDG_DESKTOP_DIR"), Some(&PathBuf::from("/home/t/Desktop")));
+        assert_eq!(dirs.get("XDG_DOWNLOAD_DIR"), Some(&PathBuf::from("/home/t/dl")));
+        assert_eq!(dirs.get("XDG_MUSIC_DIR"), Some(&PathBuf::from("/mnt/music")));
+        assert_eq!(dirs.get("XDG_PUBLICSHARE_DIR"), Some(&PathBuf::from("/home/t")));
+        assert_eq!(dirs.get("XDG_TEMPLATES_DIR"), None);
+        assert_eq!(dirs.len(), 4);
+    }
+
+    #[test]
+    fn unquoted_values() {
+        let dirs = parse_user_dirs("XDG_DOWNLOAD_DIR=$HOME/Downloads", Path::new("/home/t"));
+        assert_eq!(dirs.get("XDG_DOWNLOAD_DIR"), Some(&PathBuf::from("/home/t/Downloads")));
+    }
+
+    #[test]
+    fn from_argument_wins() {
+        let config = Config {
+            downloads_dir: Some(PathBuf::from("/from/config")),
+            ..Default::default()
+        };
+        let dir = downloads_dir(Some(Path::new("/from/arg")), &config, Path::new("/home/t")).unwrap();
+        assert_eq!(dir, PathBuf::from("/from/arg"));
+    }
+
+    #[test]
+    fn config_expands_home() {
+        let config = Config {
+            downloads_dir: Some(PathBuf::from("~/stuff/downloads")),
+            ..Default::default()
+        };
+        let dir = downloads_dir(None, &config, Path::new("/home/t")).unwrap();
+        assert_eq!(dir, PathBuf::from("/home/t/stuff/downloads"));
+    }
+
+    #[test]
+    fn partial_downloads() {
+        assert!(is_partial(Path::new("/dl/movie.mkv.part")));
+        assert!(is_partial(Path::new("/dl/Unconfirmed 123.crdownload")));
+        assert!(is_partial(Path::new("/dl/report.pdf.DOWNLOAD")));
+        assert!(!is_partial(Path::new("/dl/report.pdf")));
+        assert!(!is_partial(Path::new("/dl/part")));
+    }
+
+    #[test]
+    fn url_file_names() {
+        assert_eq!(file_name_from_url("https://example.com/file.tar.gz"), "file.tar.gz");
+        assert_eq!(file_name_from_url("https://example.com/a/b/c.pdf?token=abc#page=2"), "c.pdf");
+        assert_eq!(file_name_from_url("https://example.com/releases/"), "releases");
+        assert_eq!(file_name_from_url("https://example.com"), "example.com");
+        assert_eq!(file_name_from_url("https://example.com/"), "example.com");
+        assert_eq!(file_name_from_url("https://example.com/a%20b.tar.gz"), "a b.tar.gz");
+        assert_eq!(file_name_from_url("https://example.com/?file=x.zip"), "example.com");
+        assert_eq!(file_name_from_url("https://user@example.com:8080/"), "example.com");
+        assert_eq!(file_name_from_url("https://example.com/%2F..%3Cx%3E"), "x");
+        assert_eq!(file_name_from_url("https://example.com/.."), "example.com");
+        assert_eq!(file_name_from_url("file:///"), "download");
+    }
+
+    #[test]
+    fn archive_top_level_dirs() {
+        assert_eq!(top_level_dir("project-1.2/\nproject-1.2/src/\nproject-1.2/src/main.rs\n"), Some("project-1.2".to_string()));
+        assert_eq!(top_level_dir("./foo/a\n./foo/b\n"), Some("foo".to_string()));
+        assert_eq!(top_level_dir("foo/a\nbar/b\n"), None);
+        assert_eq!(top_level_dir("foo/a\nREADME\n"), None);
+        assert_eq!(top_level_dir(""), None);
+    }
+
+    #[test]
+    fn sanitized_names() {
+        assert_eq!(sanitize_name(" Quarterly Report (Q3) "), Some("quarterly-report-q3".to_string()));
+        assert_eq!(sanitize_name("../../etc"), Some("etc".to_string()));
+        assert_eq!(sanitize_name("  "), None);
+    }
+
+    #[test]
+    fn abandoned_partials_dont_block() {
+        let dir = tempdir::TempDir::new("t-rs-test-partial").unwrap();
+        let abandoned = File::create(dir.path().join("old.zip.part")).unwrap();
+        abandoned.set_modified(SystemTime::now() - Duration::from_secs(60 * 60)).unwrap();
+        assert!(wait_for_partial(dir.path(), None).is_ok());
+
+        // one that's still being written to times out
+        File::create(dir.path().join("new.zip.part")).unwrap();
+        assert!(wait_for_partial(dir.path(), Some(Duration::ZERO)).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// What t-rs exits with when nothing went wrong enough to be an error, set by commands as they go
+static CODE: AtomicI32 = AtomicI32::new(0);
+
+/// Exit codes, so scripts can tell a no-op from success. Whatever happens, the directory to cd into is still written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Success = 0,
+    /// something failed, also used for errors
+    Error = 1,
+    /// the tempdir (or whatever else was asked for) doesn't exist, or we're not in a tempdir
+    NotFound = 2,
+    /// a tempdir with that name exists already
+    AlreadyExists = 3,
+    /// there was nothing to delete, clean up or undo, or the question was answered with no
+    NothingToDo = 4,
+}
+
+pub fn set(code: Code) {
+    CODE.store(code as i32, Ordering::Relaxed);
+}
+
+pub fn code() -> i32 {
+    CODE.load(Ordering::Relaxed)
+}
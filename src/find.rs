@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Lines longer than this are cut off when showing them
+const MAX_LINE: usize = 120;
+
+/// Something in a tempdir that matched
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match {
+    /// relative to the tempdir
+    pub path: PathBuf,
+    /// for matches in the contents of a file, the line number and the line
+    pub line: Option<(usize, String)>,
+}
+
+impl Match {
+    pub fn describe(&self) -> String {
+        match self.line {
+            Some((n, ref line)) => format!("{}:{n}: {line}", self.path.display()),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// Everything in `dir` with `pattern` in its name, ignoring case, and with `content` also every line of a text file
+/// containing it. `.git` directories are skipped, nobody means those
+fn search(dir: &Path, pattern: &str, content: bool) -> Vec<Match> {
+    fn walk(root: &Path, dir: &Path, pattern: &str, content: bool, res: &mut Vec<Match>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for i in entries.flatten() {
+            let path = i.path();
+            let name = i.file_name().to_string_lossy().to_lowercase();
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            if name.contains(pattern) {
+                res.push(Match { path: relative.clone(), line: None });
+            }
+
+            match i.file_type() {
+                Ok(t) if t.is_dir() && name != ".git" => walk(root, &path, pattern, content, res),
+                // binary files aren't valid UTF-8, so they're skipped here
+                Ok(t) if t.is_file() && content => {
+                    let Ok(text) = std::fs::read_to_string(&path) else {
+                        continue;
+                    };
+                    for (n, line) in text.lines().enumerate() {
+                        if line.to_lowercase().contains(pattern) {
+                            let line: String = line.trim().chars().take(MAX_LINE).collect();
+                            res.push(Match { path: relative.clone(), line: Some((n + 1, line)) });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut res = Vec::new();
+    walk(dir, dir, &pattern.to_lowercase(), content, &mut res);
+    res.sort_by(|a, b| a.path.cmp(&b.path));
+    res
+}
+
+/// Search all `entries` for `pattern` in parallel, returning the ones with matches
+pub fn find(entries: &[PathBuf], pattern: &str, content: bool) -> Vec<(PathBuf, Vec<Match>)> {
+    if entries.is_empty() {
+        return Vec::new();
+    }
+
+    let threads = thread::available_parallelism().map(|i| i.get()).unwrap_or(4);
+    let chunk_size = entries.len().div_ceil(threads);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(|i| (i.clone(), search(i, pattern, content))).collect::<Vec<_>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|i| i.join().expect("find thread panicked"))
+            .filter(|(_, matches)| !matches.is_empty())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_names_and_contents() {
+        let dir = tempdir::TempDir::new("t-rs-test-find").unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/.git")).unwrap();
+        std::fs::write(root.join("a/Invoice.pdf"), [0xff, 0xfe]).unwrap();
+        std::fs::write(root.join("a/.git/invoice"), "").unwrap();
+        std::fs::write(root.join("a/mail.txt"), "hi\nthe invoice is attached\n").unwrap();
+
+        let names = search(&root.join("a"), "invoice", false);
+        assert_eq!(names, vec![Match { path: PathBuf::from("Invoice.pdf"), line: None }]);
+
+        let all = search(&root.join("a"), "INVOICE", true);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].describe(), "mail.txt:2: the invoice is attached");
+    }
+}
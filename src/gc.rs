@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::journal::Journal;
+use crate::meta::Meta;
+use crate::trash::Trash;
+use crate::layout::Layout;
+use crate::{age, config, delete, info, prompt, sessions, usage};
+
+const CHOICES: [&str; 4] = [
+    "delete",
+    "keep (and pin it, so gc leaves it alone from now on)",
+    "skip (ask again next time)",
+    "stop",
+];
+
+/// Ask what to do with each of the `candidates` for `t gc --interactive`
+pub fn interactive(tempdirs: &Path, home: &Path, trash: &Trash, candidates: &[PathBuf]) -> Result<()> {
+    for (n, i) in candidates.iter().enumerate() {
+        let mut meta = Meta::load(i)?;
+
+        eprintln!();
+        eprintln!("{} ({}/{})", i.strip_prefix(tempdirs).unwrap_or(i).to_string_lossy(), n + 1, candidates.len());
+        eprintln!("    created {} ago", age::human(age::age(i)?));
+        eprintln!("    {}", usage::human_size(usage::size(i)));
+        if let Some(ref origin) = meta.origin {
+            eprintln!("    from {}", config::abbreviate_home(origin, home).to_string_lossy());
+        }
+        if let Some(ref note) = meta.note {
+            eprintln!("    {note}");
+        }
+
+        let choice = prompt::select("what should happen to it?", &CHOICES, 2)?;
+
+        match choice {
+            Some(0) => delete(i, trash, &Journal::new(tempdirs))?,
+            Some(1) => {
+                meta.pinned = true;
+                meta.save(i)?;
+                info!("pinned {i:?}");
+            }
+            Some(2) => {}
+            _ => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete tempdirs that are completely empty and older than `grace`, which are almost always
+/// left over from running `t` by accident. Returns how many were (or with `dry_run`, would be) deleted
+pub fn prune_empty(tempdirs: &Path, layout: Layout, grace: Duration, trash: &Trash, dry_run: bool) -> Result<usize> {
+    let journal = Journal::new(tempdirs);
+    let live = sessions::live(tempdirs)?;
+    let mut pruned = 0;
+
+    for i in layout.entries(tempdirs)? {
+        if live.contains(&i) || age::age(&i)? < grace || !is_empty(&i)? {
+            continue;
+        }
+        let meta = Meta::load(&i)?;
+        if meta.pinned || meta.foreign() {
+            continue;
+        }
+
+        if dry_run {
+            crate::would_delete(tempdirs, &i);
+        } else {
+            delete(&i, trash, &journal)?;
+        }
+        pruned += 1;
+    }
+    if !dry_run {
+        layout.tidy(tempdirs)?;
+    }
+
+    Ok(pruned)
+}
+
+fn is_empty(dir: &Path) -> Result<bool> {
+    // a symlink whose backing directory is gone isn't ours to judge, the cleanup pass handles those
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+    Ok(std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))?.next().is_none())
+}
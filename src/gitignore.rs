@@ -0,0 +1,78 @@
+use std::path::Path;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use glob::Pattern;
+use ignore::WalkBuilder;
+
+/// Whether `dir` has a `.gitignore` anywhere that could exclude something
+pub fn has_gitignore(dir: &Path) -> bool {
+    WalkBuilder::new(dir)
+        .standard_filters(false)
+        .build()
+        .filter_map(|i| i.ok())
+        .any(|i| i.path().file_name().is_some_and(|name| name == ".gitignore"))
+}
+
+/// Recursively copy the contents of `from` into `to`, skipping everything
+/// excluded by `.gitignore` files inside `from` (like `git archive` would).
+///
+/// Unlike git, this doesn't need `from` to be a git repository,
+/// and it ignores global and parent gitignores. Returns the number of bytes copied.
+pub fn copy_respecting_gitignore(from: &Path, to: &Path) -> Result<u64> {
+    copy_filtered(from, to, true, &[])
+}
+
+/// Like [`copy_respecting_gitignore`], but only copying files whose path relative to `from`
+/// matches one of `only` (when it's not empty), and only respecting `.gitignore` files if `gitignore` is set
+pub fn copy_filtered(from: &Path, to: &Path, gitignore: bool, only: &[Pattern]) -> Result<u64> {
+    let mut copied = 0;
+    // counting what to copy first would mean walking everything twice
+    let progress = crate::progress::spinner()?;
+
+    let walker = WalkBuilder::new(from)
+        .hidden(false)
+        .parents(false)
+        .ignore(false)
+        .git_ignore(gitignore)
+        .git_global(false)
+        .git_exclude(false)
+        .require_git(false)
+        .build();
+
+    for i in walker {
+        let i = i.wrap_err("walk directory")?;
+        let relative = i.path().strip_prefix(from).wrap_err("strip prefix")?;
+        let dest = to.join(relative);
+
+        let Some(file_type) = i.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            // with `only`, directories are created when something in them is copied
+            if only.is_empty() {
+                std::fs::create_dir_all(&dest).wrap_err(format!("create {dest:?}"))?;
+            }
+            continue;
+        }
+        if !only.is_empty() {
+            if !only.iter().any(|i| i.matches_path(relative)) {
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+            }
+        }
+
+        if file_type.is_symlink() {
+            let target = std::fs::read_link(i.path()).wrap_err("read link")?;
+            crate::platform::link_dir(target, &dest).wrap_err(format!("create symlink {dest:?}"))?;
+        } else {
+            copied += std::fs::copy(i.path(), &dest).wrap_err(format!("copy {:?} to {dest:?}", i.path()))?;
+            progress.set_position(copied);
+        }
+    }
+    progress.finish_and_clear();
+
+    Ok(copied)
+}
@@ -0,0 +1,163 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::age;
+use crate::journal::Op;
+use crate::warnings::{warn, Kind};
+
+/// An operation in the history, written by [`append`] whenever t-rs changes something.
+///
+/// Unlike the journal, which `t undo` rewrites, the history is only ever appended to.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Event {
+    /// in seconds since the unix epoch
+    pub time: u64,
+    /// the command line that did it
+    pub command: String,
+    #[serde(flatten)]
+    pub op: Op,
+}
+
+/// Add `op` to the history file at `path`, as a line of json
+pub fn append(path: &Path, op: &Op) -> Result<()> {
+    let command = std::env::args()
+        .enumerate()
+        .map(|(i, arg)| match i {
+            // just `t-rs`, not wherever it was installed
+            0 => Path::new(&arg).file_name().unwrap_or_default().to_string_lossy().to_string(),
+            _ => arg,
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let event = Event { time: age::now(), command, op: op.clone() };
+    let line = serde_json::to_string(&event).wrap_err("serialize history event")?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err(format!("open history {path:?}"))?;
+    writeln!(file, "{line}").wrap_err(format!("write history {path:?}"))
+}
+
+/// Everything in the history file at `path`, oldest first
+pub fn read(path: &Path) -> Result<Vec<Event>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = std::fs::read_to_string(path).wrap_err(format!("read history {path:?}"))?;
+    let mut res = Vec::new();
+    for line in contents.lines().filter(|i| !i.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(event) => res.push(event),
+            // a line cut off by a crash shouldn't make the rest unreadable
+            Err(_) => warn(Kind::Unreadable, format!("a line in history {path:?}")),
+        }
+    }
+
+    Ok(res)
+}
+
+/// A tempdir that existed at some point in the past, according to the history
+pub struct Past<'a> {
+    /// where it was at that point
+    pub entry: PathBuf,
+    /// when it was created
+    pub created: u64,
+    /// what happened to it afterwards, oldest first
+    pub since: Vec<&'a Event>,
+}
+
+/// Replay `events` to find which tempdirs existed at `time`, and what happened to them since.
+///
+/// Tempdirs created before the history was kept don't show up.
+pub fn at(events: &[Event], time: u64) -> Vec<Past<'_>> {
+    let (before, after): (Vec<_>, Vec<_>) = events.iter().partition(|i| i.time <= time);
+
+    let mut res: Vec<Past> = Vec::new();
+    for event in before {
+        match &event.op {
+            Op::Create { entry, .. } => res.push(Past { entry: entry.clone(), created: event.time, since: Vec::new() }),
+            Op::Rename { from, to } => {
+                for i in res.iter_mut().filter(|i| &i.entry == from) {
+                    i.entry = to.clone();
+                }
+            }
+            Op::Delete { entry, .. } | Op::Promote { entry, .. } => res.retain(|i| &i.entry != entry),
+            Op::Persist { .. } => {}
+        }
+    }
+
+    for past in &mut res {
+        // follow the tempdir through renames until it's gone
+        let mut current = Some(past.entry.clone());
+        for event in &after {
+            let Some(path) = &current else {
+                break;
+            };
+
+            match &event.op {
+                Op::Rename { from, to } if from == path => current = Some(to.clone()),
+                Op::Delete { entry, .. } | Op::Promote { entry, .. } if entry == path => current = None,
+                Op::Persist { entry, .. } if entry == path => {}
+                _ => continue,
+            }
+            past.since.push(event);
+        }
+    }
+
+    res
+}
+
+impl Event {
+    /// A line like `2023-07-14 13:37  created ~/tempdirs/foo from ~/src (t-rs)`
+    pub fn describe(&self, home: &Path) -> String {
+        let short = |path: &PathBuf| crate::config::abbreviate_home(path, home).to_string_lossy().to_string();
+        let time = chrono::DateTime::<chrono::Local>::from(age::from_unix(self.time)).format("%Y-%m-%d %H:%M");
+
+        let what = match &self.op {
+            Op::Create { entry, origin: Some(origin) } => format!("created {} from {}", short(entry), short(origin)),
+            Op::Create { entry, origin: None } => format!("created {}", short(entry)),
+            Op::Rename { from, to } => format!("renamed {} to {}", short(from), short(to)),
+            Op::Persist { entry, .. } => format!("persisted {}", short(entry)),
+            Op::Delete { entry, .. } => format!("deleted {}", short(entry)),
+            Op::Promote { entry, to } => format!("promoted {} to {}", short(entry), short(to)),
+        };
+
+        format!("{time}  {what} ({})", self.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(time: u64, op: Op) -> Event {
+        Event { time, command: "t-rs".to_string(), op }
+    }
+
+    #[test]
+    fn replays_history() {
+        let (a, b, c) = (PathBuf::from("/t/a"), PathBuf::from("/t/b"), PathBuf::from("/t/c"));
+        let events = vec![
+            event(1, Op::Create { entry: a.clone(), origin: None }),
+            event(2, Op::Create { entry: b.clone(), origin: None }),
+            event(3, Op::Delete { entry: b.clone(), backing: None }),
+            event(4, Op::Create { entry: b.clone(), origin: None }),
+            event(6, Op::Rename { from: a.clone(), to: c.clone() }),
+            event(7, Op::Delete { entry: c.clone(), backing: None }),
+        ];
+
+        let past = at(&events, 5);
+        assert_eq!(past.iter().map(|i| (&i.entry, i.created)).collect::<Vec<_>>(), vec![(&a, 1), (&b, 4)]);
+        assert_eq!(past[0].since.iter().map(|i| i.time).collect::<Vec<_>>(), vec![6, 7]);
+        assert!(past[1].since.is_empty());
+
+        assert!(at(&events, 0).is_empty());
+    }
+}
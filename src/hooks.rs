@@ -0,0 +1,80 @@
+use std::path::Path;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::error;
+
+/// Commands from the config file to run when something happens to a tempdir, like
+///
+/// ```toml
+/// on_create = "git init && direnv allow"
+/// ```
+///
+/// They're run with `sh -c` inside the tempdir, with `$T_RS_PATH` and `$T_RS_NAME` set to its path and name.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Hooks {
+    /// after a tempdir is created
+    pub on_create: Option<String>,
+    /// before starting a shell in a tempdir
+    pub on_enter_shell: Option<String>,
+    /// before a tempdir is deleted
+    pub on_delete: Option<String>,
+    /// after a tempdir is persisted
+    pub on_persist: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    Create,
+    EnterShell,
+    Delete,
+    Persist,
+}
+
+impl Hook {
+    fn key(self) -> &'static str {
+        match self {
+            Hook::Create => "on_create",
+            Hook::EnterShell => "on_enter_shell",
+            Hook::Delete => "on_delete",
+            Hook::Persist => "on_persist",
+        }
+    }
+}
+
+impl Hooks {
+    fn get(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::Create => self.on_create.as_deref(),
+            Hook::EnterShell => self.on_enter_shell.as_deref(),
+            Hook::Delete => self.on_delete.as_deref(),
+            Hook::Persist => self.on_persist.as_deref(),
+        }
+    }
+
+    /// Run the command configured for `hook` (if any) in the tempdir `dir`.
+    ///
+    /// A failing hook is reported, but doesn't stop whatever triggered it.
+    pub fn run(&self, hook: Hook, dir: &Path) -> Result<()> {
+        let Some(command) = self.get(hook) else {
+            return Ok(());
+        };
+
+        let status = crate::platform::script(command)
+            .current_dir(dir)
+            .env("PWD", dir)
+            .env("T_RS_PATH", dir)
+            .env("T_RS_NAME", dir.file_name().unwrap_or_default())
+            // stdout is where the wrapper looks for the directory to cd into
+            .stdout(std::io::stderr())
+            .status()
+            .wrap_err(format!("run {} hook", hook.key()))?;
+
+        if !status.success() {
+            error!("{} hook `{command}` failed ({status})", hook.key());
+        }
+
+        Ok(())
+    }
+}
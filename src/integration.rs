@@ -0,0 +1,151 @@
+use std::path::Path;
+use clap::ValueEnum;
+use color_eyre::Result;
+use crate::{error, notice};
+
+/// Set by the wrapper function for every invocation of t-rs, so we can tell whether it's installed.
+/// The value is the version of the wrapper, to detect outdated ones.
+pub const WRAPPER_MARKER: &str = "T_RS_WRAPPER";
+const WRAPPER_VERSION: &str = "3";
+/// Set by the wrapper function to a file we write the directory to cd into to,
+/// instead of printing it as the last line of stdout
+pub const CD_FILE: &str = "T_RS_CD_FILE";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(name = "powershell")]
+    PowerShell,
+}
+
+impl Shell {
+    /// The shell the user runs, based on `$SHELL`. On Windows, where that's usually not set, PowerShell
+    pub fn detect() -> Option<Self> {
+        let Some(shell) = std::env::var_os("SHELL") else {
+            return (cfg!(windows) && std::env::var_os("PSModulePath").is_some()).then_some(Shell::PowerShell);
+        };
+        match Path::new(&shell).file_name()?.to_str()? {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "pwsh" | "pwsh.exe" | "powershell.exe" => Some(Shell::PowerShell),
+            _ => None,
+        }
+    }
+
+    /// The file the wrapper function should go in
+    pub fn rc_file(self) -> &'static str {
+        match self {
+            Shell::Bash => "~/.bashrc",
+            Shell::Zsh => "~/.zshrc",
+            Shell::Fish => "~/.config/fish/config.fish",
+            Shell::PowerShell => "~/Documents/PowerShell/Microsoft.PowerShell_profile.ps1",
+        }
+    }
+
+    /// The wrapper function that makes `t` cd into the directory t-rs writes to `$T_RS_CD_FILE`,
+    /// and completion of tempdir names for it
+    pub fn snippet(self) -> String {
+        let function = match self {
+            Shell::Bash | Shell::Zsh => format!(concat!(
+                "function t() {{\n",
+                "    local cd_file=\"$(mktemp)\"\n",
+                "    {marker}={version} {cd_file}=\"$cd_file\" t-rs \"$@\"\n",
+                "    cd \"$(cat \"$cd_file\")\"\n",
+                "    rm -f \"$cd_file\"\n",
+                "}}",
+            ), marker = WRAPPER_MARKER, version = WRAPPER_VERSION, cd_file = CD_FILE),
+            Shell::Fish => format!(concat!(
+                "function t\n",
+                "    set -l cd_file (mktemp)\n",
+                "    env {marker}={version} {cd_file}=$cd_file t-rs $argv\n",
+                "    cd (cat $cd_file)\n",
+                "    rm -f $cd_file\n",
+                "end",
+            ), marker = WRAPPER_MARKER, version = WRAPPER_VERSION, cd_file = CD_FILE),
+            Shell::PowerShell => format!(concat!(
+                "function t {{\n",
+                "    $cd_file = New-TemporaryFile\n",
+                "    $env:{marker} = \"{version}\"; $env:{cd_file} = $cd_file\n",
+                "    t-rs @args\n",
+                "    Remove-Item Env:{marker}, Env:{cd_file}\n",
+                "    $dir = Get-Content -Raw $cd_file\n",
+                "    if ($dir) {{ Set-Location $dir }}\n",
+                "    Remove-Item $cd_file\n",
+                "}}",
+            ), marker = WRAPPER_MARKER, version = WRAPPER_VERSION, cd_file = CD_FILE),
+        };
+
+        let completion = match self {
+            Shell::Bash => concat!(
+                "function _t_complete() {\n",
+                "    COMPREPLY=($(compgen -W \"$(t-rs __complete)\" -- \"${COMP_WORDS[COMP_CWORD]}\"))\n",
+                "}\n",
+                "complete -F _t_complete t",
+            ),
+            Shell::Zsh => concat!(
+                "function _t_complete() {\n",
+                "    compadd -- ${(f)\"$(t-rs __complete)\"}\n",
+                "}\n",
+                "compdef _t_complete t",
+            ),
+            Shell::Fish => "complete -c t -f -a '(t-rs __complete)'",
+            Shell::PowerShell => concat!(
+                "Register-ArgumentCompleter -Native -CommandName t -ScriptBlock {\n",
+                "    param($word)\n",
+                "    t-rs __complete | Where-Object { $_ -like \"$word*\" }\n",
+                "}",
+            ),
+        };
+
+        format!("{function}\n\n{completion}")
+    }
+}
+
+/// Add the wrapper function for `shell` to its rc file, if there isn't one in there already
+pub fn install(shell: Shell, home: &Path) -> Result<()> {
+    let rc_file = crate::config::expand_home(Path::new(shell.rc_file()), home);
+    let snippet = format!("# added by t-rs\n{}", shell.snippet());
+
+    if crate::setup::append_once(&rc_file, WRAPPER_MARKER, &snippet)? {
+        notice!("added the `t` function to {rc_file:?}, open a new shell to use it");
+    } else {
+        notice!("{rc_file:?} already has a `t` function, see `t check-integration` if it doesn't work");
+    }
+    Ok(())
+}
+
+/// What's wrong with the wrapper function we were run through, if anything
+pub fn wrapper_problem() -> Option<&'static str> {
+    match std::env::var(WRAPPER_MARKER) {
+        Ok(version) if version == WRAPPER_VERSION && std::env::var_os(CD_FILE).is_some() => None,
+        Ok(_) => Some("the `t` wrapper function is outdated"),
+        Err(_) => Some("t-rs wasn't run through the `t` wrapper function, so it can't change your directory"),
+    }
+}
+
+/// Check whether we were run through an up to date wrapper function, and explain how to install it if not
+pub fn check() {
+    let shell = Shell::detect();
+
+    match wrapper_problem() {
+        None => {
+            notice!("the `t` wrapper function is installed and working");
+            return;
+        }
+        Some(problem) if std::env::var_os(WRAPPER_MARKER).is_some() => eprintln!("{problem}, replace it with the one below"),
+        Some(problem) => eprintln!("{problem}"),
+    }
+
+    match shell {
+        Some(shell) => {
+            eprintln!("put this in {}, and open a new shell:\n", shell.rc_file());
+            eprintln!("{}", shell.snippet());
+        }
+        None => {
+            error!("couldn't tell which shell you use, try `t-rs init <shell>` with bash, zsh, fish or powershell");
+        }
+    }
+}
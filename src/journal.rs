@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use fs_extra::dir::CopyOptions;
+use serde::{Deserialize, Serialize};
+use crate::meta::Meta;
+use crate::registry::Registry;
+use crate::trash::{move_dir, Trash};
+use crate::{age, delete, history, info, rename, STATE_DIR};
+
+const JOURNAL: &str = "journal.toml";
+const HISTORY: &str = "history.jsonl";
+/// Only the most recent operations are kept, nobody undoes a hundred steps
+const MAX_RECORDS: usize = 100;
+
+/// Everything that changed the tempdirs root, so `t undo` can reverse it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Op {
+    Create {
+        entry: PathBuf,
+        /// the directory `t` was run from
+        #[serde(default)]
+        origin: Option<PathBuf>,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Persist {
+        entry: PathBuf,
+        /// where the tempdir lived before it was persisted
+        backing: PathBuf,
+    },
+    Delete {
+        entry: PathBuf,
+        /// for non-persistent tempdirs, where the symlink pointed to
+        backing: Option<PathBuf>,
+    },
+    Promote {
+        entry: PathBuf,
+        /// where the tempdir was moved to
+        to: PathBuf,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Record {
+    /// in seconds since the unix epoch
+    time: u64,
+    op: Op,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct Records {
+    ops: Vec<Record>,
+}
+
+/// The journal of operations, stored in `<tempdirs>/.t-rs/journal.toml`.
+/// Everything recorded in it also goes in the history, `<tempdirs>/.t-rs/history.jsonl`.
+pub struct Journal {
+    tempdirs: PathBuf,
+    path: PathBuf,
+    history: PathBuf,
+    registry: Option<Registry>,
+}
+
+impl Journal {
+    pub fn new(tempdirs: &Path) -> Self {
+        Self {
+            tempdirs: tempdirs.to_path_buf(),
+            path: tempdirs.join(STATE_DIR).join(JOURNAL),
+            history: tempdirs.join(STATE_DIR).join(HISTORY),
+            registry: home::home_dir().map(|home| Registry::new(&home)),
+        }
+    }
+
+    pub fn history(&self) -> Result<Vec<history::Event>> {
+        history::read(&self.history)
+    }
+
+    fn load(&self) -> Result<Records> {
+        if !self.path.exists() {
+            return Ok(Records::default());
+        }
+
+        let contents = std::fs::read_to_string(&self.path).wrap_err(format!("read journal {:?}", self.path))?;
+        toml::from_str(&contents).wrap_err(format!("parse journal {:?}", self.path))
+    }
+
+    fn save(&self, records: &Records) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+        }
+
+        let contents = toml::to_string(records).wrap_err("serialize journal")?;
+        std::fs::write(&self.path, contents).wrap_err(format!("write journal {:?}", self.path))
+    }
+
+    pub fn record(&self, op: Op) -> Result<()> {
+        let mut records = self.load()?;
+        history::append(&self.history, &op)?;
+        if let Some(ref registry) = self.registry {
+            registry.apply(&self.tempdirs, &op)?;
+        }
+        records.ops.push(Record { time: age::now(), op });
+
+        let excess = records.ops.len().saturating_sub(MAX_RECORDS);
+        records.ops.drain(..excess);
+
+        self.save(&records)
+    }
+
+    /// Whether there's nothing to [`undo`](Self::undo)
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.load()?.ops.is_empty())
+    }
+
+    /// Reverse the most recent operation, returning where to cd into
+    pub fn undo(&self, trash: &Trash) -> Result<Option<PathBuf>> {
+        let mut records = self.load()?;
+        let Some(last) = records.ops.pop() else {
+            bail!("there's nothing to undo");
+        };
+        let ago = age::human(SystemTime::now().duration_since(age::from_unix(last.time)).unwrap_or_default());
+        info!("the last operation was {ago} ago");
+
+        let res = match last.op {
+            Op::Create { ref entry, .. } => {
+                if !entry.exists() && !entry.is_symlink() {
+                    bail!("can't undo creating {entry:?}, it's already gone");
+                }
+                info!("undoing creating {entry:?}");
+                delete(entry, trash, self)?;
+                entry.parent().map(Path::to_path_buf)
+            }
+            Op::Rename { ref from, ref to } => {
+                info!("undoing renaming {from:?} to {to:?}");
+                if !rename(to, from, self)? {
+                    bail!("can't undo renaming {from:?}, it exists again");
+                }
+                Some(from.clone())
+            }
+            Op::Persist { ref entry, ref backing } => {
+                if entry.is_symlink() || !entry.is_dir() {
+                    bail!("can't undo persisting {entry:?}, it's not a persistent tempdir anymore");
+                }
+                if backing.exists() {
+                    bail!("can't undo persisting {entry:?}, {backing:?} exists again");
+                }
+
+                info!("undoing persisting {entry:?}, moving it back to {backing:?}");
+                fs_extra::dir::move_dir(entry, backing, &CopyOptions {
+                    copy_inside: true,
+                    ..Default::default()
+                }).wrap_err(format!("move {entry:?} back to {backing:?}"))?;
+                crate::platform::link_dir(backing, entry).wrap_err("create symlink")?;
+                let mut meta = Meta::load(entry)?;
+                meta.persistent = None;
+                meta.save(entry)?;
+                Some(entry.clone())
+            }
+            Op::Promote { ref entry, ref to } => {
+                if !to.is_dir() {
+                    bail!("can't undo promoting {entry:?}, {to:?} is gone");
+                }
+                if entry.exists() || entry.is_symlink() {
+                    bail!("can't undo promoting {entry:?}, it exists again");
+                }
+
+                info!("undoing promoting {entry:?}, moving {to:?} back as a persistent tempdir");
+                if let Some(parent) = entry.parent() {
+                    std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+                }
+                move_dir(to, entry)?;
+                Some(entry.clone())
+            }
+            Op::Delete { ref entry, ref backing } => {
+                let name = entry.file_name().unwrap_or_default().to_string_lossy();
+                info!("undoing deleting {entry:?}");
+
+                if trash.list()?.iter().any(|i| &i.entry == entry) {
+                    Some(trash.restore(&name)?)
+                } else if let Some(backing) = backing.as_ref().filter(|i| i.exists()) {
+                    crate::platform::link_dir(backing, entry).wrap_err("create symlink")?;
+                    Some(entry.clone())
+                } else {
+                    bail!("can't undo deleting {entry:?}, its contents are gone");
+                }
+            }
+        };
+
+        // leave out whatever undoing recorded itself, or the next undo would just redo this
+        self.save(&records)?;
+
+        Ok(res)
+    }
+}
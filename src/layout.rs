@@ -0,0 +1,253 @@
+use std::path::{Component, Path, PathBuf};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use serde::Deserialize;
+use crate::STATE_DIR;
+
+/// Where in the tempdirs root the entries go, set with `layout` in the config.
+///
+/// Symlinks directly in the tempdirs root are always entries, whatever the layout,
+/// so tempdirs created before changing the layout don't get lost.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// `~/tempdirs/<name>`
+    #[default]
+    Flat,
+    /// `~/tempdirs/<yyyy-mm-dd>/<name>`, by creation date
+    ByDate,
+    /// `~/tempdirs/<group>/<name>`, with the group given by `--group`
+    ByGroup,
+}
+
+/// The group of tempdirs created without `--group` in the `by-group` layout
+const UNGROUPED: &str = "ungrouped";
+
+impl Layout {
+    fn nested(self) -> bool {
+        self != Layout::Flat
+    }
+
+    /// Whether `dir` in the tempdirs root holds entries, like `2024-05-01` or a group. Directories in the root
+    /// that were persisted or have nothing to do with t-rs are left alone
+    fn bucket(self, dir: &Path) -> bool {
+        if !self.nested() || dir.is_symlink() || !dir.is_dir() || crate::meta::exists(dir) {
+            return false;
+        }
+
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        match self {
+            Layout::Flat => false,
+            Layout::ByDate => chrono::NaiveDate::parse_from_str(&name, "%Y-%m-%d").is_ok(),
+            // the metadata of the entries in a group is kept in the group
+            Layout::ByGroup => name == UNGROUPED || dir.join(STATE_DIR).is_dir(),
+        }
+    }
+
+    /// The directory a new entry should be created in
+    pub fn dir(self, tempdirs: &Path, group: Option<&str>) -> PathBuf {
+        match self {
+            Layout::Flat => tempdirs.to_path_buf(),
+            Layout::ByDate => tempdirs.join(chrono::Local::now().format("%Y-%m-%d").to_string()),
+            Layout::ByGroup => tempdirs.join(group.unwrap_or(UNGROUPED)),
+        }
+    }
+
+    /// All entries in the tempdirs root, sorted by path
+    pub fn entries(self, tempdirs: &Path) -> Result<Vec<PathBuf>> {
+        let mut res = Vec::new();
+        for i in read_dir(tempdirs)? {
+            if self.bucket(&i) {
+                res.extend(read_dir(&i)?);
+            } else {
+                res.push(i);
+            }
+        }
+
+        res.sort();
+        Ok(res)
+    }
+
+    /// Find the entry with this name. With nested layouts, `group/name` works too.
+    pub fn find(self, tempdirs: &Path, name: &str) -> Result<Option<PathBuf>> {
+        let direct = tempdirs.join(name);
+        let is_entry = self.entry_containing(tempdirs, &direct).as_ref() == Some(&direct);
+        if is_entry && (direct.is_symlink() || direct.exists()) {
+            return Ok(Some(direct));
+        }
+
+        Ok(self
+            .entries(tempdirs)?
+            .into_iter()
+            .find(|i| i.file_name().is_some_and(|i| i == name)))
+    }
+
+    /// The entries whose name, or path in the tempdirs root like `group/name`, matches the glob `pattern`
+    pub fn glob(self, tempdirs: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+        let pattern = glob::Pattern::new(pattern).wrap_err(format!("invalid glob pattern {pattern:?}"))?;
+        Ok(self
+            .entries(tempdirs)?
+            .into_iter()
+            .filter(|i| {
+                let relative = i.strip_prefix(tempdirs).unwrap_or(i);
+                pattern.matches_path(relative) || i.file_name().is_some_and(|i| pattern.matches(&i.to_string_lossy()))
+            })
+            .collect())
+    }
+
+    /// The entries whose name starts with `query`, or if there are none, that contain its characters in order
+    pub fn find_fuzzy(self, tempdirs: &Path, query: &str) -> Result<Vec<PathBuf>> {
+        Ok(fuzzy_matches(self.entries(tempdirs)?, query))
+    }
+
+    /// The entry that `path` (a path somewhere inside the tempdirs root) belongs to.
+    ///
+    /// The tempdirs root can be a symlink to somewhere else, like another disk, so `path` can also go
+    /// through where it points. The entry is always in terms of `tempdirs` itself.
+    pub fn entry_containing(self, tempdirs: &Path, path: &Path) -> Option<PathBuf> {
+        let rest = crate::platform::strip_prefix(path, tempdirs)?;
+        let mut components = rest.components();
+        let Some(Component::Normal(first)) = components.next() else {
+            return None;
+        };
+        if first == STATE_DIR {
+            return None;
+        }
+
+        let first = tempdirs.join(first);
+        if !self.bucket(&first) {
+            return Some(first);
+        }
+
+        match components.next() {
+            Some(Component::Normal(second)) if second != STATE_DIR => Some(first.join(second)),
+            _ => None,
+        }
+    }
+
+    /// Remove directories of nested layouts that don't contain any entries anymore
+    pub fn tidy(self, tempdirs: &Path) -> Result<()> {
+        if !self.nested() {
+            return Ok(());
+        }
+
+        for i in read_dir(tempdirs)? {
+            if self.bucket(&i) && read_dir(&i)?.is_empty() {
+                // only the bookkeeping of deleted tempdirs is left
+                let state = i.join(STATE_DIR);
+                if state.exists() {
+                    std::fs::remove_dir_all(&state).wrap_err(format!("remove {state:?}"))?;
+                }
+                std::fs::remove_dir(&i).wrap_err(format!("remove empty {i:?}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Everything in `dir` except for our own bookkeeping
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut res = Vec::new();
+    for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+        let i = i.wrap_err("read direntry")?;
+        if i.file_name() != STATE_DIR {
+            res.push(i.path());
+        }
+    }
+
+    Ok(res)
+}
+
+fn fuzzy_matches(entries: Vec<PathBuf>, query: &str) -> Vec<PathBuf> {
+    let name = |i: &PathBuf| i.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+    let (prefixed, rest): (Vec<_>, Vec<_>) = entries.into_iter().partition(|i| name(i).starts_with(query));
+    if !prefixed.is_empty() {
+        return prefixed;
+    }
+
+    rest.into_iter()
+        .filter(|i| {
+            let name = name(i);
+            let mut chars = name.chars();
+            query.chars().all(|c| chars.any(|i| i == c))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_containing() {
+        let tempdirs = Path::new("/home/t/tempdirs");
+        let inside = Path::new("/home/t/tempdirs/a/b/c");
+
+        assert_eq!(Layout::Flat.entry_containing(tempdirs, inside), Some(PathBuf::from("/home/t/tempdirs/a")));
+        assert_eq!(Layout::Flat.entry_containing(tempdirs, tempdirs), None);
+        assert_eq!(Layout::Flat.entry_containing(tempdirs, Path::new("/home/t/tempdirs/.t-rs/meta")), None);
+        assert_eq!(Layout::Flat.entry_containing(tempdirs, Path::new("/elsewhere")), None);
+    }
+
+    #[test]
+    fn fuzzy() {
+        let entries = ["/t/unnamed_4", "/t/unnamed_12", "/t/project", "/t/g/parser"].map(PathBuf::from).to_vec();
+        let matches = |query| fuzzy_matches(entries.clone(), query).into_iter().map(|i| i.to_string_lossy().to_string()).collect::<Vec<_>>();
+
+        assert_eq!(matches("unnamed_4"), vec!["/t/unnamed_4"]);
+        assert_eq!(matches("unna"), vec!["/t/unnamed_4", "/t/unnamed_12"]);
+        assert_eq!(matches("pro"), vec!["/t/project"]);
+        // no prefix matches, so anything with the letters in order
+        assert_eq!(matches("prs"), vec!["/t/g/parser"]);
+        assert_eq!(matches("pj"), vec!["/t/project"]);
+        assert!(matches("xyz").is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn entry_containing_through_symlinked_root() {
+        let dir = tempdir::TempDir::new("t-rs-test-layout").unwrap();
+        let root = dir.path();
+        let disk = root.join("disk");
+        let tempdirs = root.join("tempdirs");
+        std::fs::create_dir_all(disk.join("foo/src")).unwrap();
+        std::os::unix::fs::symlink(&disk, &tempdirs).unwrap();
+        let disk = disk.canonicalize().unwrap();
+
+        assert_eq!(Layout::Flat.entry_containing(&tempdirs, &disk.join("foo/src")), Some(tempdirs.join("foo")));
+        assert_eq!(Layout::Flat.entry_containing(&tempdirs, &tempdirs.join("foo/src")), Some(tempdirs.join("foo")));
+        assert_eq!(Layout::Flat.entry_containing(&tempdirs, root), None);
+    }
+
+    #[test]
+    fn nested_layouts_only_descend_into_buckets() {
+        let dir = tempdir::TempDir::new("t-rs-test-layout").unwrap();
+        let tempdirs = dir.path();
+        for i in ["2024-05-01/foo", "kept/src", "group/bar", "ungrouped/baz"] {
+            std::fs::create_dir_all(tempdirs.join(i)).unwrap();
+        }
+        // t-rs keeps the metadata of a group's entries in the group
+        crate::meta::Meta::default().save(&tempdirs.join("group/bar")).unwrap();
+        // persisted in the root, back when the layout was flat
+        crate::meta::Meta { persistent: Some(true), ..Default::default() }.save(&tempdirs.join("2024-06-01")).unwrap();
+        std::fs::create_dir_all(tempdirs.join("2024-06-01/src")).unwrap();
+
+        let names = |layout: Layout| layout.entries(tempdirs).unwrap().into_iter().map(|i| i.strip_prefix(tempdirs).unwrap().to_string_lossy().to_string()).collect::<Vec<_>>();
+        assert_eq!(names(Layout::ByDate), ["2024-05-01/foo", "2024-06-01", "group", "kept", "ungrouped"]);
+        assert_eq!(names(Layout::ByGroup), ["2024-05-01", "2024-06-01", "group/bar", "kept", "ungrouped/baz"]);
+
+        let inside = tempdirs.join("group/bar/src");
+        assert_eq!(Layout::ByGroup.entry_containing(tempdirs, &inside), Some(tempdirs.join("group/bar")));
+        assert_eq!(Layout::ByGroup.entry_containing(tempdirs, &tempdirs.join("group")), None);
+        assert_eq!(Layout::ByGroup.entry_containing(tempdirs, &tempdirs.join("kept/src")), Some(tempdirs.join("kept")));
+        assert_eq!(Layout::ByDate.entry_containing(tempdirs, &tempdirs.join("2024-06-01/src")), Some(tempdirs.join("2024-06-01")));
+
+        std::fs::remove_dir(tempdirs.join("2024-05-01/foo")).unwrap();
+        std::fs::remove_dir(tempdirs.join("kept/src")).unwrap();
+        Layout::ByDate.tidy(tempdirs).unwrap();
+        assert!(!tempdirs.join("2024-05-01").exists());
+        assert!(tempdirs.join("kept").exists());
+    }
+}
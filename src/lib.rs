@@ -0,0 +1,986 @@
+//! Managing temporary directories: creating them, finding them again, persisting and deleting them.
+//!
+//! The `t` command is a thin layer over this, and [`TempdirManager`] is the place to start for other tools.
+
+use std::fs::read_link;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, SystemTime};
+use clap::ValueEnum;
+use color_eyre::eyre::{Context, ContextCompat};
+use color_eyre::Result;
+use crate::hooks::{Hook, Hooks};
+use crate::journal::{Journal, Op};
+use crate::layout::Layout;
+use crate::lock::Lock;
+use crate::meta::Meta;
+use crate::pipeline::ShellOptions;
+use crate::registry::Registry;
+use crate::sessions::Session;
+use crate::table::{Color, Table};
+use crate::trash::Trash;
+use crate::warnings::{warn, Kind};
+
+pub use crate::manager::TempdirManager;
+// for the logging macros in `output`
+#[doc(hidden)]
+pub use tracing;
+
+pub const TEMPDIR_PREFIX: &str = "T-RS-TEMPDIR";
+pub const TEMPDIRS: &str = "tempdirs";
+/// Where t-rs keeps its own bookkeeping, inside the tempdirs root
+pub const STATE_DIR: &str = ".t-rs";
+
+pub mod age;
+pub mod archive;
+pub mod backup;
+pub mod builder;
+pub mod cache;
+pub mod clipboard;
+pub mod compare;
+pub mod config;
+pub mod daemon;
+pub mod direnv;
+pub mod doctor;
+pub mod downloads;
+pub mod exit;
+pub mod find;
+pub mod gc;
+pub mod gitignore;
+pub mod history;
+pub mod hooks;
+pub mod integration;
+pub mod journal;
+pub mod layout;
+pub mod lock;
+pub mod manpages;
+pub mod manager;
+pub mod meta;
+pub mod naming;
+pub mod notify;
+pub mod orphans;
+pub mod output;
+pub mod pipeline;
+pub mod platform;
+pub mod progress;
+pub mod prompt;
+pub mod push;
+pub mod registry;
+pub mod secret;
+pub mod sessions;
+pub mod setup;
+pub mod share;
+pub mod stats;
+pub mod table;
+pub mod tasks;
+pub mod timer;
+#[cfg(target_os = "linux")]
+pub mod tmpfs;
+pub mod tmux;
+pub mod top;
+pub mod trash;
+pub mod ui;
+pub mod update;
+pub mod usage;
+pub mod warnings;
+pub mod watch;
+pub mod zoxide;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum SortBy {
+    #[default]
+    Name,
+    /// oldest first
+    Age,
+    /// largest first
+    Size,
+}
+
+/// Remove stale symlinks (after a reboot, for example) and bring the registry in line with the tempdirs root.
+/// With `dry_run`, only say which symlinks would be removed
+pub fn cleanup(tempdirs: &Path, layout: Layout, home: &Path, dry_run: bool) -> Result<Vec<PathBuf>> {
+    // another t-rs could be creating a tempdir whose symlink is there before its metadata
+    let _lock = (!dry_run).then(|| Lock::acquire(tempdirs)).transpose()?;
+
+    // finish the job for `t shell` sessions that were killed
+    if !dry_run {
+        sessions::cleanup_dead(tempdirs)?;
+    }
+
+    let mut removed = Vec::new();
+    for i in layout.entries(tempdirs)? {
+        let Ok(backing) = read_link(&i) else {
+            continue;
+        };
+        // the backing dirs of tempdirs from other machines that share the tempdirs root aren't here
+        if !backing.exists() && !Meta::load(&i)?.foreign() {
+            if dry_run {
+                notice!("would remove {:?}, its files at {backing:?} are gone", i.strip_prefix(tempdirs).unwrap_or(&i));
+                removed.push(i);
+                continue;
+            }
+            debug!("removing {i:?}, the files it pointed to at {backing:?} are gone");
+            warn(Kind::StaleSymlink, i.to_string_lossy());
+            symlink::remove_symlink_auto(&i).wrap_err("remove symlink")?;
+            release_backing(&backing)?;
+            meta::remove(&i)?;
+            zoxide::remove(&i);
+            removed.push(i);
+        }
+    }
+    if dry_run {
+        return Ok(removed);
+    }
+
+    layout.tidy(tempdirs)?;
+    Registry::new(home).reconcile(tempdirs, &layout.entries(tempdirs)?)?;
+    Ok(removed)
+}
+
+/// Start a shell in the new tempdir `res`, and delete it when the shell exits
+pub fn shell(tempdirs: &Path, res: &Path, hooks: &Hooks, options: &ShellOptions) -> Result<()> {
+    hooks.run(Hook::EnterShell, res)?;
+    let mut cmd = shell_command(res, options.program.as_deref())?;
+    if let Some(ref command) = options.command {
+        cmd.arg(platform::command_flag(cmd.get_program())).arg(command);
+    }
+    run_in_session(tempdirs, res, cmd, |_| false)?;
+
+    Ok(())
+}
+
+/// Run `cmd` in the new tempdir `res`, and delete the tempdir when it exits unless `keep` says otherwise.
+/// Persisting the tempdir in the meantime also keeps it.
+pub fn run_in_session(tempdirs: &Path, res: &Path, mut cmd: Command, keep: impl FnOnce(ExitStatus) -> bool) -> Result<ExitStatus> {
+    // remember what to clean up, in case we don't get the chance to do it ourselves
+    let mut session = if res.is_symlink() {
+        let target = std::fs::read_link(res).wrap_err("read link")?;
+        Some(Session::register(tempdirs, res, &target)?)
+    } else {
+        None
+    };
+
+    cmd.env(sessions::SESSION_PID, std::process::id().to_string());
+    let mut child = cmd.spawn().wrap_err(format!("spawn {:?}", cmd.get_program()))?;
+    if let Some(ref mut session) = session {
+        session.set_shell_pid(child.id())?;
+    }
+    let status = child.wait().wrap_err("wait for child")?;
+
+    if res.is_symlink() && !keep(status) {
+        discard(res)?;
+    }
+
+    if let Some(session) = session {
+        session.finish()?;
+    }
+
+    Ok(status)
+}
+
+/// Remove the tempdir `res` t-rs just created, with its backing directory, without putting it in the trash
+pub fn discard(res: &Path) -> Result<()> {
+    // find the symlink target
+    let target = std::fs::read_link(res).wrap_err("read link")?;
+    // unlink the link so only the /tmp/... remains
+    symlink::remove_symlink_auto(res).wrap_err("unlink")?;
+    // remove the /tmp/... dir too
+    release_backing(&target)?;
+    if target.exists() {
+        std::fs::remove_dir_all(&target).wrap_err("remove dir")?;
+    }
+    meta::remove(res)?;
+    zoxide::remove(res);
+    Ok(())
+}
+
+/// Turn the tempdir `p` into a real directory in the tempdirs root, returning `false` if it was persistent already.
+/// With `gitignore` or `only`, just the files that aren't ignored or do match are kept.
+pub fn persist(p: &Path, gitignore: bool, only: &[glob::Pattern], journal: &Journal) -> Result<bool> {
+    let mut meta = Meta::load(p)?;
+    if meta.persistent(p) {
+        return Ok(false);
+    }
+    if !p.is_symlink() {
+        // a real directory already, so there's nothing to move
+        meta.persistent = Some(true);
+        meta.save(p)?;
+        info!("{p:?} is now persistent");
+
+        return Ok(true);
+    }
+
+    let original_target = std::fs::read_link(p).wrap_err("read link")?;
+
+    // everything goes to a sibling of the entry first, and only replaces the symlink once it's all there.
+    // Until then the tempdir is untouched, so nothing is lost when copying fails halfway (like when the disk is full)
+    let name = p.file_name().wrap_err(format!("{p:?} has no name"))?.to_string_lossy();
+    let staging = p.with_file_name(format!(".{name}.t-rs-partial"));
+    if staging.exists() {
+        // left behind by a persist that was interrupted before
+        std::fs::remove_dir_all(&staging).wrap_err(format!("remove {staging:?}"))?;
+    }
+
+    let filtered = !only.is_empty() || (gitignore && gitignore::has_gitignore(&original_target));
+    // on the same filesystem the backing directory can just be renamed
+    let moved = !filtered && std::fs::rename(&original_target, &staging).is_ok();
+    if !moved {
+        if let Err(e) = copy_for_persist(&original_target, &staging, gitignore, only) {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e.wrap_err(format!("copy {original_target:?} to {p:?}, which is left as it was")));
+        }
+    }
+
+    symlink::remove_symlink_auto(p).wrap_err("unlink")?;
+    if let Err(e) = std::fs::rename(&staging, p) {
+        // give the tempdir its entry back, wherever its files are now
+        if moved {
+            std::fs::rename(&staging, &original_target).wrap_err(format!("move {staging:?} back to {original_target:?}"))?;
+        }
+        platform::link_dir(&original_target, p).wrap_err("restore symlink")?;
+        return Err(e).wrap_err(format!("move {staging:?} to {p:?}"));
+    }
+    if !moved {
+        std::fs::remove_dir_all(&original_target).wrap_err("remove original tempdir")?;
+    }
+
+    release_backing(&original_target)?;
+    meta.persistent = Some(true);
+    meta.save(p)?;
+    info!("{:?} is now persistent", p);
+    journal.record(Op::Persist { entry: p.to_path_buf(), backing: original_target })?;
+    Ok(true)
+}
+
+/// Copy the tempdir `from` to `to` for [`persist`], leaving out files like `gitignore` and `only` say, and check it all made it
+fn copy_for_persist(from: &Path, to: &Path, gitignore: bool, only: &[glob::Pattern]) -> Result<()> {
+    if !only.is_empty() {
+        let patterns = only.iter().map(glob::Pattern::as_str).collect::<Vec<_>>().join(" ");
+        info!("copying files matching {patterns} from {from:?}, deleting the rest");
+    } else if gitignore && gitignore::has_gitignore(from) {
+        info!("copying from {from:?}, leaving out gitignored files");
+    } else {
+        info!("copying from {from:?}");
+        return trash::copy_dir(from, to);
+    }
+
+    std::fs::create_dir(to).wrap_err(format!("create {to:?}"))?;
+    let copied = gitignore::copy_filtered(from, to, gitignore, only)?;
+    let (_, size) = trash::contents(to)?;
+    if size != copied {
+        color_eyre::eyre::bail!("copied {size} of {copied} bytes from {from:?} to {to:?}");
+    }
+    Ok(())
+}
+
+/// The environment variables of the tempdir at `dir`, to tell prompts and scripts where they are:
+/// `$T_NAME`, `$T_DIR`, `$T_PERSISTENT` and `$T_EPHEMERAL` (`1` or `0`), then the ones set with `t env set`
+pub fn tempdir_env(dir: &Path) -> Result<Vec<(String, String)>> {
+    let meta = Meta::load(dir)?;
+    let persistent = meta.persistent(dir);
+    let mut res = vec![
+        ("T_NAME".to_string(), dir.file_name().unwrap_or_default().to_string_lossy().to_string()),
+        ("T_DIR".to_string(), dir.to_string_lossy().to_string()),
+        ("T_PERSISTENT".to_string(), if persistent { "1" } else { "0" }.to_string()),
+        ("T_EPHEMERAL".to_string(), if persistent { "0" } else { "1" }.to_string()),
+    ];
+    res.extend(meta.env);
+    Ok(res)
+}
+
+/// What `t env export` prints for the tempdir at `entry`: `export` lines for its [`tempdir_env`], or outside a
+/// tempdir an `unset` of the standard variables, so they don't linger after leaving one
+pub fn env_script(entry: Option<&Path>) -> Result<String> {
+    let Some(entry) = entry else {
+        return Ok("unset T_NAME T_DIR T_PERSISTENT T_EPHEMERAL\n".to_string());
+    };
+
+    let mut res = String::new();
+    for (key, value) in tempdir_env(entry)? {
+        res.push_str(&format!("export {key}={}\n", direnv::quote(&value)));
+    }
+    Ok(res)
+}
+
+/// A command to start `program`, or otherwise the user's shell, in the tempdir at `dir`, with its [`tempdir_env`] set
+pub fn shell_command(dir: &Path, program: Option<&Path>) -> Result<Command> {
+    let shell = match program {
+        Some(program) => program.to_path_buf(),
+        None => platform::default_shell(),
+    };
+
+    let mut cmd = Command::new(shell);
+    // this only sets the cd path which resolves symlinks
+    cmd.current_dir(dir);
+    // but most shells actually show what path you're in based on `pwd` and PWD
+    // so we also set that
+    cmd.env("PWD", dir);
+    cmd.envs(tempdir_env(dir)?);
+    Ok(cmd)
+}
+
+/// Where [`promote`] moves the tempdir at `entry` for `dest`: into it if it's a directory, otherwise to it
+pub fn promote_target(entry: &Path, dest: &Path) -> PathBuf {
+    if dest.is_dir() {
+        dest.join(entry.file_name().unwrap_or_default())
+    } else {
+        dest.to_path_buf()
+    }
+}
+
+/// Move the tempdir at `entry` out of the tempdirs to `dest`, returning where it ended up,
+/// or `None` if something's there already
+pub fn promote(entry: &Path, dest: &Path, journal: &Journal) -> Result<Option<PathBuf>> {
+    let dest = promote_target(entry, dest);
+    if dest.exists() {
+        return Ok(None);
+    }
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+    }
+
+    info!("moving {entry:?} to {dest:?}");
+    if entry.is_symlink() {
+        let backing = std::fs::read_link(entry).wrap_err("read link")?;
+        trash::move_dir(&backing, &dest)?;
+        symlink::remove_symlink_auto(entry).wrap_err("unlink")?;
+        release_backing(&backing)?;
+    } else {
+        trash::move_dir(entry, &dest)?;
+    }
+    meta::remove(entry)?;
+    journal.record(Op::Promote { entry: entry.to_path_buf(), to: dest.clone() })?;
+
+    info!("{entry:?} is now {dest:?}");
+    Ok(Some(dest))
+}
+
+/// Make `dir` a git repository with everything in it committed, unless it's one already
+pub fn git_init(dir: &Path) -> Result<()> {
+    if dir.join(".git").exists() {
+        info!("{dir:?} is already a git repository, not committing anything");
+        return Ok(());
+    }
+
+    for args in [&["init", "--quiet"][..], &["add", "--all"], &["commit", "--quiet", "--message", "Initial commit"]] {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .wrap_err("run git")?;
+        if !status.success() {
+            color_eyre::eyre::bail!("`git {}` failed ({status})", args.join(" "));
+        }
+    }
+    Ok(())
+}
+
+/// Rename the tempdir at `old` to `new`, returning `false` if there's something at `new` already
+pub fn rename(old: &Path, new: &Path, journal: &Journal) -> Result<bool> {
+    if new.exists() {
+        return Ok(false);
+    }
+
+    if !old.is_symlink() {
+        // if it's a folder, rename normally
+        info!("renaming persistent tempdir {old:?} to {new:?}");
+        std::fs::rename(old, new).wrap_err("rename")?;
+    } else {
+        info!("renaming tempdir {old:?} to {new:?}");
+        // else unlink and create a new link
+        let target = std::fs::read_link(old).wrap_err("read link")?;
+        symlink::remove_symlink_auto(old).wrap_err("unlink old")?;
+        platform::link_dir(target, new).wrap_err("symlink new")?;
+    }
+    meta::rename(old, new)?;
+    journal.record(Op::Rename { from: old.to_path_buf(), to: new.to_path_buf() })?;
+    Ok(true)
+}
+
+pub fn delete(path: &Path, trash: &Trash, journal: &Journal) -> Result<()> {
+    let meta = Meta::load(path)?;
+    if let (true, Some(host)) = (meta.foreign() && !path.exists(), &meta.host) {
+        color_eyre::eyre::bail!("{path:?} was created on {host} and its files are there, delete it from there");
+    }
+
+    if meta.persistent(path) {
+        info!("deleting {:?} (persistent)", path);
+    } else {
+        info!("deleting {:?}", path);
+    }
+    let backing = if path.is_symlink() {
+        Some(std::fs::read_link(path).wrap_err("read link")?)
+    } else {
+        None
+    };
+
+    trash.delete(path)?;
+    if let Some(ref backing) = backing {
+        release_backing(backing)?;
+    }
+    zoxide::remove(path);
+    output::record(&["deleted", &path.to_string_lossy(), &backing.as_deref().unwrap_or(path).to_string_lossy()]);
+    journal.record(Op::Delete { entry: path.to_path_buf(), backing })
+}
+
+/// Copy the tempdir at `entry` to a new entry next to it called `<name>-snap-<n>`, which is persistent if `entry` is
+pub fn snapshot(tempdirs: &Path, layout: Layout, entry: &Path, backing: &Backing, trash: &Trash, journal: &Journal) -> Result<PathBuf> {
+    let name = entry.file_name().wrap_err(format!("{entry:?} has no name"))?.to_string_lossy().to_string();
+    let mut n = 1;
+    let snapshot = loop {
+        let candidate = entry.with_file_name(format!("{name}-snap-{n}"));
+        let candidate_name = candidate.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if layout.find(tempdirs, &candidate_name)?.is_none() && trash.find(&candidate_name)?.is_none() {
+            break candidate;
+        }
+        n += 1;
+    };
+
+    let meta = Meta::load(entry)?;
+    if meta.persistent(entry) {
+        trash::copy_dir(entry, &snapshot)?;
+    } else {
+        let target = backing.create()?;
+        // copy_dir wants to create the directory itself
+        std::fs::remove_dir(&target).wrap_err(format!("remove {target:?}"))?;
+        trash::copy_dir(&std::fs::read_link(entry).wrap_err("read link")?, &target)?;
+        platform::link_dir(&target, &snapshot).wrap_err("create symlink")?;
+    }
+
+    Meta {
+        created: Some(age::now()),
+        last_used: None,
+        host: meta::hostname(),
+        ..meta
+    }.save(&snapshot)?;
+    journal.record(Op::Create { entry: snapshot.clone(), origin: Some(entry.to_path_buf()) })?;
+
+    Ok(snapshot)
+}
+
+/// What's wrong if the tempdir at `entry` holds more than its `--max-size`, like `holds 2.1G, more than 500M`
+pub fn over_limit(entry: &Path, meta: &Meta) -> Option<String> {
+    let max = meta.max_size?;
+    let size = usage::size(entry);
+    (size > max).then(|| format!("holds {}, more than {}", usage::human_size(size), usage::human_size(max)))
+}
+
+/// Warn about every tempdir that holds more than its `--max-size`, returning them
+pub fn check_limits(tempdirs: &Path, layout: Layout) -> Result<Vec<PathBuf>> {
+    let mut res = Vec::new();
+    for i in layout.entries(tempdirs)? {
+        if let Some(message) = over_limit(&i, &Meta::load(&i)?) {
+            warn(Kind::OverLimit, format!("{} {message}", i.strip_prefix(tempdirs).unwrap_or(&i).to_string_lossy()));
+            res.push(i);
+        }
+    }
+    Ok(res)
+}
+
+/// Which tempdirs `t status` shows, and how
+pub struct Listing<'a> {
+    pub tag: Option<&'a str>,
+    pub filter: Option<&'a str>,
+    pub sort: SortBy,
+    pub reverse: bool,
+    /// show the disk usage of every tempdir
+    pub size: bool,
+    /// `max_total_size` from the config, to show the total against
+    pub max_total_size: Option<u64>,
+    /// hidden tempdirs to show after the others, for `t status --all`
+    pub hidden: &'a [registry::Record],
+    /// point out tempdirs that weren't used for longer than this
+    pub stale_after: Duration,
+}
+
+pub fn active_tempdirs(tempdirs: &Path, layout: Layout, home: &Path, listing: &Listing) -> Result<()> {
+    let mut found = Vec::new();
+    for i in layout.entries(tempdirs)? {
+        let meta = Meta::load(&i)?;
+        if listing.tag.is_some_and(|tag| !meta.has_tag(tag)) {
+            continue;
+        }
+        let name = i.strip_prefix(tempdirs).unwrap_or(&i).to_string_lossy().to_string();
+        if listing.filter.is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+
+        if let Some(mode) = read_link(&i).ok().and_then(|target| platform::loose_mode(&target)) {
+            warn(Kind::Permissions, format!("{name} ({mode:o}), `chmod 700` it unless that's on purpose"));
+        }
+        if let Some(message) = over_limit(&i, &meta) {
+            warn(Kind::OverLimit, format!("{name} {message}"));
+        }
+
+        found.push((i, meta));
+    }
+
+    let sizes = if listing.size || matches!(listing.sort, SortBy::Size) {
+        let paths: Vec<_> = found.iter().map(|(i, _)| i.clone()).collect();
+        usage::sizes(&paths).into_iter().map(Some).collect()
+    } else {
+        vec![None; found.len()]
+    };
+    let mut entries: Vec<_> = found.into_iter().zip(sizes).map(|((i, meta), size)| (i, meta, size)).collect();
+
+    match listing.sort {
+        // entries are already sorted by name
+        SortBy::Name => {}
+        SortBy::Age => {
+            let mut with_age = Vec::new();
+            for entry in entries {
+                with_age.push((age::age(&entry.0)?, entry));
+            }
+            with_age.sort_by_key(|(age, _)| std::cmp::Reverse(*age));
+            entries = with_age.into_iter().map(|(_, entry)| entry).collect();
+        }
+        SortBy::Size => entries.sort_by_key(|(_, _, size)| std::cmp::Reverse(*size)),
+    }
+    if listing.reverse {
+        entries.reverse();
+    }
+
+    let total: u64 = entries.iter().filter_map(|(_, _, size)| *size).sum();
+
+    if output::porcelain() {
+        for (i, meta, size) in entries {
+            status_record(tempdirs, &i, &meta, size)?;
+        }
+        if listing.tag.is_none() {
+            hidden_tempdirs(listing, &mut Table::new(&[]))?;
+        }
+        return Ok(());
+    }
+
+    let mut table = Table::new(&["name", "type", "age", "last used", "size", "from", "note", "tags"]);
+    let mut stale = 0;
+    for (i, meta, size) in entries {
+        let name = i.strip_prefix(tempdirs).unwrap_or(&i).to_string_lossy().to_string();
+        let (kind, mut color) = match (meta.persistent(&i), meta.pinned) {
+            _ if meta.foreign() => (format!("on {}", meta.host.as_deref().unwrap_or_default()), Some(Color::Dim)),
+            // `t status` doesn't clean these up, the next command that changes something does
+            _ if !i.exists() => ("files gone".to_string(), Some(Color::Red)),
+            (false, false) if durable(&i, home) => ("durable".to_string(), None),
+            (false, false) => ("ephemeral".to_string(), None),
+            (false, true) if durable(&i, home) => ("durable, pinned".to_string(), Some(Color::Green)),
+            (false, true) => ("pinned".to_string(), Some(Color::Green)),
+            (true, false) => ("persistent".to_string(), Some(Color::Blue)),
+            (true, true) => ("persistent, pinned".to_string(), Some(Color::Blue)),
+        };
+
+        let unused = match meta.last_used {
+            Some(last_used) => SystemTime::now().duration_since(age::from_unix(last_used)).unwrap_or_default(),
+            None => age::age(&i)?,
+        };
+        let mut last_used = match meta.last_used {
+            Some(_) => format!("{} ago", age::human(unused)),
+            None => "never".to_string(),
+        };
+        if i.exists() && !meta.foreign() && unused > listing.stale_after {
+            stale += 1;
+            last_used.push_str(" (stale)");
+            color = Some(Color::Yellow);
+        }
+
+        let size = match size {
+            Some(size) if listing.size => usage::human_size(size),
+            _ => String::new(),
+        };
+        let origin = meta.origin.as_deref().map(|i| config::abbreviate_home(i, home).to_string_lossy().to_string()).unwrap_or_default();
+        let note = meta.note.as_deref().map(|i| table::truncate(i, 40)).unwrap_or_default();
+
+        table.push(vec![name, kind, age::human(age::age(&i)?), last_used, size, origin, note, meta.tags.join(", ")], color);
+    }
+    // they have no tags to filter on
+    if listing.tag.is_none() {
+        hidden_tempdirs(listing, &mut table)?;
+    }
+
+    if table.is_empty() {
+        notice!("no active tempdirs");
+    } else {
+        for line in table.render(table::colors()) {
+            eprintln!("{line}");
+        }
+    }
+    if let (true, Some(max)) = (listing.size, listing.max_total_size) {
+        eprintln!("total: {} of {}", usage::human_size(total), usage::human_size(max));
+    } else if listing.size && !table.is_empty() {
+        eprintln!("total: {}", usage::human_size(total));
+    }
+    match stale {
+        0 => {}
+        1 => notice!("1 tempdir wasn't used in {}, `t gc --interactive` helps to clean it up", age::human(listing.stale_after)),
+        n => notice!("{n} tempdirs weren't used in {}, `t gc --interactive` helps to clean them up", age::human(listing.stale_after)),
+    }
+
+    Ok(())
+}
+
+/// The `--porcelain` line for the tempdir at `entry`: `tempdir`, its name, entry, backing directory,
+/// `ephemeral`/`persistent`/`stale`/`foreign`, `pinned` or nothing, when it was created and last used
+/// in seconds since the unix epoch, its size in bytes if known and its tags separated by commas
+fn status_record(tempdirs: &Path, entry: &Path, meta: &Meta, size: Option<u64>) -> Result<()> {
+    let name = entry.strip_prefix(tempdirs).unwrap_or(entry).to_string_lossy();
+    let backing = read_link(entry).unwrap_or_else(|_| entry.to_path_buf());
+    let state = if meta.foreign() {
+        "foreign"
+    } else if !entry.exists() {
+        "stale"
+    } else if meta.persistent(entry) {
+        "persistent"
+    } else {
+        "ephemeral"
+    };
+    let created = match meta.created {
+        Some(i) => i.to_string(),
+        None => age::to_unix(age::created(entry)?).to_string(),
+    };
+
+    output::record(&[
+        "tempdir",
+        &name,
+        &entry.to_string_lossy(),
+        &backing.to_string_lossy(),
+        state,
+        if meta.pinned { "pinned" } else { "" },
+        &created,
+        &meta.last_used.map(|i| i.to_string()).unwrap_or_default(),
+        &size.map(|i| i.to_string()).unwrap_or_default(),
+        &meta.tags.join(","),
+    ]);
+    Ok(())
+}
+
+/// The hidden tempdirs in `listing`, which go by the name of their backing directory.
+/// Printed as records with `--porcelain`, otherwise added to `table`
+fn hidden_tempdirs(listing: &Listing, table: &mut Table) -> Result<()> {
+    for record in listing.hidden {
+        let Some(ref backing) = record.backing else {
+            continue;
+        };
+        let name = backing.file_name().unwrap_or_default().to_string_lossy();
+        if listing.filter.is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+
+        if output::porcelain() {
+            // same fields as for other tempdirs, see `status_record`
+            let created = record.created.to_string();
+            let size = if listing.size { usage::size(backing).to_string() } else { String::new() };
+            let path = backing.to_string_lossy();
+            output::record(&["hidden", &name, &path, &path, "ephemeral", "", &created, "", &size, ""]);
+            continue;
+        }
+
+        let ago = SystemTime::now().duration_since(age::from_unix(record.created)).unwrap_or_default();
+        let size = if listing.size { usage::human_size(usage::size(backing)) } else { String::new() };
+        table.push(vec![name.to_string(), "hidden".to_string(), age::human(ago), String::new(), size], Some(Color::Dim));
+    }
+
+    Ok(())
+}
+
+/// The entry for `name` if given, otherwise the entry of the tempdir we're in (if any)
+pub fn named_or_current(tempdirs: &Path, layout: Layout, name: Option<String>, cwd: &Path, pwd: Option<&Path>) -> Result<Option<PathBuf>> {
+    let entry = match name {
+        Some(n) => Some(resolve_name(tempdirs, layout, &n)?),
+        None => in_tempdir(tempdirs, layout, cwd, pwd).wrap_err("in tempdir")?
+            .map(|i| tempdir_entry(tempdirs, layout, &i))
+            .transpose()?
+            .flatten(),
+    };
+
+    // whatever we're about to do with it counts as using it
+    if let Some(ref entry) = entry {
+        meta::touch(entry)?;
+    }
+    Ok(entry)
+}
+
+/// The entry for `name`, or the only one it's a prefix or fuzzy match of,
+/// or where it would be in the tempdirs root if there's none
+pub fn resolve_name(tempdirs: &Path, layout: Layout, name: &str) -> Result<PathBuf> {
+    if let Some(entry) = layout.find(tempdirs, name)? {
+        return Ok(entry);
+    }
+
+    // `t delete unna` for `unnamed_4`, as long as it's clear which one is meant
+    let mut candidates = layout.find_fuzzy(tempdirs, name)?;
+    match candidates.len() {
+        0 => Ok(tempdirs.join(name)),
+        1 => {
+            let entry = candidates.remove(0);
+            info!("{name:?} matches {entry:?}");
+            Ok(entry)
+        }
+        _ => {
+            let names: Vec<_> = candidates.iter().map(|i| i.strip_prefix(tempdirs).unwrap_or(i).to_string_lossy()).collect();
+            color_eyre::eyre::bail!("{name:?} could be any of {}", names.join(", "))
+        }
+    }
+}
+
+/// The backing directory of the hidden tempdir made for `tempdirs` that's called `name`.
+/// Hidden tempdirs have no entry, so they go by the name of their backing directory
+pub fn find_hidden(tempdirs: &Path, home: &Path, name: &str) -> Result<Option<PathBuf>> {
+    Ok(Registry::new(home)
+        .hidden(tempdirs)?
+        .into_iter()
+        .filter_map(|i| i.backing)
+        .find(|i| i.file_name().is_some_and(|i| i == name)))
+}
+
+/// The backing directories of hidden tempdirs made for `tempdirs` that were created longer ago than `older_than`,
+/// or whose `--ttl` ran out
+pub fn hidden_candidates(tempdirs: &Path, home: &Path, older_than: Duration) -> Result<Vec<PathBuf>> {
+    let cutoff = age::now().saturating_sub(older_than.as_secs());
+    Ok(Registry::new(home)
+        .hidden(tempdirs)?
+        .into_iter()
+        .filter(|i| i.created < cutoff || i.expires.is_some_and(|i| i <= age::now()))
+        .filter_map(|i| i.backing)
+        .collect())
+}
+
+/// Delete the hidden tempdir at `backing`, moving it to the trash
+pub fn delete_hidden(backing: &Path, trash: &Trash, home: &Path) -> Result<()> {
+    info!("deleting {backing:?} (hidden)");
+    trash.delete(backing)?;
+    release_backing(backing)?;
+    output::record(&["deleted", &backing.to_string_lossy(), &backing.to_string_lossy()]);
+    Registry::new(home).forget_hidden(backing)
+}
+
+/// The entry in the tempdirs root for a tempdir found by [`in_tempdir`],
+/// which might be the backing directory instead of the symlink to it.
+pub fn tempdir_entry(tempdirs: &Path, layout: Layout, tempdir: &Path) -> Result<Option<PathBuf>> {
+    if layout.entry_containing(tempdirs, tempdir).as_deref() == Some(tempdir) {
+        return Ok(Some(tempdir.to_path_buf()));
+    }
+
+    for i in layout.entries(tempdirs)? {
+        if i.is_symlink() && read_link(&i).wrap_err("read link")? == tempdir {
+            return Ok(Some(i));
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn in_tempdir(tempdirs: &Path, layout: Layout, cwd: &Path, pwd: Option<&Path>) -> Result<Option<PathBuf>> {
+    // backing dirs can be in the configured backing dir, the one of any profile, the system's temp dir
+    // or the durable dir, so look for their name instead of their location
+    fn backing_dir_of(path: &Path) -> Option<PathBuf> {
+        path.ancestors()
+            .find(|i| i.file_name().is_some_and(|i| i.to_string_lossy().starts_with(TEMPDIR_PREFIX)))
+            .map(Path::to_path_buf)
+    }
+
+    if let Some(pwd) = pwd {
+        if let Some(entry) = layout.entry_containing(tempdirs, pwd) {
+            trace!("$PWD {pwd:?} is in {entry:?} in the tempdirs root");
+            return Ok(Some(entry));
+        }
+
+        let canonical = pwd.canonicalize().wrap_err("canonicalize pwd")?;
+        if let Some(i) = backing_dir_of(pwd).or_else(|| backing_dir_of(&canonical)) {
+            trace!("$PWD {pwd:?} (really {canonical:?}) is in backing directory {i:?}");
+            return Ok(Some(i));
+        }
+        trace!("$PWD {pwd:?} isn't in a tempdir");
+    }
+
+    // the current dir is always the real path, which only goes through the tempdirs root for persistent tempdirs
+    if let Some(entry) = layout.entry_containing(tempdirs, cwd) {
+        trace!("the current dir {cwd:?} is in {entry:?} in the tempdirs root");
+        return Ok(Some(entry));
+    }
+    let res = backing_dir_of(cwd);
+    match res {
+        Some(ref i) => trace!("the current dir {cwd:?} is in backing directory {i:?}"),
+        None => trace!("the current dir {cwd:?} isn't in a tempdir either"),
+    }
+    Ok(res)
+}
+
+/// The non-persistent tempdirs that bulk deletes apply to. Pinned tempdirs are left out.
+pub fn bulk_candidates(tempdirs: &Path, layout: Layout, older_than: Option<Duration>, tag: Option<&str>) -> Result<Vec<PathBuf>> {
+    let mut res = Vec::new();
+    let live = sessions::live(tempdirs)?;
+    for i in layout.entries(tempdirs)? {
+        // bulk deletes only unlink symlinks, real directories have to be deleted one by one
+        if !i.is_symlink() {
+            continue;
+        }
+        if live.contains(&i) {
+            info!("skipping {i:?} because a `t shell` session is using it");
+            continue;
+        }
+        let meta = Meta::load(&i)?;
+        if let Some(older_than) = older_than {
+            let age = age::age(&i)?;
+            if age < older_than && !meta.expired() {
+                trace!("leaving {i:?} alone, it's only {} old", age::human(age));
+                continue;
+            }
+            if meta.expired() {
+                debug!("{i:?} is expired, its --ttl ran out");
+            } else {
+                debug!("{i:?} is {} old, which is older than {}", age::human(age), age::human(older_than));
+            }
+        }
+
+        if tag.is_some_and(|tag| !meta.has_tag(tag)) {
+            continue;
+        }
+        if meta.persistent(&i) || meta.foreign() {
+            trace!("leaving {i:?} alone, it's persistent or from another machine");
+            continue;
+        }
+        if meta.pinned {
+            info!("skipping {i:?} because it's pinned");
+            continue;
+        }
+
+        res.push(i);
+    }
+
+    Ok(res)
+}
+
+pub fn delete_all(tempdirs: &Path, layout: Layout, home: &Path, older_than: Option<Duration>, tag: Option<&str>, hooks: &Hooks, dry_run: bool) -> Result<PathBuf> {
+    if dry_run {
+        for i in bulk_candidates(tempdirs, layout, older_than, tag)? {
+            would_delete(tempdirs, &i);
+        }
+        return Ok(tempdirs.to_path_buf());
+    }
+
+    let started = age::now();
+    let _lock = Lock::acquire(tempdirs)?;
+
+    let journal = Journal::new(tempdirs);
+    for i in bulk_candidates(tempdirs, layout, older_than, tag)? {
+        // created by another t-rs while we were waiting for the lock
+        if age::to_unix(age::created(&i)?) >= started {
+            continue;
+        }
+
+        hooks.run(Hook::Delete, &i)?;
+        let backing = read_link(&i).wrap_err("read link")?;
+        symlink::remove_symlink_auto(&i).wrap_err(format!("remove symlink {:?}", i))?;
+        release_backing(&backing)?;
+        // the OS cleans up the system's temp dir, but nothing else cleans up the durable dir
+        if config::is_durable(&backing, home) && backing.exists() {
+            std::fs::remove_dir_all(&backing).wrap_err(format!("remove {backing:?}"))?;
+        }
+        meta::remove(&i)?;
+        zoxide::remove(&i);
+        info!("deleting {:?}", i);
+        output::record(&["deleted", &i.to_string_lossy(), &backing.to_string_lossy()]);
+        journal.record(Op::Delete { entry: i, backing: Some(backing) })?;
+    }
+
+    layout.tidy(tempdirs)?;
+
+    Ok(tempdirs.to_path_buf())
+}
+
+/// Say what deleting `entry` would remove, for `--dry-run`
+pub fn would_delete(tempdirs: &Path, entry: &Path) {
+    let name = entry.strip_prefix(tempdirs).unwrap_or(entry);
+    let size = usage::human_size(usage::size(entry));
+    match read_link(entry) {
+        Ok(backing) => notice!("would delete {name:?} -> {backing:?} ({size})"),
+        // hidden tempdirs aren't in the tempdirs root
+        Err(_) if !entry.starts_with(tempdirs) => notice!("would delete {entry:?} (hidden, {size})"),
+        Err(_) => notice!("would delete {name:?} (persistent, {size})"),
+    }
+}
+
+/// Where the backing directories of new tempdirs go
+#[derive(Clone)]
+pub struct Backing {
+    pub dir: PathBuf,
+    /// put every backing directory on its own tmpfs in `dir`
+    pub tmpfs: bool,
+    /// the size limit of those tmpfses
+    pub size: Option<String>,
+    /// the permissions of new backing directories, `0o700` unless configured otherwise
+    pub mode: u32,
+    /// put every backing directory in its own encrypted store in `dir`, for `t secret`
+    pub secret: Option<secret::Key>,
+}
+
+impl Backing {
+    /// Make a new backing directory
+    pub fn create(&self) -> Result<PathBuf> {
+        let parent = if let Some(key) = self.secret {
+            secret::mount(&self.dir, key)?
+        } else if self.tmpfs {
+            #[cfg(target_os = "linux")]
+            let parent = tmpfs::mount(&self.dir, self.size.as_deref())?;
+            #[cfg(not(target_os = "linux"))]
+            color_eyre::eyre::bail!("--tmpfs only works on Linux");
+            #[cfg(target_os = "linux")]
+            parent
+        } else {
+            std::fs::create_dir_all(&self.dir).wrap_err(format!("create {:?}", self.dir))?;
+            self.dir.clone()
+        };
+
+        let dir = tempdir::TempDir::new_in(parent, TEMPDIR_PREFIX).wrap_err("create temp dir")?.into_path();
+        // the system's temp dir is usually readable by everyone, but what's in tempdirs is nobody else's business
+        platform::set_mode(&dir, self.mode).wrap_err(format!("set permissions of {dir:?}"))?;
+        Ok(dir)
+    }
+}
+
+/// Whether the tempdir at `entry` has its backing directory in the [`config::durable_dir`], so it survives reboots
+pub fn durable(entry: &Path, home: &Path) -> bool {
+    read_link(entry).is_ok_and(|i| config::is_durable(&i, home))
+}
+
+/// Unmount the tmpfs or encrypted store the backing directory `backing` was on (and so remove it), if it was on one
+pub fn release_backing(backing: &Path) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    tmpfs::release(backing)?;
+
+    secret::release(backing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_script_unsets_outside_tempdirs() {
+        assert_eq!(env_script(None).unwrap(), "unset T_NAME T_DIR T_PERSISTENT T_EPHEMERAL\n");
+
+        let dir = tempdir::TempDir::new("t-rs-test-env").unwrap();
+        let entry = dir.path().join("it's");
+        std::fs::create_dir(&entry).unwrap();
+        let script = env_script(Some(&entry)).unwrap();
+        assert!(script.starts_with("export T_NAME='it'\\''s'\n"));
+        assert!(script.contains("export T_PERSISTENT='1'\n"));
+    }
+
+    #[test]
+    fn gc_removes_durable_backing_dirs() {
+        let home = tempdir::TempDir::new("t-rs-test-durable").unwrap();
+        let tempdirs = home.path().join("tempdirs");
+        std::fs::create_dir_all(&tempdirs).unwrap();
+
+        let durable = Backing { dir: config::durable_dir(home.path()), tmpfs: false, size: None, mode: 0o700, secret: None };
+        let backing = durable.create().unwrap();
+        let entry = tempdirs.join("old");
+        platform::link_dir(&backing, &entry).unwrap();
+        // `delete_all` leaves tempdirs created in the same second alone, they might be another t-rs' new ones
+        Meta { created: Some(age::now() - 60), ..Default::default() }.save(&entry).unwrap();
+
+        delete_all(&tempdirs, Layout::Flat, home.path(), Some(Duration::ZERO), None, &Hooks::default(), false).unwrap();
+        assert!(!entry.is_symlink());
+        assert!(!backing.exists());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use crate::{notice, STATE_DIR};
+
+const LOCK: &str = "lock";
+
+/// How long to wait for another t-rs to let go of the lock, set once from the config
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// How often to check whether the lock is free while waiting
+const POLL: Duration = Duration::from_millis(100);
+
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+fn timeout() -> Duration {
+    TIMEOUT.get().copied().unwrap_or(Duration::from_secs(10))
+}
+
+/// An exclusive lock on the tempdirs root, held until it's dropped.
+///
+/// Only t-rs itself looks at it, so it just keeps concurrent invocations from stepping on each other.
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Take the lock on `tempdirs`, waiting for whoever has it now until the timeout
+    pub fn acquire(tempdirs: &Path) -> Result<Self> {
+        let dir = tempdirs.join(STATE_DIR);
+        std::fs::create_dir_all(&dir).wrap_err(format!("create {dir:?}"))?;
+
+        let path = dir.join(LOCK);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .wrap_err(format!("open lock file {path:?}"))?;
+
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) if started.elapsed() >= timeout() => {
+                    let mut holder = String::new();
+                    let _ = file.read_to_string(&mut holder);
+                    let holder = match holder.trim() {
+                        "" => "another t-rs".to_string(),
+                        pid => format!("another t-rs (pid {pid})"),
+                    };
+                    bail!(
+                        "{holder} has been busy with the tempdirs for over {}s, try again when it's done. \
+                         If it's stuck, stop it; {path:?} is unlocked as soon as it exits",
+                        timeout().as_secs(),
+                    );
+                }
+                Err(TryLockError::WouldBlock) => {
+                    if !waiting {
+                        notice!("waiting for another t-rs to finish");
+                        waiting = true;
+                    }
+                    std::thread::sleep(POLL);
+                }
+                Err(TryLockError::Error(e)) => return Err(e).wrap_err(format!("lock {path:?}")),
+            }
+        }
+
+        // so whoever times out waiting can say who they were waiting for
+        file.set_len(0).wrap_err(format!("truncate {path:?}"))?;
+        file.rewind().wrap_err(format!("rewind {path:?}"))?;
+        write!(file, "{}", std::process::id()).wrap_err(format!("write {path:?}"))?;
+
+        Ok(Self { _file: file })
+    }
+}
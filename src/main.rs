@@ -1,24 +1,41 @@
 use std::default::Default;
-use std::env::VarError;
-use std::fs::read_link;
+use std::io::IsTerminal;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
-use std::process::{Command, exit};
-use std::time::SystemTime;
-use clap::{Parser, Subcommand};
+use std::process::exit;
+use std::time::{Duration, SystemTime};
+use clap::{CommandFactory, Parser, Subcommand};
 use color_eyre::eyre::{Context, ContextCompat};
 use color_eyre::Result;
-use fs_extra::dir::CopyOptions;
-
-const TEMPDIR_PREFIX: &str = "T-RS-TEMPDIR";
-const TEMPDIRS: &str = "tempdirs";
+use t_rs::builder::{self, Created, OnCollision};
+use t_rs::cache::Cache;
+use t_rs::config::Config;
+use t_rs::journal::{Journal, Op};
+use t_rs::layout::Layout;
+use t_rs::lock;
+use t_rs::meta::Meta;
+use t_rs::pipeline::{Pipeline, ShellOptions};
+use t_rs::registry::Registry;
+use t_rs::trash::Trash;
+use t_rs::TempdirManager;
+use t_rs::warnings::{warn, Kind, LogFormat};
+#[cfg(target_os = "linux")]
+use t_rs::tmpfs;
+use t_rs::{
+    active_tempdirs, age, archive, backup, bulk_candidates, check_limits, cleanup, clipboard, compare, config, daemon, delete_all,
+    debug, delete_hidden, discard, doctor, env_script, downloads, error, find, find_hidden, info, log_only, gc, hidden_candidates, history, in_tempdir, integration::{self, Shell}, manpages, meta, named_or_current, naming, notice, notify, orphans, output, git_init,
+    platform, promote, promote_target, prompt, push, run_in_session, secret, sessions, setup, share, shell_command, snapshot, stats, tasks,
+    tempdir_entry, exit::{self, Code}, timer, tmux, top, trash, ui, update, usage, warnings, watch, would_delete, zoxide, Backing, Listing, SortBy, TEMPDIR_PREFIX,
+};
 
 /// Usage:
 ///
 /// Put the following in your bashrc or zshrc file.
 ///
-/// `function t() { cd $(t-rs $@ | tail -n 1) }`
+/// `function t() { local f="$(mktemp)"; T_RS_WRAPPER=3 T_RS_CD_FILE="$f" t-rs "$@"; cd "$(cat "$f")"; rm -f "$f" }`
 ///
-/// Then use the `t` command.
+/// Then use the `t` command. `t-rs check-integration` checks that this works.
+/// Older wrappers that cd into the last line t-rs prints on stdout keep working.
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Cli {
@@ -32,6 +49,89 @@ struct Cli {
     #[clap(long, env)]
     tempdirs: Option<PathBuf>,
 
+    /// Use the tempdirs of this profile, configured under `[profiles.<name>]` in the config.
+    /// Ignored when `--tempdirs` is given
+    #[clap(long, env = "T_RS_PROFILE")]
+    profile: Option<String>,
+
+    /// Where to put the backing directories of new tempdirs, instead of the system's temp dir.
+    /// Can also be set with `backing_dir` in the config
+    #[clap(long, env = "T_RS_BACKING_DIR")]
+    backing_dir: Option<PathBuf>,
+
+    /// Put the backing directory of a new tempdir in `~/.cache/t-rs/dirs` instead of the system's temp dir,
+    /// so it survives reboots. `t gc` still deletes it like any other tempdir
+    #[clap(long, conflicts_with_all = ["backing_dir", "tmpfs"])]
+    durable: bool,
+
+    /// Put a new tempdir on its own tmpfs, so its contents never hit the disk (Linux only, needs sudo)
+    #[clap(long)]
+    tmpfs: bool,
+
+    /// The most a tmpfs tempdir can hold, like `2G` or `50%` (of memory)
+    #[clap(long, requires = "tmpfs")]
+    size: Option<String>,
+
+    /// The permissions of the backing directory of a new tempdir in octal, like `750`.
+    /// `700` by default, or `backing_mode` from the config
+    #[clap(long, value_parser = platform::parse_mode)]
+    mode: Option<u32>,
+
+    /// Let `t gc` delete the new tempdir once it's older than this (like `2h`), even if `gc_older_than` is longer
+    #[clap(long, value_parser = age::parse_duration)]
+    ttl: Option<Duration>,
+
+    /// Point out the new tempdir in `t status` and `t gc` once it holds more than this, like `500M`
+    #[clap(long, value_parser = usage::parse_size)]
+    max_size: Option<u64>,
+
+    /// Give a new tempdir a `.envrc` exporting `T_NAME` and `T_DIR` (and `direnv_snippets` from the config),
+    /// and `direnv allow` it. Can also be set with `direnv = true` in the config
+    #[clap(long)]
+    direnv: bool,
+
+    /// If a tempdir with the name exists already, add `-2`, `-3`, ... to the name of the new one.
+    /// Can also be set with `unique_names = true` in the config, except for a bare `t foo`
+    #[clap(long, conflicts_with = "force")]
+    unique: bool,
+
+    /// If a tempdir with the name exists already, cd into it instead of creating a new one
+    #[clap(long)]
+    force: bool,
+
+    /// How to answer questions when stdin isn't a terminal: `yes`, `no` (the default), or `fail` with an error
+    #[clap(long, value_enum, env = "T_RS_ASSUME", default_value_t)]
+    assume: prompt::Assume,
+
+    /// Don't ask before deleting several tempdirs at once or a persistent one.
+    /// Can also be set with `skip_confirmations = true` in the config
+    #[clap(long, short)]
+    yes: bool,
+
+    /// Explain why things happen, or with `-vv` every step along the way, like how the current tempdir is found
+    #[clap(long, short, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print what was asked for, warnings and errors, not what's happening along the way
+    #[clap(long, short)]
+    quiet: bool,
+
+    /// Like `--quiet`, and print tab-separated lines on stdout for scripts: one per tempdir for `t status`,
+    /// and one for every tempdir that's created or deleted. See the README for the fields
+    #[clap(long)]
+    porcelain: bool,
+
+    /// How to print the warnings collected while running, at the end
+    #[clap(long, value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// The group to put a new tempdir in, with `layout = "by-group"` in the config
+    #[clap(long, short)]
+    group: Option<String>,
+
+    #[command(flatten)]
+    pipeline: Pipeline,
+
     #[command(subcommand)]
     command: Option<CliCommand>,
 }
@@ -42,7 +142,52 @@ enum CliCommand {
     /// will also persist `t shell` sessions
     Persist {
         /// the name of the dir to persist (you can also use the top-level name argument or by being in a tempdir)
-        name: Option<String>
+        name: Option<String>,
+
+        /// leave out everything excluded by `.gitignore` files in the tempdir (like `git archive`).
+        /// Can be made the default with `persist_gitignore = true` in the config
+        #[arg(long)]
+        gitignore: bool,
+
+        /// persist a backing directory (`/tmp/T-RS-TEMPDIR...`) directly, like the ones created by `t hidden`.
+        /// It's moved into the tempdirs root under the given name, or a generated one
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// only keep files matching these space-separated globs (like `'src/** Cargo.toml'`), deleting the rest
+        #[arg(long)]
+        only: Option<String>,
+    },
+
+    /// Turn an existing directory into a tempdir, so status, gc and delete treat it like any other.
+    /// It's moved to where new tempdirs go, and named after itself unless a name is given
+    Adopt {
+        dir: PathBuf,
+
+        /// Leave the directory where it is, and only link to it from the tempdirs root
+        #[arg(long)]
+        symlink: bool,
+    },
+
+    /// Create a temporary directory with what's on the clipboard in `clipboard.txt` or `clipboard.png`
+    Paste,
+
+    /// Create a temporary directory with what's piped into t in a file, like `curl ... | t pipe data.json`
+    Pipe {
+        /// The file to write it to
+        #[arg(default_value = "stdin.txt")]
+        filename: PathBuf,
+    },
+
+    /// Create a temporary directory with copies of these files or directories in it.
+    With {
+        /// The files or directories to copy in
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Move them instead of copying them
+        #[arg(long, short)]
+        r#move: bool,
     },
 
     /// Create a temporary directory with the most recently downloaded file copied into it.
@@ -53,14 +198,238 @@ enum CliCommand {
         /// Delete the original in the downloads directory
         #[arg(long, short)]
         r#move: bool,
+
+        /// Copy the N most recent downloads instead of just the most recent one
+        #[arg(long, short, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        last: Option<usize>,
+
+        /// Copy all downloads whose file name matches this glob (e.g. `*.pdf`)
+        #[arg(long, short)]
+        glob: Option<String>,
+
+        /// The directory to take downloads from.
+        /// Defaults to `downloads_dir` from the config, or the XDG download directory
+        #[arg(long, short)]
+        from: Option<PathBuf>,
+
+        /// If a download is still in progress, wait for it to finish first
+        #[arg(long, short)]
+        wait: bool,
+
+        /// Wait for a new download to appear and finish first, like after clicking a link in the browser
+        #[arg(long, conflicts_with_all = ["last", "choose"])]
+        watch: bool,
+
+        /// Give up waiting for a download with `--watch` or `--wait` after this long (like `5m`)
+        #[arg(long, value_parser = age::parse_duration)]
+        timeout: Option<Duration>,
+
+        /// Pick one of the 10 most recent downloads (matching `--glob`) instead of taking the most recent one
+        #[arg(long, short, conflicts_with = "last")]
+        choose: bool,
+
+        /// Download this url into the new tempdir instead of looking in the downloads directory
+        #[arg(long, short, conflicts_with_all = ["move", "last", "glob", "from", "wait", "choose", "watch"])]
+        url: Option<String>,
+
+        /// download the url again, even if it's in the download cache
+        #[arg(long, requires = "url")]
+        no_cache: bool,
+
+        /// name the tempdir after what's in the download (an archive's top-level directory,
+        /// a pdf's title or a zip's comment) instead of its file name, when possible
+        #[arg(long, conflicts_with = "name")]
+        name_from_content: bool,
     },
 
     /// don't show up in the list of tempdirs
     Hidden,
 
+    /// create a tempdir whose files are encrypted with gocryptfs before they hit the disk, and wiped when it's deleted
+    /// instead of going to the trash. For credential dumps, keys and other things that shouldn't linger
+    Secret {
+        /// encrypt with a passphrase you type instead of a random key that's forgotten right away,
+        /// so you can still get at the files with `gocryptfs` after a crash or reboot
+        #[arg(long)]
+        passphrase: bool,
+    },
+
+    /// Copy the most recent screenshot into a new tempdir named after it, and cd there
+    Shot {
+        /// The name of the new temporary directory. Defaults to the name of the screenshot
+        name: Option<String>,
+
+        /// Delete the original in the screenshots directory
+        #[arg(long, short)]
+        r#move: bool,
+
+        /// The directory to take screenshots from. Defaults to `screenshots_dir` from the config,
+        /// the screenshot location on macOS or `Screenshots` in the XDG pictures directory
+        #[arg(long, short)]
+        from: Option<PathBuf>,
+
+        /// Pick one of the 10 most recent screenshots instead of taking the most recent one
+        #[arg(long, short)]
+        choose: bool,
+    },
+
     /// Start a shell in a tempdir, deleting the tempdir when you leave the shell
-    /// (unless you use `t persist`)
-    Shell,
+    /// (unless you use `t persist`). `$T_NAME`, `$T_DIR` and `$T_EPHEMERAL` are set in it
+    Shell {
+        #[command(flatten)]
+        options: ShellOptions,
+
+        /// start a new tempdir and shell even when already in a `t shell`
+        #[arg(long)]
+        force_new: bool,
+    },
+
+    /// open a tempdir in a new tmux window or session named after it, instead of cding into it.
+    /// Like `t <name>` it goes to the tempdir if it exists and creates it otherwise
+    Tmux {
+        /// the tempdir to open
+        name: Option<String>,
+
+        /// what to open it in when already inside tmux, `tmux` in the config by default.
+        /// Outside tmux it's always a new session
+        #[arg(long, value_enum)]
+        target: Option<tmux::Target>,
+    },
+
+    /// copy the current or specified tempdir to a new one (like `foo-snap-1`), before trying something destructive
+    Snapshot {
+        /// the tempdir to copy (the current one if not given)
+        name: Option<String>,
+    },
+
+    /// pack the current or specified tempdir into `<name>.tar.zst` in the archive directory
+    /// (`archive_dir` in the config), to bring it back later with `t unarchive`
+    Archive {
+        /// the tempdir to archive (the current one if not given)
+        name: Option<String>,
+
+        /// delete the tempdir once it's archived
+        #[arg(long)]
+        prune: bool,
+    },
+
+    /// pack every tempdir, with its metadata, into `file` (like `tempdirs.tar.zst`), to bring them back
+    /// on another machine with `t restore-backup`
+    Backup {
+        file: PathBuf,
+    },
+
+    /// bring back the tempdirs in a backup made by `t backup`, skipping ones that exist already
+    RestoreBackup {
+        file: PathBuf,
+    },
+
+    /// create a tempdir from an archive made by `t archive`, named after the archive unless a name is given
+    Unarchive {
+        file: PathBuf,
+    },
+
+    /// turn a tempdir into a real project: move it to `dest` and remove it from the tempdirs,
+    /// like `t promote experiment ~/projects`
+    Promote {
+        /// the tempdir to promote (the current one if not given)
+        #[arg(long, short)]
+        name: Option<String>,
+
+        /// where to move it, or with two arguments, the tempdir to promote.
+        /// If the destination is an existing directory, the tempdir is moved into it
+        name_or_dest: PathBuf,
+
+        /// where to move the tempdir given as the first argument
+        dest: Option<PathBuf>,
+
+        /// run `git init` in the promoted directory and commit everything in it
+        #[arg(long)]
+        git: bool,
+    },
+
+    /// run a command in a new tempdir, and delete the tempdir when it's done,
+    /// like `t run -- 'cargo init && cargo add serde'`
+    Run {
+        /// don't delete the tempdir afterwards
+        #[arg(long)]
+        keep: bool,
+
+        /// don't delete the tempdir if the command fails
+        #[arg(long)]
+        keep_on_failure: bool,
+
+        /// the command, run by your shell
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// create several empty tempdirs at once, like `exp-1` to `exp-3` for `t empty -n 3 --prefix exp`,
+    /// and print their paths (on stdout, before the directory to cd into)
+    Empty {
+        /// how many to create
+        #[arg(long, short, default_value_t = 1)]
+        n: usize,
+
+        /// what to call them, followed by a number
+        #[arg(long, short, default_value = "scratch")]
+        prefix: String,
+    },
+
+    /// run a task from the justfile, Makefile or package.json in the current or specified tempdir.
+    /// Without a task, runs the last one again (or the default one)
+    Task {
+        task: Option<String>,
+
+        /// passed on to the task
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// open the current or specified tempdir in `$VISUAL` or `$EDITOR` (or `editor` from the config).
+    /// Like `t <name>` it creates the tempdir if it doesn't exist, or a new one when not in a tempdir
+    Edit {
+        name: Option<String>,
+    },
+
+    /// open the current or specified tempdir in the file manager
+    Open {
+        name: Option<String>,
+    },
+
+    /// serve the current or specified tempdir over HTTP, to get files onto another device. Stops with ctrl-c
+    Share {
+        name: Option<String>,
+
+        /// the port to listen on, a random free one by default
+        #[arg(long, short, default_value_t = 0)]
+        port: u16,
+
+        /// also accept files uploaded with `PUT`, like `curl -T file <url>`
+        #[arg(long)]
+        upload: bool,
+    },
+
+    /// copy a tempdir to another machine, like `t push foo me@server:scratch` (to `scratch/foo`).
+    /// Without a target, it goes where the tempdir was pushed last
+    Push {
+        /// the tempdir to push (the current one if not given), or with one argument, where to push to
+        name_or_target: Option<String>,
+
+        /// where to push to, like `user@host:path`
+        target: Option<String>,
+    },
+
+    /// run a command in the current or specified tempdir, like `t exec --watch -- cargo test`
+    Exec {
+        /// run the command again whenever files in the tempdir change
+        #[arg(long, short)]
+        watch: bool,
+
+        /// the command, run by your shell
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
 
     /// rename the current or specified tempdir
     Rename {
@@ -75,494 +444,2203 @@ enum CliCommand {
         #[arg(long, short)]
         all: bool,
 
-        /// the name of the dir to delete (you can also use the top-level name argument or by being in a tempdir)
+        /// delete all *non-persistent* directories created longer ago than this (like `7d` or `12h`)
+        #[arg(long, value_parser = age::parse_duration)]
+        older_than: Option<Duration>,
+
+        /// delete all *non-persistent* directories with this tag, or only those of `--older-than`
+        #[arg(long, short, conflicts_with = "names")]
+        tag: Option<String>,
+
+        /// the names of the dirs to delete, or glob patterns like `'exp-*'`
+        /// (you can also use the top-level name argument or by being in a tempdir)
+        names: Vec<String>,
+
+        /// only show what would be deleted, with where its files are and how big it is
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// delete *non-persistent* tempdirs that are older than `gc_older_than` from the config (default 7 days).
+    /// Also empties old things from the trash and the download cache
+    Gc {
+        /// delete tempdirs created longer ago than this instead (like `7d` or `12h`)
+        #[arg(long, value_parser = age::parse_duration)]
+        older_than: Option<Duration>,
+
+        /// only delete tempdirs with this tag
+        #[arg(long, short)]
+        tag: Option<String>,
+
+        /// go through the tempdirs one by one, choosing which to delete.
+        /// The ones you keep are pinned, so they won't come up again
+        #[arg(long, short)]
+        interactive: bool,
+
+        /// remove all cached downloads of `t dl --url`, not just the ones that haven't been used for a while
+        #[arg(long)]
+        clear_cache: bool,
+
+        /// only show what would be deleted, without deleting anything
+        #[arg(long, conflicts_with = "interactive")]
+        dry_run: bool,
+    },
+
+    /// have the OS run `t gc` regularly, with a systemd user timer or a launchd agent on macOS
+    InstallTimer {
+        /// what `t gc` deletes: tempdirs created longer ago than this (like `7d`)
+        #[arg(long, value_parser = age::parse_duration, default_value = "7d")]
+        older_than: Duration,
+
+        /// how often to run it (like `12h`)
+        #[arg(long, value_parser = age::parse_duration, default_value = "1d")]
+        every: Duration,
+
+        /// remove the timer again
+        #[arg(long)]
+        uninstall: bool,
+    },
+
+    /// keep cleaning up in the background: remove stale symlinks, delete expired tempdirs
+    /// and the least recently used ones when they're over `max_total_size`
+    Daemon {
+        /// clean up at least this often (like `5m`), besides whenever the tempdirs root changes
+        #[arg(long, value_parser = age::parse_duration, default_value = "1m")]
+        interval: Duration,
+    },
+
+    /// delete empty tempdirs, which are usually left over from running `t` by accident
+    PruneEmpty {
+        /// only delete ones created longer ago than this (like `30m`).
+        /// Defaults to `prune_empty_after` from the config, or an hour
+        #[arg(long, value_parser = age::parse_duration)]
+        older_than: Option<Duration>,
+
+        /// only show which tempdirs would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// list backing directories that no tempdir points to anymore, like after removing a symlink by hand
+    Orphans {
+        /// make them tempdirs again, with new names
+        #[arg(long, conflicts_with = "purge")]
+        relink: bool,
+
+        /// delete them
+        #[arg(long)]
+        purge: bool,
+    },
+
+    /// add tags to a tempdir, to filter on in `t status`, `t gc` and `t delete`.
+    /// Without tags, print the tags of the tempdir
+    Tag {
+        /// the name of the tempdir
+        name: String,
+
+        /// comma separated list of tags, like `work,experiment`
+        tags: Option<String>,
+
+        /// remove these tags instead of adding them
+        #[arg(long, short)]
+        remove: bool,
+    },
+
+    /// attach a note to the current or specified tempdir, shown in `t status`.
+    /// Without any text, print the current note
+    Note {
+        /// the name of the tempdir, or the text of the note if you're in a tempdir
+        name_or_text: Option<String>,
+
+        /// the text of the note
+        text: Option<String>,
+
+        /// remove the note
+        #[arg(long, short)]
+        clear: bool,
+    },
+
+    /// environment variables for the current or specified tempdir, set in `t shell`
+    Env {
+        /// the tempdir (you can also use the top-level name argument or by being in a tempdir)
+        #[arg(long, short)]
+        name: Option<String>,
+
+        #[command(subcommand)]
+        command: Option<EnvCommand>,
+    },
+
+    /// protect the current or specified tempdir from bulk deletes like `t delete --all` and `t gc`
+    Pin {
+        /// the tempdir (you can also use the top-level name argument or by being in a tempdir)
+        name: Option<String>,
+    },
+
+    /// let bulk deletes delete the current or specified tempdir again
+    Unpin {
+        /// the tempdir (you can also use the top-level name argument or by being in a tempdir)
         name: Option<String>,
     },
 
+    /// compare two tempdirs, listing added, removed and changed files
+    #[clap(alias = "diff")]
+    Compare {
+        /// the tempdir to compare from
+        a: String,
+        /// the tempdir to compare to
+        b: String,
+
+        /// also show unified diffs of changed text files
+        #[arg(long, short)]
+        content: bool,
+
+        /// show changed files with this command instead, like `difft` or `diff -u`.
+        /// `differ` from the config by default, with `--content`
+        #[arg(long)]
+        differ: Option<String>,
+    },
+
+    /// interactively pick a tempdir to jump to
+    Pick,
+
+    /// go back to the directory the tempdir you're in was created from
+    Back,
+
+    /// jump to the most recently used tempdir
+    Last {
+        /// jump to the Nth most recently used one instead
+        #[arg(default_value_t = 1)]
+        n: usize,
+    },
+
+    /// manage tempdirs in a full-screen interface.
+    /// Press enter to leave and cd into the highlighted tempdir
+    Ui,
+
+    /// see which tempdirs take up the most space, and mark the ones to delete.
+    /// This also deletes the backing directories of non-persistent tempdirs
+    Top,
+
+    /// bring back a deleted tempdir. Without a name, list what's in the trash
+    Restore {
+        name: Option<String>,
+    },
+
+    /// reverse the most recent create, rename, persist or delete
+    Undo,
+
+    /// show what t-rs has done: which tempdirs were created, renamed, persisted and deleted, and when
+    History {
+        /// only show the most recent N operations
+        #[arg(long, short)]
+        limit: Option<usize>,
+
+        /// print the history as json (on stdout, before the directory to cd into)
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// move the config and templates between machines
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// check that the `t` shell function is set up, and show how to set it up if it isn't
+    CheckIntegration,
+
+    /// check for common problems with how t-rs is set up, and say how to fix them
+    Doctor,
+
+    /// set up t-rs: where tempdirs go, how they're named and the `t` shell function.
+    /// Runs by itself the first time you use t-rs
+    Setup,
+
+    /// print the `t` shell function, like `t-rs init powershell | Out-String | Invoke-Expression` in your profile
+    Init {
+        /// the shell to print it for, the one you're using by default
+        #[arg(value_enum)]
+        shell: Option<Shell>,
+    },
+
+    /// list the names of tempdirs, for shell completion
+    #[command(name = "__complete", hide = true)]
+    Complete,
+
+    /// replace t-rs with the latest release from GitHub, after checking its checksum
+    SelfUpdate {
+        /// only say whether there's a newer version
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// totals across all tempdirs: how many of each kind, how much space they use, how many were created lately
+    Stats {
+        /// print them as json (on stdout, before the directory to cd into)
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// write man pages and completion scripts into `dir`, for packaging
+    #[command(hide = true)]
+    Manpages {
+        dir: PathBuf,
+    },
+
+    /// print a short indicator like `[t:foo]` when in a tempdir, and nothing otherwise, for PS1 or starship.
+    /// Skips all housekeeping so it's fast enough to run on every prompt
+    Prompt {
+        /// what to print, with `{name}` replaced by the name of the tempdir
+        #[arg(long, default_value = "[t:{name}]")]
+        format: String,
+    },
+
+    /// search the names of files in all tempdirs for `pattern`, ignoring case.
+    /// Goes to the tempdir with matches if there's only one
+    Find {
+        pattern: String,
+
+        /// also search the contents of text files
+        #[arg(long, short)]
+        content: bool,
+    },
+
     /// info about the current tempdirs
     #[clap(alias = "s")]
     #[clap(alias = "list")]
     #[clap(alias = "l")]
     #[clap(alias = "ls")]
-    Status,
+    Status {
+        /// only show tempdirs with this tag
+        #[arg(long, short)]
+        tag: Option<String>,
+
+        /// only show tempdirs with this in their name
+        #[arg(long, short)]
+        filter: Option<String>,
+
+        /// what to sort the tempdirs by
+        #[arg(long, short, value_enum, default_value_t)]
+        sort: SortBy,
+
+        /// reverse the order
+        #[arg(long, short)]
+        reverse: bool,
+
+        /// don't show how much space tempdirs take up, which can be slow for huge ones
+        #[arg(long)]
+        no_size: bool,
+
+        /// also show hidden tempdirs, made with `t hidden`
+        #[arg(long, short)]
+        all: bool,
+
+        /// show the tempdirs that existed at this date (like `2023-07-14` or `3d` ago) and what happened to them since
+        #[arg(long, value_parser = age::parse_time)]
+        at: Option<u64>,
+    },
 }
 
-fn cleanup(tempdirs: &Path) -> Result<()> {
-    for i in std::fs::read_dir(tempdirs).wrap_err(format!("read {tempdirs:?}"))? {
-        let i = i.wrap_err("read direntry")?;
+#[derive(Subcommand, Debug)]
+enum EnvCommand {
+    /// set variables, like `t env set RUST_LOG=debug`
+    Set {
+        #[arg(required = true)]
+        vars: Vec<String>,
+    },
 
-        if i.path().is_symlink() && !read_link(i.path()).wrap_err("read link")?.exists() {
-            eprintln!("cleaning up stale symlink {:?}", i.path());
-            symlink::remove_symlink_auto(i.path()).wrap_err("remove symlink")?;
-        }
-    }
+    /// remove variables
+    Unset {
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
 
-    Ok(())
+    /// print the variables (the default)
+    List,
+
+    /// print `export` lines with `$T_NAME`, `$T_DIR`, `$T_PERSISTENT` and the variables of the tempdir you're in,
+    /// for `eval "$(t-rs env export)"` in shell hooks. Outside a tempdir it unsets them
+    Export,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// write the config file and all templates to a single file
+    Export {
+        file: PathBuf,
+    },
+
+    /// set up the config file and templates from a file written by `t config export`
+    Import {
+        file: PathBuf,
+
+        /// overwrite the existing config file and templates
+        #[arg(long, short)]
+        force: bool,
+    },
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
-
-    let args: Cli = match Cli::try_parse() {
-        Ok(i) => i,
+    output::init();
+
+    let pwd = std::env::var_os("PWD")
+        .filter(|pwd| !pwd.is_empty())
+        .map(PathBuf::from);
+    let cwd = std::env::current_dir();
+
+    // where the wrapper ends up when we don't have anywhere better to go,
+    // or when something went wrong halfway through a command.
+    let orig = pwd.clone()
+        .or_else(|| cwd.as_ref().ok().cloned())
+        .or_else(home::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let (go_to, code) = match Cli::try_parse() {
+        // the shell reads completions from stdout, so don't print a directory after them
+        Ok(args) if matches!(args.command, Some(CliCommand::Complete)) => {
+            exit(match complete(args) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            });
+        }
+        // neither after a prompt segment
+        Ok(args) if matches!(args.command, Some(CliCommand::Prompt { .. })) => {
+            let Some(CliCommand::Prompt { ref format }) = args.command else { unreachable!() };
+            let format = format.clone();
+            exit(match prompt_segment(args, &format, cwd.as_deref().ok(), pwd.as_deref()) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            });
+        }
+        // the shell evaluates what's printed, and it runs on every prompt so it shouldn't clean up either
+        Ok(args) if matches!(args.command, Some(CliCommand::Env { command: Some(EnvCommand::Export), .. })) => {
+            exit(match env_exports(args, cwd.as_deref().ok(), pwd.as_deref()) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            });
+        }
+        // not something to run through the wrapper, and it doesn't need a config
+        Ok(args) if matches!(args.command, Some(CliCommand::Manpages { .. })) => {
+            let Some(CliCommand::Manpages { dir }) = args.command else { unreachable!() };
+            manpages::generate(Cli::command(), &dir)?;
+            exit(0);
+        }
+        // the shell evaluates what's printed, so don't print a directory after it either
+        Ok(args) if matches!(args.command, Some(CliCommand::Init { .. })) => {
+            let Some(CliCommand::Init { shell }) = args.command else { unreachable!() };
+            match shell.or_else(Shell::detect) {
+                Some(shell) => {
+                    println!("{}", shell.snippet());
+                    exit(0);
+                }
+                None => {
+                    eprintln!("couldn't tell which shell you use, pass one of bash, zsh, fish or powershell");
+                    exit(1);
+                }
+            }
+        }
+        Ok(args) => {
+            let log_format = args.log_format;
+            prompt::set_assume(args.assume);
+            output::set_mode(match (args.quiet, args.porcelain) {
+                (_, true) => output::Mode::Porcelain,
+                (true, false) => output::Mode::Quiet,
+                (false, false) => output::Mode::Normal,
+            });
+            output::set_verbosity(args.verbose);
+            let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                let cwd = cwd.wrap_err("get current dir")?;
+                run(args, cwd, pwd)
+            }));
+
+            let res = match res {
+                Ok(Ok(go_to)) => (go_to, exit::code()),
+                Ok(Err(e)) => {
+                    log_only!("{e}");
+                    eprintln!("Error: {e:?}");
+                    (None, 1)
+                }
+                // the panic hook already reported what happened
+                Err(_) => (None, 101),
+            };
+            warnings::print_summary(log_format);
+            res
+        }
         Err(e) => {
             eprintln!("{}", e.render());
-            exit(e.exit_code());
+            (None, e.exit_code())
         }
     };
-    // let args = Cli::parse();
 
+    // the path printed here is where we will cd to after.
+    // this has to happen no matter what went wrong before,
+    // or the wrapper would try to cd into an error message
+    let go_to = go_to.unwrap_or(orig);
+    match std::env::var_os(integration::CD_FILE).filter(|i| !i.is_empty()) {
+        // newer wrappers don't read stdout, so commands can print whatever they want there
+        Some(file) => {
+            if let Err(e) = std::fs::write(&file, go_to.to_string_lossy().as_bytes()) {
+                error!("couldn't write the directory to cd into to {file:?}: {e}");
+            }
+        }
+        None => println!("\n\n{}", go_to.to_string_lossy()),
+    }
+    exit(code)
+}
+
+/// Print the names of all tempdirs, one per line
+fn complete(args: Cli) -> Result<()> {
     let home = home::home_dir()
         .wrap_err("couldn't get home directory")?;
-    let tempdirs = args.tempdirs.unwrap_or_else(|| home.join(TEMPDIRS));
+    let config = Config::load(&home)?;
+    let tempdirs = args.tempdirs.unwrap_or_else(|| config.tempdirs(&home, args.profile.as_deref()));
+    if !tempdirs.exists() {
+        return Ok(());
+    }
+
+    for i in config.layout.entries(&tempdirs)? {
+        if let Some(name) = i.file_name() {
+            println!("{}", name.to_string_lossy());
+        }
+    }
+
+    Ok(())
+}
+
+/// Print `format` with the name of the tempdir we're in, if any. This runs on every prompt,
+/// so it only reads the config and looks at the current directory
+fn prompt_segment(args: Cli, format: &str, cwd: Option<&Path>, pwd: Option<&Path>) -> Result<()> {
+    let home = home::home_dir()
+        .wrap_err("couldn't get home directory")?;
+    let config = Config::load(&home)?;
+    let tempdirs = args.tempdirs.unwrap_or_else(|| config.tempdirs(&home, args.profile.as_deref()));
+    let cwd = cwd.wrap_err("get current dir")?;
+
+    if let Some(entry) = in_tempdir(&tempdirs, config.layout, cwd, pwd)? {
+        let name = entry.strip_prefix(&tempdirs).unwrap_or(&entry).to_string_lossy().to_string();
+        println!("{}", format.replace("{name}", &name));
+    }
+    Ok(())
+}
+
+/// Print `export` lines for the environment of the tempdir we're in, or `unset` them when we're not in one
+fn env_exports(args: Cli, cwd: Option<&Path>, pwd: Option<&Path>) -> Result<()> {
+    let home = home::home_dir()
+        .wrap_err("couldn't get home directory")?;
+    let config = Config::load(&home)?;
+    let tempdirs = args.tempdirs.unwrap_or_else(|| config.tempdirs(&home, args.profile.as_deref()));
+    let cwd = cwd.wrap_err("get current dir")?;
+
+    let entry = match in_tempdir(&tempdirs, config.layout, cwd, pwd)? {
+        Some(dir) => Some(tempdir_entry(&tempdirs, config.layout, &dir)?.unwrap_or(dir)),
+        None => None,
+    };
+    print!("{}", env_script(entry.as_deref())?);
+    Ok(())
+}
+
+/// Run the command given on the command line, returning the directory to cd into
+fn run(mut args: Cli, cwd: PathBuf, pwd: Option<PathBuf>) -> Result<Option<PathBuf>> {
+    let home = home::home_dir()
+        .wrap_err("couldn't get home directory")?;
+    let mut config = Config::load(&home)?;
+    // `t tmux foo` and `t edit foo` create or go to foo like `t foo` does
+    if let Some(CliCommand::Tmux { ref mut name, .. } | CliCommand::Edit { ref mut name }) = args.command {
+        if name.is_some() {
+            args.name = name.take();
+        }
+    }
+    if config.log_file {
+        output::open_log(&home)?;
+    }
+    let tempdirs = args.tempdirs.unwrap_or_else(|| config.tempdirs(&home, args.profile.as_deref()));
+    debug!("running {:?} on {tempdirs:?}", std::env::args().collect::<Vec<_>>());
+
+    if matches!(args.command, Some(CliCommand::Setup)) {
+        setup::run(&home, &tempdirs)?;
+        return Ok(None);
+    }
+    // only ask when someone's there to answer
+    if setup::first_run(&home, &tempdirs) && prompt::can_ask() {
+        setup::run(&home, &tempdirs)?;
+        config = Config::load(&home)?;
+    }
+    let backing = Backing {
+        dir: match args.backing_dir {
+            Some(dir) => dir,
+            None if args.durable => config::durable_dir(&home),
+            None => config.backing_dir(&home, args.profile.as_deref()),
+        },
+        tmpfs: args.tmpfs,
+        size: args.size,
+        mode: match args.mode {
+            Some(mode) => mode,
+            None => config.backing_mode()?,
+        },
+        secret: None,
+    };
     if !tempdirs.exists() {
         std::fs::create_dir_all(&tempdirs)
             .wrap_err(format!("create tempdirs ({tempdirs:?})"))?;
     }
 
+    lock::set_timeout(config.lock_timeout()?);
+    if config.zoxide {
+        zoxide::set_command(config.zoxide_command.as_deref().unwrap_or("zoxide"));
+    }
+
+    let layout = config.layout;
+    let group = args.group.as_deref();
+    let trash = Trash::new(&home, &config)?;
+    let journal = Journal::new(&tempdirs);
+    let manager = TempdirManager::new(tempdirs.clone(), layout, backing.clone(), Trash::new(&home, &config)?, config.hooks.clone());
+
+    // commands that only look shouldn't have to wait for anything, so they skip the housekeeping
+    let read_only = matches!(args.command, Some(CliCommand::Status { .. } | CliCommand::History { .. } | CliCommand::Find { .. } | CliCommand::CheckIntegration | CliCommand::Doctor | CliCommand::SelfUpdate { .. } | CliCommand::Stats { .. }));
+
+    let yes = args.yes || config.skip_confirmations;
+    let dry_run = matches!(args.command, Some(CliCommand::Delete { dry_run: true, .. } | CliCommand::Gc { dry_run: true, .. } | CliCommand::PruneEmpty { dry_run: true, .. }));
+
     // first see if there are any stale symlinks (for after boot)
-    cleanup(&tempdirs)?;
+    if !read_only {
+        let removed = cleanup(&tempdirs, layout, &home, dry_run)?;
+        if config.notify_removals && !dry_run {
+            notify::removed(&tempdirs, &removed, "their files were gone, probably after a reboot", "Nothing to restore.");
+        }
+    }
+
+    let creating = matches!(args.command, None | Some(CliCommand::Shell { .. } | CliCommand::Hidden | CliCommand::Secret { .. } | CliCommand::Run { .. } | CliCommand::Dl { .. } | CliCommand::Shot { .. } | CliCommand::With { .. } | CliCommand::Pipe { .. } | CliCommand::Paste | CliCommand::Empty { .. } | CliCommand::Unarchive { .. } | CliCommand::Tmux { .. } | CliCommand::Edit { .. }));
+    let max_total_size = config.max_total_size()?;
+    if let (Some(max), false) = (max_total_size, read_only) {
+        check_quota(usage::total(&layout.entries(&tempdirs)?), max, creating)?;
+    }
 
     let name = args.name
         .clone()
         .map(Ok)
-        .unwrap_or_else(|| new_name(&tempdirs))?;
+        .unwrap_or_else(|| naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), None)))?;
+
+    let mut pipeline = args.pipeline;
+    let template = pipeline.template.take();
+    let scaffold_name = pipeline.scaffold.take();
+    let scaffold = match scaffold_name {
+        Some(ref name) => Some((name.as_str(), config.scaffold(name)?)),
+        None => None,
+    };
+    let origin = pwd.clone().unwrap_or_else(|| cwd.clone());
+    let direnv = (args.direnv || config.direnv).then_some(&config.direnv_snippets[..]);
+    // `t foo`, `t tmux foo` and `t edit foo` go to foo if it's there, everything else has to create a tempdir.
+    // A name given with `--in-shell` is still taken, that shell would delete it afterwards
+    let jumps = !pipeline.shell && matches!(args.command, None | Some(CliCommand::Tmux { .. } | CliCommand::Edit { .. }));
+    let on_collision = OnCollision::choose(args.force, args.unique, args.name.is_some(), config.unique_names, !jumps);
+    // these delete the tempdir afterwards, which shouldn't happen to one that was there already
+    let deleted_afterwards = pipeline.shell || matches!(args.command, Some(CliCommand::Shell { .. } | CliCommand::Run { .. }));
+    let new_tempdir = |name: &str| manager.new_tempdir(name)
+        .group(group)
+        .origin(&origin)
+        .ttl(args.ttl)
+        .max_size(args.max_size)
+        .template(template.as_deref(), &home)
+        .scaffold(scaffold)
+        .direnv(direnv)
+        .on_collision(on_collision)
+        .restore_from((!deleted_afterwards).then_some(&trash));
+
+    if args.force && deleted_afterwards {
+        color_eyre::eyre::bail!("--force can't be used to start a shell or run a command, the tempdir would be deleted afterwards");
+    }
+
+    if pipeline.shell && matches!(args.command, Some(CliCommand::Tmux { .. } | CliCommand::Edit { .. })) {
+        color_eyre::eyre::bail!("--in-shell can't be used with `t tmux` or `t edit`, they open the tempdir somewhere else");
+    }
+
+    // `t foo` goes to foo if it's there, and only creates it if it isn't. `t --unique foo` always creates one
+    let existing = match on_collision {
+        OnCollision::Fail if jumps => manager.find(&name)?,
+        _ => None,
+    };
+
+    let go_to: Option<PathBuf> = match args.command {
+        None => match existing {
+            Some(existing) => {
+                info!("{existing:?} exists already, cding into it");
+                Some(existing)
+            }
+            None => {
+                let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                    return Ok(None);
+                };
+                pipeline.run(&tempdirs, &config.hooks, res)?
+            }
+        },
+        Some(CliCommand::Tmux { target, .. }) => {
+            let dir = match existing {
+                Some(existing) => existing,
+                None => {
+                    let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                        return Ok(None);
+                    };
+                    pipeline.run(&tempdirs, &config.hooks, res)?.unwrap_or_default()
+                }
+            };
+            tmux::open(&dir, target.unwrap_or(config.tmux))?;
+            None
+        }
+        Some(CliCommand::Shell { force_new: false, .. }) if sessions::current().is_some() => {
+            let current = sessions::current().unwrap_or_default();
+            error!("already in a `t shell` in {current:?}, exit that one first (or use --force-new)");
+            exit::set(Code::AlreadyExists);
+            None
+        }
+        Some(CliCommand::Shell { options, .. }) => {
+            pipeline.shell = true;
+            pipeline.shell_options = options;
+            let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                return Ok(None);
+            };
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Persist { name, gitignore, path, only }) => {
+            let gitignore = gitignore || config.persist_gitignore;
+            let only = only
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(glob::Pattern::new)
+                .collect::<Result<Vec<_>, _>>()
+                .wrap_err("invalid glob pattern")?;
+
+            // hidden tempdirs have no entry, `t persist T-RS-TEMPDIR...` persists their backing directory like `--path`
+            let hidden = match (&path, name.as_deref().or(args.name.as_deref())) {
+                (None, Some(n)) if manager.find(n)?.is_none() => find_hidden(&tempdirs, &home, n)?,
+                _ => None,
+            };
+            let (path, name, top_name) = match hidden {
+                Some(backing) => (Some(backing), None, None),
+                None => (path, name, args.name),
+            };
+
+            if let Some(path) = path {
+                let path = path.canonicalize().wrap_err(format!("canonicalize {path:?}"))?;
+                let is_backing_dir = path.is_dir() && path
+                    .file_name()
+                    .is_some_and(|i| i.to_string_lossy().starts_with(TEMPDIR_PREFIX));
+
+                // a name given to `t persist` is just as much asked for as one given to `t`
+                let on_collision = match (name.is_some(), on_collision) {
+                    (true, OnCollision::Unique) if !args.unique && !config.unique_names => OnCollision::Fail,
+                    (_, i) => i,
+                };
+                let entry_name = match name.or(top_name.clone()) {
+                    Some(n) => n,
+                    None => naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), None))?,
+                };
+
+                if !is_backing_dir {
+                    error!("{path:?} isn't a t-rs tempdir (those are called {TEMPDIR_PREFIX}...)");
+                    exit::set(Code::NotFound);
+                    None
+                } else if let Some(entry_name) = free_name(&tempdirs, layout, entry_name, on_collision)? {
+                    // give it an entry like any other tempdir, and then persist that
+                    let dir = layout.dir(&tempdirs, group);
+                    std::fs::create_dir_all(&dir).wrap_err(format!("create {dir:?}"))?;
+                    let entry = dir.join(entry_name);
+                    platform::link_dir(&path, &entry).wrap_err("create symlink")?;
+                    Meta {
+                        created: Some(age::to_unix(age::created(&path)?)),
+                        ..Default::default()
+                    }.save(&entry)?;
+                    persist_entry(&manager, &entry, gitignore, &only)?;
+
+                    Some(entry)
+                } else {
+                    None
+                }
+            } else if let Some(i) = in_tempdir(&tempdirs, layout, &cwd, pwd.as_deref()).wrap_err("in tempdir while renaming")? {
+                let original_symlink = i.as_path();
+                persist_entry(&manager, original_symlink, gitignore, &only)?;
+
+                Some(i)
+            } else if let Some(ref n) = top_name {
+                let original_symlink = manager.resolve(n)?;
+                if !original_symlink.exists() {
+                    error!("{original_symlink:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                    None
+                } else {
+                    persist_entry(&manager, &original_symlink, gitignore, &only)?;
+
+                    Some(tempdirs.clone())
+                }
+            } else if let Some(ref n) = name {
+                let original_symlink = manager.resolve(n)?;
+                if !original_symlink.exists() {
+                    error!("{original_symlink:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                    None
+                } else {
+                    persist_entry(&manager, &original_symlink, gitignore, &only)?;
+
+                    Some(tempdirs.clone())
+                }
+            } else {
+                error!("not in a tempdir and no tempdir specified (run `t persist` inside one, or give its name or `--path`)");
+                exit::set(Code::NotFound);
+                None
+            }
+        }
+        Some(CliCommand::Delete { all, older_than, tag, names: _, dry_run }) if all || older_than.is_some() || tag.is_some() => {
+            let candidates = bulk_candidates(&tempdirs, layout, older_than, tag.as_deref())?;
+            if candidates.is_empty() {
+                exit::set(Code::NothingToDo);
+            }
+            if !dry_run && !confirm_delete(&tempdirs, &candidates, yes)? {
+                notice!("not deleting anything");
+                exit::set(Code::NothingToDo);
+                return Ok(None);
+            }
+            Some(delete_all(&tempdirs, layout, &home, older_than, tag.as_deref(), &config.hooks, dry_run)?)
+        }
+        Some(CliCommand::Gc { older_than, tag, dry_run: true, .. }) => {
+            let older_than = match older_than {
+                Some(i) => i,
+                None => config.gc_older_than()?,
+            };
+            delete_all(&tempdirs, layout, &home, Some(older_than), tag.as_deref(), &config.hooks, true)?;
+            // hidden tempdirs have no tags
+            if tag.is_none() {
+                for i in hidden_candidates(&tempdirs, &home, older_than)? {
+                    would_delete(&tempdirs, &i);
+                }
+            }
+            if config.gc_prune_empty {
+                gc::prune_empty(&tempdirs, layout, config.prune_empty_after()?, &trash, true)?;
+            }
+            notice!("(would also empty old things from the trash and the download cache)");
+            check_limits(&tempdirs, layout)?;
+            None
+        }
+        Some(CliCommand::Gc { older_than, tag, interactive, clear_cache, dry_run: false }) => {
+            let older_than = match older_than {
+                Some(i) => i,
+                None => config.gc_older_than()?,
+            };
+
+            let before = layout.entries(&tempdirs)?;
+            let res = if interactive {
+                let candidates = bulk_candidates(&tempdirs, layout, Some(older_than), tag.as_deref())?;
+                if candidates.is_empty() {
+                    notice!("nothing to clean up");
+                    exit::set(Code::NothingToDo);
+                } else {
+                    gc::interactive(&tempdirs, &home, &trash, &candidates)?;
+                }
+                layout.tidy(&tempdirs)?;
+                tempdirs.clone()
+            } else {
+                let candidates = bulk_candidates(&tempdirs, layout, Some(older_than), tag.as_deref())?;
+                // hidden tempdirs have no tags
+                let hidden = match tag {
+                    None => hidden_candidates(&tempdirs, &home, older_than)?,
+                    Some(_) => Vec::new(),
+                };
+                if candidates.is_empty() && hidden.is_empty() {
+                    exit::set(Code::NothingToDo);
+                }
+                if !confirm_delete(&tempdirs, &[candidates, hidden.clone()].concat(), yes)? {
+                    notice!("not deleting anything");
+                    exit::set(Code::NothingToDo);
+                    return Ok(None);
+                }
+                for i in &hidden {
+                    delete_hidden(i, &trash, &home)?;
+                }
+                delete_all(&tempdirs, layout, &home, Some(older_than), tag.as_deref(), &config.hooks, false)?
+            };
+            if config.gc_prune_empty {
+                gc::prune_empty(&tempdirs, layout, config.prune_empty_after()?, &trash, false)?;
+            }
+            if config.notify_removals && !interactive {
+                let after = layout.entries(&tempdirs)?;
+                let removed: Vec<_> = before.into_iter().filter(|i| !after.contains(i)).collect();
+                notify::removed(&tempdirs, &removed, "cleaned up by `t gc`", "`t undo` or `t restore <name>` brings them back while their files are still there.");
+            }
+            trash.purge()?;
+            Cache::new(&home).prune((!clear_cache).then_some(older_than))?;
+
+            if let Some(max) = max_total_size {
+                let used = usage::total(&layout.entries(&tempdirs)?);
+                info!("tempdirs now use {} of {}", usage::human_size(used), usage::human_size(max));
+            }
+            // gc doesn't delete them for being big, but it's a good moment to point them out
+            check_limits(&tempdirs, layout)?;
+
+            Some(res)
+        }
+        Some(CliCommand::InstallTimer { uninstall: true, .. }) => {
+            if !timer::uninstall(&home)? {
+                notice!("no timer installed");
+                exit::set(Code::NothingToDo);
+            }
+            None
+        }
+        Some(CliCommand::InstallTimer { older_than, every, uninstall: false }) => {
+            // nobody's there to answer questions
+            let mut gc_args = vec!["--yes".to_string()];
+            if let Some(ref profile) = args.profile {
+                gc_args.extend(["--profile".to_string(), profile.clone()]);
+            }
+            gc_args.extend(["gc".to_string(), "--older-than".to_string(), format!("{}s", older_than.as_secs())]);
+            timer::install(&home, &gc_args, every)?;
+            None
+        }
+        Some(CliCommand::Daemon { interval }) => {
+            daemon::run(&tempdirs, layout, &home, &config, &trash, interval)?;
+            None
+        }
+        Some(CliCommand::PruneEmpty { older_than, dry_run }) => {
+            let older_than = match older_than {
+                Some(i) => i,
+                None => config.prune_empty_after()?,
+            };
+            let pruned = gc::prune_empty(&tempdirs, layout, older_than, &trash, dry_run)?;
+            if pruned == 0 {
+                exit::set(Code::NothingToDo);
+            }
+            if dry_run {
+                notice!("would delete {pruned} empty tempdirs");
+                None
+            } else {
+                info!("deleted {pruned} empty tempdirs");
+                // we might have been in one
+                Some(tempdirs.clone())
+            }
+        }
+        Some(CliCommand::Orphans { relink, purge }) => {
+            // so we don't mistake a tempdir that's being created for an orphan
+            let _lock = lock::Lock::acquire(&tempdirs)?;
+            let orphans = orphans::find(&tempdirs, layout, &home, &config.backing_locations(&home))?;
+            if orphans.is_empty() {
+                notice!("no orphaned backing directories");
+                exit::set(Code::NothingToDo);
+            }
+
+            if relink {
+                for i in &orphans {
+                    let name = naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), None))?;
+                    orphans::relink(&tempdirs, layout, i, &name)?;
+                }
+                return Ok(None);
+            }
+
+            for i in &orphans {
+                eprintln!("{} ({}, {} old)", i.to_string_lossy(), usage::human_size(usage::size(i)), age::human(age::age(i)?));
+                if !purge {
+                    output::record(&["orphan", &i.to_string_lossy()]);
+                }
+            }
+            let question = match orphans.len() {
+                0 => None,
+                1 => Some("delete this backing directory for good?".to_string()),
+                n => Some(format!("delete these {n} backing directories for good?")),
+            };
+            match question {
+                Some(question) if purge => {
+                    if yes || prompt::confirm(&question, false)? {
+                        for i in &orphans {
+                            orphans::purge(i)?;
+                        }
+                    } else {
+                        notice!("not deleting anything");
+                        exit::set(Code::NothingToDo);
+                    }
+                }
+                Some(_) => notice!("use `t orphans --relink` to get them back as tempdirs, or `t orphans --purge` to delete them"),
+                None => {}
+            }
+            None
+        }
+        Some(CliCommand::Tag { name, tags, remove }) => {
+            let entry = manager.resolve(&name)?;
+            if !entry.exists() {
+                error!("{entry:?} doesn't exist");
+                exit::set(Code::NotFound);
+            } else if let Some(tags) = tags {
+                let mut meta = Meta::load(&entry)?;
+                for tag in tags.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+                    if remove {
+                        meta.tags.retain(|i| i != tag);
+                    } else if !meta.has_tag(tag) {
+                        meta.tags.push(tag.to_string());
+                    }
+                }
+                meta.save(&entry)?;
+                notice!("{entry:?} is tagged {}", meta.tags.join(", "));
+            } else {
+                let meta = Meta::load(&entry)?;
+                if meta.tags.is_empty() {
+                    notice!("{entry:?} has no tags");
+                } else {
+                    eprintln!("{}", meta.tags.join(", "));
+                }
+            }
+
+            None
+        }
+        Some(CliCommand::Delete { names, dry_run, .. }) => {
+            let names: Vec<String> = args.name.into_iter().chain(names).collect();
+            let mut entries = Vec::new();
+            let mut hidden = Vec::new();
+            if names.is_empty() {
+                match in_tempdir(&tempdirs, layout, &cwd, pwd.as_deref()).wrap_err("in tempdir while deleting")? {
+                    Some(i) => entries.push(i),
+                    None => {
+                        error!("not in a tempdir and no tempdir specified (use --all if you want to delete them all)");
+                        exit::set(Code::NotFound);
+                        return Ok(None);
+                    }
+                }
+            }
+            for n in &names {
+                let matches = if n.contains(['*', '?', '[']) {
+                    layout.glob(&tempdirs, n)?
+                } else {
+                    vec![manager.resolve(n)?]
+                };
+                let matches: Vec<_> = matches.into_iter().filter(|i| i.exists()).collect();
+                // hidden tempdirs go by the name of their backing directory
+                let hidden_match = match matches.is_empty() {
+                    true => find_hidden(&tempdirs, &home, n)?,
+                    false => None,
+                };
+                if let Some(backing) = hidden_match {
+                    if !hidden.contains(&backing) {
+                        hidden.push(backing);
+                    }
+                } else if matches.is_empty() {
+                    error!("{:?} doesn't exist", tempdirs.join(n));
+                    exit::set(Code::NotFound);
+                }
+                for i in matches {
+                    if !entries.contains(&i) {
+                        entries.push(i);
+                    }
+                }
+            }
+
+            if dry_run {
+                for i in entries.iter().chain(&hidden) {
+                    would_delete(&tempdirs, i);
+                }
+                return Ok(None);
+            }
+            let all: Vec<PathBuf> = entries.iter().chain(&hidden).cloned().collect();
+            let confirmed = match all.as_slice() {
+                [] => return Ok(None),
+                [entry] if entries.contains(entry) && Meta::load(entry)?.persistent(entry) => {
+                    yes || prompt::confirm(&format!("{entry:?} is persistent, delete it anyway?"), false)?
+                }
+                [_] => true,
+                _ => confirm_delete(&tempdirs, &all, yes)?,
+            };
+            if !confirmed {
+                notice!("not deleting anything");
+                exit::set(Code::NothingToDo);
+                return Ok(None);
+            }
+
+            for i in &entries {
+                manager.delete(i)?;
+            }
+            for i in &hidden {
+                delete_hidden(i, &trash, &home)?;
+            }
+            if all.len() > 1 {
+                info!("deleted {} tempdirs", all.len());
+            }
+            Some(tempdirs.clone())
+        }
+        Some(CliCommand::Run { keep, keep_on_failure, command }) => {
+            let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                return Ok(None);
+            };
+            pipeline.shell = false;
+            let Some(res) = pipeline.run(&tempdirs, &config.hooks, res)? else {
+                return Ok(None);
+            };
+
+            let mut cmd = shell_command(&res, None)?;
+            cmd.arg(platform::command_flag(cmd.get_program())).arg(command.join(" "));
+            // stdout goes to the wrapper, so show the output of the command on stderr
+            cmd.stdout(std::io::stderr());
+
+            let status = run_in_session(&tempdirs, &res, cmd, |status| keep || (keep_on_failure && !status.success()))?;
+            if !status.success() {
+                error!("`{}` failed ({status})", command.join(" "));
+                exit::set(Code::Error);
+            }
+
+            res.exists().then_some(res)
+        }
+        Some(CliCommand::Adopt { dir, symlink: link }) => {
+            let dir = dir.canonicalize().wrap_err(format!("canonicalize {dir:?}"))?;
+            if !dir.is_dir() {
+                color_eyre::eyre::bail!("{dir:?} isn't a directory");
+            }
+            if dir.starts_with(&tempdirs) || dir.starts_with(tempdirs.canonicalize().wrap_err("canonicalize tempdirs")?) {
+                color_eyre::eyre::bail!("{dir:?} is in the tempdirs root already");
+            }
+
+            let name = match args.name {
+                Some(name) => name,
+                None => dir.file_name().wrap_err(format!("{dir:?} has no name"))?.to_string_lossy().to_string(),
+            };
+            let Some(name) = free_name(&tempdirs, layout, name, on_collision)? else {
+                return Ok(None);
+            };
+
+            let target = if link {
+                dir.clone()
+            } else {
+                let target = backing.create()?;
+                // move_dir wants to create the directory itself
+                std::fs::remove_dir(&target).wrap_err(format!("remove {target:?}"))?;
+                info!("moving {dir:?} to {target:?}");
+                trash::move_dir(&dir, &target)?;
+                target
+            };
+
+            let parent = layout.dir(&tempdirs, group);
+            std::fs::create_dir_all(&parent).wrap_err(format!("create {parent:?}"))?;
+            let entry = parent.join(&name);
+            platform::link_dir(&target, &entry).wrap_err("create symlink")?;
+            let origin = pwd.clone().unwrap_or_else(|| cwd.clone());
+            Meta {
+                created: Some(age::now()),
+                origin: Some(origin.clone()),
+                host: meta::hostname(),
+                ..Default::default()
+            }.save(&entry)?;
+            journal.record(Op::Create { entry: entry.clone(), origin: Some(origin) })?;
+            info!("adopted {dir:?} as {entry:?}");
+
+            // we might have been in the directory we just moved
+            Some(entry)
+        }
+        Some(CliCommand::Paste) => {
+            // before creating anything, so an empty clipboard doesn't leave an empty tempdir
+            let contents = clipboard::read()?;
+
+            let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                return Ok(None);
+            };
+            let path = res.join(contents.file_name());
+            std::fs::write(&path, contents.bytes()).wrap_err(format!("write {path:?}"))?;
+            info!("wrote {} to {path:?}", usage::human_size(contents.bytes().len() as u64));
+
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Pipe { filename }) => {
+            if filename.file_name().is_none_or(|i| Path::new(i) != filename) {
+                color_eyre::eyre::bail!("{filename:?} should be just a file name");
+            }
+            if std::io::stdin().is_terminal() {
+                notice!("nothing is piped in, reading from the terminal until ctrl-d");
+            }
+
+            let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                return Ok(None);
+            };
+            // only stdin carries data here, the directory to cd into still goes where it always does
+            let path = res.join(&filename);
+            let mut file = std::fs::File::create(&path).wrap_err(format!("create {path:?}"))?;
+            let bytes = std::io::copy(&mut std::io::stdin().lock(), &mut file).wrap_err(format!("write stdin to {path:?}"))?;
+            info!("wrote {} to {path:?}", usage::human_size(bytes));
+
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::With { paths, r#move }) => {
+            if let Some(missing) = paths.iter().find(|i| !i.exists()) {
+                color_eyre::eyre::bail!("{missing:?} doesn't exist");
+            }
+
+            // named after the first file, unless a name was given
+            let first = paths[0].canonicalize().wrap_err(format!("canonicalize {:?}", paths[0]))?;
+            let stem = first.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let name = match args.name {
+                Some(name) => name,
+                None if config.name_template.is_some() || stem.is_empty() => {
+                    naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), Some(&stem)))?
+                }
+                None => stem,
+            };
+            let Some(res) = created_entry(new_tempdir(&name).seed(paths, r#move).create()?) else {
+                return Ok(None);
+            };
+
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Empty { n, prefix }) => {
+            let mut created = Vec::new();
+            for i in 1.. {
+                if created.len() == n {
+                    break;
+                }
+                let name = format!("{prefix}-{i}");
+                // also leave the names of deleted ones free, so they can be restored
+                if manager.find(&name)?.is_some() || trash.find(&name)?.is_some() {
+                    continue;
+                }
+
+                created.extend(created_entry(new_tempdir(&name).create()?));
+            }
+
+            for i in &created {
+                println!("{}", i.to_string_lossy());
+            }
+            None
+        }
+        Some(CliCommand::Edit { .. }) => {
+            let current = match args.name {
+                Some(_) => None,
+                None => named_or_current(&tempdirs, layout, None, &cwd, pwd.as_deref())?,
+            };
+            let entry = match current.or(existing) {
+                Some(entry) => entry,
+                None => {
+                    let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                        return Ok(None);
+                    };
+                    pipeline.run(&tempdirs, &config.hooks, res)?.unwrap_or_default()
+                }
+            };
+
+            let Some(mut cmd) = platform::editor(config.editor.as_deref()) else {
+                color_eyre::eyre::bail!("no editor to open {entry:?} with, set $EDITOR or `editor` in the config");
+            };
+            // terminal editors need the terminal until they're done
+            let status = cmd.arg(&entry)
+                .stdout(std::io::stderr())
+                .status()
+                .wrap_err(format!("run {:?}", cmd.get_program()))?;
+            if !status.success() {
+                color_eyre::eyre::bail!("{:?} failed ({status})", cmd.get_program());
+            }
+            None
+        }
+        Some(CliCommand::Open { name }) => {
+            let Some(entry) = named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())? else {
+                error!("not in a tempdir and no tempdir specified");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            };
+            if !entry.exists() {
+                color_eyre::eyre::bail!("{entry:?} doesn't exist");
+            }
+
+            let mut cmd = platform::opener(config.opener.as_deref());
+            // some file managers only return once their window is closed, so don't wait for them
+            cmd.arg(&entry)
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .wrap_err(format!("run {:?}", cmd.get_program()))?;
+            info!("opened {entry:?}");
+            None
+        }
+        Some(CliCommand::Share { name, port, upload }) => {
+            let Some(entry) = named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())? else {
+                error!("not in a tempdir and no tempdir specified");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            };
+            if !entry.exists() {
+                color_eyre::eyre::bail!("{entry:?} doesn't exist");
+            }
+            share::serve(&entry, port, upload)?;
+            None
+        }
+        Some(CliCommand::Push { name_or_target, target }) => {
+            let (name, target) = match (name_or_target, target) {
+                (Some(i), None) if push::is_target(&i) => (None, Some(i)),
+                (name, target) => (name, target),
+            };
+            let Some(entry) = named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())? else {
+                error!("not in a tempdir and no tempdir specified");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            };
+            if !entry.exists() {
+                color_eyre::eyre::bail!("{entry:?} doesn't exist");
+            }
+
+            let mut meta = Meta::load(&entry)?;
+            let Some(target) = target.or(meta.pushed_to.clone()) else {
+                color_eyre::eyre::bail!("where to? {entry:?} hasn't been pushed before");
+            };
+            let name = entry.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut cmd = config.push_tool.command(&entry, &name, &target);
+            info!("pushing {entry:?} to {target}");
+            // stdout goes to the wrapper, so show the progress on stderr
+            cmd.stdout(std::io::stderr());
+
+            let status = cmd.status().wrap_err(format!("run {:?}", cmd.get_program()))?;
+            if !status.success() {
+                color_eyre::eyre::bail!("pushing {entry:?} failed ({status})");
+            }
+            if meta.pushed_to.as_ref() != Some(&target) {
+                meta.pushed_to = Some(target);
+                meta.save(&entry)?;
+            }
+            None
+        }
+        Some(CliCommand::Task { task, args: task_args }) => {
+            let Some(entry) = named_or_current(&tempdirs, layout, args.name, &cwd, pwd.as_deref())? else {
+                error!("not in a tempdir and no tempdir specified");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            };
+            let Some(runner) = tasks::Runner::detect(&entry) else {
+                color_eyre::eyre::bail!("{entry:?} has no justfile, Makefile or package.json");
+            };
+
+            let mut meta = Meta::load(&entry)?;
+            let task = task.or(meta.task.clone());
+            let Some(mut cmd) = runner.command(task.as_deref(), &task_args) else {
+                color_eyre::eyre::bail!("which task? {runner:?} has no default one");
+            };
+            // stdout goes to the wrapper, so show the output of the task on stderr
+            cmd.current_dir(&entry).stdout(std::io::stderr());
+
+            let status = cmd.status().wrap_err(format!("run {:?}", cmd.get_program()))?;
+            if !status.success() {
+                error!("the task failed ({status})");
+                exit::set(Code::Error);
+            }
+            if task.is_some() && task != meta.task {
+                meta.task = task;
+                meta.save(&entry)?;
+            }
+
+            None
+        }
+        Some(CliCommand::Exec { watch, command }) => {
+            let Some(entry) = named_or_current(&tempdirs, layout, args.name, &cwd, pwd.as_deref())? else {
+                error!("not in a tempdir and no tempdir specified");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            };
+            let command = command.join(" ");
+
+            let run = || {
+                let mut cmd = shell_command(&entry, None)?;
+                cmd.arg(platform::command_flag(cmd.get_program())).arg(&command);
+                // stdout goes to the wrapper, so show the output of the command on stderr
+                cmd.stdout(std::io::stderr());
+
+                let status = cmd.status().wrap_err(format!("run `{command}`"))?;
+                if !status.success() {
+                    error!("`{command}` failed ({status})");
+                    exit::set(Code::Error);
+                }
+                Ok(())
+            };
+            if watch {
+                watch::watch(&entry, run)?;
+            } else {
+                run()?;
+            }
+
+            None
+        }
+        Some(CliCommand::Hidden) => {
+            let Some(res) = created_entry(new_tempdir(&name).hidden(true).create()?) else {
+                return Ok(None);
+            };
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Secret { passphrase }) => {
+            let key = if passphrase { secret::Key::Passphrase } else { secret::Key::Generated };
+            let backing = Backing { secret: Some(key), ..backing.clone() };
+            let Some(res) = created_entry(new_tempdir(&name).backing(&backing).create()?) else {
+                return Ok(None);
+            };
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Note { name_or_text, text, clear }) => {
+            // with two arguments (or the top-level name) the tempdir is explicit,
+            // with just one it's the text for the tempdir we're in, if any.
+            let (entry, text) = match (args.name, name_or_text, text) {
+                (_, Some(n), Some(text)) => (Some(manager.resolve(&n)?), Some(text)),
+                (Some(n), text, None) => (Some(manager.resolve(&n)?), text),
+                (None, text, None) => {
+                    let current = in_tempdir(&tempdirs, layout, &cwd, pwd.as_deref()).wrap_err("in tempdir while noting")?
+                        .map(|i| tempdir_entry(&tempdirs, layout, &i))
+                        .transpose()?
+                        .flatten();
+
+                    match (current, text) {
+                        (Some(current), text) => (Some(current), text),
+                        // `t note foo` outside of a tempdir: show the note of foo
+                        (None, Some(n)) => (Some(manager.resolve(&n)?), None),
+                        (None, None) => (None, None),
+                    }
+                }
+                (_, None, Some(_)) => unreachable!("positional arguments are filled in order"),
+            };
+
+            match entry {
+                None => {
+                    error!("not in a tempdir and no tempdir specified");
+                    exit::set(Code::NotFound);
+                }
+                Some(entry) if !entry.exists() => {
+                    error!("{entry:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                }
+                Some(entry) => {
+                    let mut meta = Meta::load(&entry)?;
+                    if clear {
+                        meta.note = None;
+                        meta.save(&entry)?;
+                        info!("removed note from {entry:?}");
+                    } else if let Some(text) = text {
+                        meta.note = Some(text);
+                        meta.save(&entry)?;
+                        info!("saved note for {entry:?}");
+                    } else if let Some(note) = meta.note {
+                        eprintln!("{note}");
+                    } else {
+                        notice!("{entry:?} has no note");
+                    }
+                }
+            }
 
-    let cwd = std::env::current_dir().wrap_err("get current dir")?;
-    let pwd = {
-        let pwd = std::env::var("PWD").wrap_err("get pwd")?;
-        if pwd.is_empty() {
             None
-        } else {
-            Some(PathBuf::from(pwd))
         }
-    };
-
-    let orig = pwd.clone().unwrap_or(cwd.clone());
-
-    let go_to: Option<PathBuf> = match args.command {
-        None => {
-            Some(create_tempdir(&tempdirs, &name, &cwd, pwd.as_deref(), true)?)
+        Some(CliCommand::Pin { name }) => {
+            set_pinned(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref(), true)?;
+            None
         }
-        Some(CliCommand::Shell) => {
-            shell(&tempdirs, &name, &cwd, &pwd)?;
+        Some(CliCommand::Unpin { name }) => {
+            set_pinned(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref(), false)?;
             None
         }
-        Some(CliCommand::Persist { name }) => {
-            fn persist(p: &Path) -> Result<()> {
-                if !p.is_symlink() {
-                    eprintln!("{p:?} was already persistent");
+        Some(CliCommand::Env { name, command }) => {
+            let entry = named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())?;
 
-                    return Ok(());
+            match entry {
+                None => {
+                    error!("not in a tempdir and no tempdir specified");
+                    exit::set(Code::NotFound);
                 }
+                Some(entry) if !entry.exists() => {
+                    error!("{entry:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                }
+                Some(entry) => {
+                    let mut meta = Meta::load(&entry)?;
+                    match command.unwrap_or(EnvCommand::List) {
+                        EnvCommand::Set { vars } => {
+                            for var in vars {
+                                let Some((key, value)) = var.split_once('=') else {
+                                    error!("expected KEY=VALUE, got {var:?}");
+                                    exit::set(Code::Error);
+                                    return Ok(None);
+                                };
+                                meta.env.insert(key.to_string(), value.to_string());
+                            }
+                            meta.save(&entry)?;
+                        }
+                        EnvCommand::Unset { keys } => {
+                            for key in keys {
+                                meta.env.remove(&key);
+                            }
+                            meta.save(&entry)?;
+                        }
+                        EnvCommand::List if meta.env.is_empty() => {
+                            notice!("{entry:?} has no environment variables");
+                        }
+                        EnvCommand::List => {
+                            for (key, value) in &meta.env {
+                                eprintln!("{key}={value}");
+                            }
+                        }
+                        EnvCommand::Export => unreachable!("handled before running commands"),
+                    }
+                }
+            }
 
-                let original_target = std::fs::read_link(p).wrap_err("read link")?;
-
-                // unlink the original reference
-                symlink::remove_symlink_auto(&p).wrap_err("unlink")?;
+            None
+        }
+        Some(CliCommand::Compare { a, b, content, differ }) => {
+            let a = manager.resolve(&a)?;
+            let b = manager.resolve(&b)?;
+
+            if !a.exists() {
+                error!("{a:?} doesn't exist");
+                exit::set(Code::NotFound);
+            } else if !b.exists() {
+                error!("{b:?} doesn't exist");
+                exit::set(Code::NotFound);
+            } else {
+                let res = compare::compare(&a, &b)?;
+                if res.is_empty() {
+                    notice!("{a:?} and {b:?} are the same");
+                }
 
-                eprintln!("moving from {original_target:?} to {p:?}");
-                // but then move the original temporary dir to where the symlink used to be
-                fs_extra::dir::move_dir(&original_target, p, &CopyOptions {
-                    copy_inside: true,
-                    ..Default::default()
-                }).wrap_err("copy to original symlink location")?;
+                for i in &res.added {
+                    eprintln!("+ {}", i.to_string_lossy());
+                }
+                for i in &res.removed {
+                    eprintln!("- {}", i.to_string_lossy());
+                }
+                for i in &res.changed {
+                    eprintln!("~ {}", i.to_string_lossy());
+                }
+                if !res.is_empty() {
+                    notice!("{}", res.summary());
+                }
 
-                eprintln!("{:?} is now persistent", p);
-                Ok(())
+                let differ = differ.or_else(|| content.then(|| config.differ.clone()).flatten());
+                if let Some(ref differ) = differ {
+                    for i in &res.changed {
+                        eprintln!();
+                        compare::external_diff(differ, &a, &b, i)?;
+                    }
+                } else if content {
+                    for i in &res.changed {
+                        match compare::unified_diff(&a, &b, i)? {
+                            Some(diff) => eprint!("\n{diff}"),
+                            None => eprintln!("\nbinary file {} differs", i.to_string_lossy()),
+                        }
+                    }
+                }
             }
 
-            if let Some(i) = in_tempdir(&tempdirs, &cwd, pwd.as_deref()).wrap_err("in tempdir while renaming")? {
-                let original_symlink = i.as_path();
-                persist(original_symlink)?;
+            None
+        }
+        Some(CliCommand::Back) => {
+            match named_or_current(&tempdirs, layout, args.name, &cwd, pwd.as_deref())? {
+                None => {
+                    error!("not in a tempdir");
+                    exit::set(Code::NotFound);
+                    None
+                }
+                Some(entry) => match Meta::load(&entry)?.origin {
+                    Some(origin) if origin.is_dir() => Some(origin),
+                    Some(origin) => {
+                        error!("{origin:?} doesn't exist anymore");
+                        exit::set(Code::NotFound);
+                        None
+                    }
+                    None => {
+                        error!("don't know where {entry:?} was created from");
+                        exit::set(Code::NotFound);
+                        None
+                    }
+                },
+            }
+        }
+        Some(CliCommand::Last { n }) => {
+            let mut entries = Vec::new();
+            for i in manager.list()?.into_iter().filter(|i| i.entry.exists()) {
+                entries.push((age::last_used(&i.entry)?, i.entry));
+            }
+            entries.sort_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
 
-                Some(i)
-            } else if let Some(ref n) = args.name {
-                let original_symlink = tempdirs.join(n);
-                if !original_symlink.exists() {
-                    eprintln!("{original_symlink:?} doesn't exist");
+            match entries.into_iter().nth(n.saturating_sub(1)) {
+                Some((_, entry)) => Some(entry),
+                None => {
+                    error!("there aren't {n} active tempdirs");
+                    exit::set(Code::NotFound);
                     None
-                } else {
-                    persist(&original_symlink)?;
+                }
+            }
+        }
+        Some(CliCommand::Pick) => {
+            let listed = manager.list()?;
+            if listed.is_empty() {
+                error!("no active tempdirs");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            }
 
-                    Some(tempdirs)
+            let mut items = Vec::new();
+            for i in &listed {
+                let mut item = format!("{} ({} old)", i.name, age::human(age::age(&i.entry)?));
+                if let Some(ref note) = i.meta.note {
+                    item.push_str(&format!(" - {note}"));
                 }
-            } else if let Some(ref n) = name {
-                let original_symlink = tempdirs.join(n);
-                if !original_symlink.exists() {
-                    eprintln!("{original_symlink:?} doesn't exist");
-                    None
-                } else {
-                    persist(&original_symlink)?;
+                items.push(item);
+            }
+
+            let selected = prompt::fuzzy_select("pick a tempdir", &items, 0)?;
+
+            selected.map(|i| listed[i].entry.clone())
+        }
+        Some(CliCommand::Ui) => {
+            ui::run(&tempdirs, layout, &trash, config.persist_gitignore)?
+        }
+        Some(CliCommand::Top) => {
+            top::run(&tempdirs, layout, &trash)?;
+            None
+        }
+        Some(CliCommand::Complete | CliCommand::Init { .. } | CliCommand::Prompt { .. } | CliCommand::Manpages { .. }) => unreachable!("handled before running commands"),
+        Some(CliCommand::History { limit, json }) => {
+            let mut events = journal.history()?;
+            if let Some(limit) = limit {
+                events.drain(..events.len().saturating_sub(limit));
+            }
 
-                    Some(tempdirs)
+            if json {
+                println!("{}", serde_json::to_string_pretty(&events).wrap_err("serialize history")?);
+            } else if events.is_empty() {
+                notice!("no history yet");
+            } else {
+                for i in &events {
+                    eprintln!("{}", i.describe(&home));
                 }
+            }
+
+            None
+        }
+        Some(CliCommand::Undo) if journal.is_empty()? => {
+            notice!("nothing to undo");
+            exit::set(Code::NothingToDo);
+            None
+        }
+        Some(CliCommand::Undo) => {
+            journal.undo(&trash)?
+        }
+        Some(CliCommand::Restore { name: Some(name) }) => {
+            Some(trash.restore(&name)?)
+        }
+        Some(CliCommand::Restore { name: None }) => {
+            let trashed = trash.list()?;
+            if trashed.is_empty() {
+                notice!("the trash is empty");
+            }
+            for i in trashed {
+                let ago = age::human(SystemTime::now().duration_since(age::from_unix(i.deleted)).unwrap_or_default());
+                eprintln!("{} (deleted {ago} ago)", i.entry.to_string_lossy());
+            }
+
+            None
+        }
+        Some(CliCommand::Config { command: ConfigCommand::Export { file } }) => {
+            config::export(&home, &file)?;
+            None
+        }
+        Some(CliCommand::Config { command: ConfigCommand::Import { file, force } }) => {
+            config::import(&home, &file, force)?;
+            None
+        }
+        Some(CliCommand::CheckIntegration) => {
+            integration::check();
+            None
+        }
+        Some(CliCommand::Stats { json }) => {
+            let stats = stats::collect(&tempdirs, layout, &home, &trash)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats).wrap_err("serialize stats")?);
             } else {
-                eprintln!("not in a tempdir and no tempdir specified (use --all if you want to delete them all)");
-                None
+                stats::print(&stats);
+            }
+            None
+        }
+        Some(CliCommand::SelfUpdate { check }) => {
+            if !update::self_update(check)? {
+                exit::set(Code::NothingToDo);
             }
+            None
         }
-        Some(CliCommand::Delete { all: true, name: _ }) => {
-            Some(delete_all(&tempdirs)?)
+        Some(CliCommand::Doctor) => {
+            let checks = doctor::checks(&tempdirs, layout, &home, &config)?;
+            for i in &checks {
+                match i.problem {
+                    None => eprintln!("ok    {}", i.name),
+                    Some((ref what, ref fix)) => {
+                        eprintln!("FAIL  {}: {what}", i.name);
+                        eprintln!("      fix: {fix}");
+                    }
+                }
+            }
+            if checks.iter().any(|i| i.problem.is_some()) {
+                exit::set(Code::Error);
+            }
+            None
         }
-        Some(CliCommand::Delete { all: false, name }) => {
-            if let Some(i) = in_tempdir(&tempdirs, &cwd, pwd.as_deref()).wrap_err("in tempdir while renaming")? {
-                let original_symlink = i.as_path();
-                delete(original_symlink)?;
+        // handled before anything else happens
+        Some(CliCommand::Setup) => None,
+        Some(CliCommand::Find { pattern, content }) => {
+            let found = find::find(&layout.entries(&tempdirs)?, &pattern, content);
+            for (entry, matches) in &found {
+                eprintln!("{}:", entry.strip_prefix(&tempdirs).unwrap_or(entry).display());
+                for i in matches {
+                    eprintln!("  {}", i.describe());
+                }
+            }
 
-                Some(tempdirs)
-            } else if let Some(ref n) = args.name {
-                let original_symlink = tempdirs.join(n);
-                if !original_symlink.exists() {
-                    eprintln!("{original_symlink:?} doesn't exist");
+            match found.len() {
+                0 => {
+                    notice!("nothing in any tempdir matches {pattern:?}");
+                    exit::set(Code::NotFound);
                     None
-                } else {
-                    symlink::remove_symlink_auto(&original_symlink).wrap_err(format!("remove symlink {:?}", original_symlink))?;
-                    delete(&original_symlink)?;
-
-                    Some(tempdirs)
                 }
-            } else if let Some(ref n) = name {
-                let original_symlink = tempdirs.join(n);
-                if !original_symlink.exists() {
-                    eprintln!("{original_symlink:?} doesn't exist");
+                1 => Some(found[0].0.clone()),
+                n => {
+                    notice!("found matches in {n} tempdirs");
                     None
-                } else {
-                    delete(&original_symlink)?;
-
-                    Some(tempdirs)
                 }
-            } else {
-                eprintln!("not in a tempdir and no tempdir specified (use --all if you want to delete them all)");
-                None
             }
         }
-        Some(CliCommand::Hidden) => {
-            Some(create_tempdir(&tempdirs, &name, &cwd, pwd.as_deref(), false)?)
+        Some(CliCommand::Status { at: Some(time), .. }) => {
+            let events = journal.history()?;
+            let past = history::at(&events, time);
+            let when = chrono::DateTime::<chrono::Local>::from(age::from_unix(time)).format("%Y-%m-%d %H:%M");
+
+            if past.is_empty() {
+                notice!("no tempdirs at {when}");
+            } else {
+                eprintln!("tempdirs at {when}:");
+            }
+            for i in past {
+                let created = chrono::DateTime::<chrono::Local>::from(age::from_unix(i.created)).format("%Y-%m-%d %H:%M");
+                eprintln!("{} (created {created})", config::abbreviate_home(&i.entry, &home).to_string_lossy());
+                for event in &i.since {
+                    eprintln!("    {}", event.describe(&home));
+                }
+                if i.since.is_empty() {
+                    eprintln!("    still there");
+                }
+            }
+            None
         }
-        Some(CliCommand::Status) => {
-            if let Some(i) = in_tempdir(&tempdirs, &cwd, pwd.as_deref()).wrap_err("in tempdir while getting status")? {
-                if i.is_symlink() {
+        Some(CliCommand::Status { tag, filter, sort, reverse, no_size, all, at: None }) => {
+            let current = in_tempdir(&tempdirs, layout, &cwd, pwd.as_deref()).wrap_err("in tempdir while getting status")?;
+            if output::porcelain() {
+                if let Some(ref i) = current {
+                    output::record(&["current", &i.to_string_lossy()]);
+                }
+            } else if let Some(i) = current {
+                if Meta::load(&i)?.persistent(&i) {
+                    eprintln!("currently in persisted tempdir {i:?}");
+                } else {
                     eprintln!("currently in tempdir {i:?}");
+                }
+                if i.is_symlink() {
                     eprintln!("which is a symlink to {:?}", std::fs::read_link(&i).wrap_err("read link")?)
-                } else {
-                    eprintln!("currently in persisted tempdir {i:?}");
                 }
             } else {
-                eprintln!("currently not in a tempdir");
+                notice!("currently not in a tempdir");
+            }
+            if let Some(daemon) = daemon::status(&tempdirs)?.filter(|_| !output::porcelain()) {
+                eprintln!("{}", daemon.describe());
             }
 
-            active_tempdirs(&tempdirs)?;
+            let hidden = if all { Registry::new(&home).hidden(&tempdirs)? } else { Vec::new() };
+            active_tempdirs(&tempdirs, layout, &home, &Listing {
+                tag: tag.as_deref(),
+                filter: filter.as_deref(),
+                sort,
+                reverse,
+                size: !no_size,
+                max_total_size,
+                hidden: &hidden,
+                stale_after: config.stale_after()?,
+            })?;
+            None
+        }
+        Some(CliCommand::Snapshot { name }) => {
+            match named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())? {
+                None => {
+                    error!("not in a tempdir and no tempdir specified");
+                    exit::set(Code::NotFound);
+                }
+                Some(entry) if !entry.exists() => {
+                    error!("{entry:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                }
+                Some(entry) => {
+                    let snapshot = snapshot(&tempdirs, layout, &entry, &backing, &trash, &journal)?;
+                    info!("copied {entry:?} to {snapshot:?}");
+                }
+            }
+            None
+        }
+        Some(CliCommand::Backup { file }) => {
+            let n = backup::backup(&tempdirs, layout, &file)?;
+            info!("backed up {n} tempdirs to {file:?}");
+            if n == 0 {
+                exit::set(Code::NothingToDo);
+            }
+            None
+        }
+        Some(CliCommand::RestoreBackup { file }) => {
+            let n = backup::restore(&tempdirs, &file, &backing)?;
+            info!("restored {n} tempdirs from {file:?}");
+            if n == 0 {
+                exit::set(Code::NothingToDo);
+            }
             None
         }
+        Some(CliCommand::Archive { name, prune }) => {
+            match named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())? {
+                None => {
+                    error!("not in a tempdir and no tempdir specified");
+                    exit::set(Code::NotFound);
+                    None
+                }
+                Some(entry) if !entry.exists() => {
+                    error!("{entry:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                    None
+                }
+                Some(entry) => {
+                    let name = entry.file_name().unwrap_or_default().to_string_lossy().to_string();
+                    let file = archive::dir(&home, &config).join(format!("{name}{}", archive::EXTENSION));
+                    // tar doesn't follow the symlink of non-persistent tempdirs
+                    let dir = entry.canonicalize().wrap_err(format!("canonicalize {entry:?}"))?;
+                    archive::create(&dir, &file)?;
+                    info!("archived {entry:?} to {file:?}");
+
+                    if prune {
+                        manager.delete(&entry)?;
+                        // we might have been in it
+                        Some(tempdirs.clone())
+                    } else {
+                        None
+                    }
+                }
+            }
+        }
+        Some(CliCommand::Unarchive { file }) => {
+            let file = pwd.as_deref().unwrap_or(&cwd).join(file);
+            if !file.is_file() {
+                color_eyre::eyre::bail!("{file:?} doesn't exist");
+            }
+            let name = match args.name {
+                Some(name) => name,
+                None => archive::name_of(&file).wrap_err(format!("can't tell what to name the tempdir for {file:?}"))?,
+            };
+
+            let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                return Ok(None);
+            };
+            archive::extract(&file, &res)?;
+            info!("unpacked {file:?} into {res:?}");
+
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Promote { name, name_or_dest, dest, git }) => {
+            let (name, dest) = match dest {
+                Some(dest) => (Some(name_or_dest.to_string_lossy().to_string()), dest),
+                None => (name, name_or_dest),
+            };
+
+            match named_or_current(&tempdirs, layout, name.or(args.name), &cwd, pwd.as_deref())? {
+                None => {
+                    error!("not in a tempdir and no tempdir specified");
+                    exit::set(Code::NotFound);
+                    None
+                }
+                Some(entry) if !entry.exists() => {
+                    error!("{entry:?} doesn't exist");
+                    exit::set(Code::NotFound);
+                    None
+                }
+                Some(entry) => {
+                    let dest = pwd.as_deref().unwrap_or(&cwd).join(dest);
+                    let res = promote(&entry, &dest, &journal)?;
+                    layout.tidy(&tempdirs)?;
+                    match res {
+                        None => {
+                            error!("can't promote to {:?} because it already exists", promote_target(&entry, &dest));
+                            exit::set(Code::AlreadyExists);
+                        }
+                        Some(ref dest) if git => {
+                            if let Err(e) = git_init(dest) {
+                                error!("{e}");
+                                exit::set(Code::Error);
+                            }
+                        }
+                        Some(_) => {}
+                    }
+                    res
+                }
+            }
+        }
         Some(CliCommand::Rename { from, to }) => {
             if let Some(mut new_name) = from.clone() {
                 if let Some(to) = to.clone() {
                     new_name = to;
                 }
 
-                if let Some(i) = in_tempdir(&tempdirs, &cwd, pwd.as_deref()).wrap_err("in tempdir while renaming")? {
+                if let Some(i) = in_tempdir(&tempdirs, layout, &cwd, pwd.as_deref()).wrap_err("in tempdir while renaming")? {
                     let original_symlink = i.as_path();
-                    let new_symlink = tempdirs.join(new_name);
-
-                    if rename(original_symlink, &new_symlink)? {
-                        Some(new_symlink)
-                    } else {
-                        None
-                    }
+                    rename_entry(&manager, original_symlink, &new_name)?
                 } else if let Some(ref n) = args.name {
-                    let original_symlink = tempdirs.join(n);
+                    let original_symlink = manager.resolve(n)?;
                     if !original_symlink.exists() {
-                        eprintln!("{original_symlink:?} doesn't exist");
+                        error!("{original_symlink:?} doesn't exist");
+                        exit::set(Code::NotFound);
                     } else {
-                        let new_symlink = tempdirs.join(new_name);
-
-                        rename(&original_symlink, &new_symlink)?;
+                        rename_entry(&manager, &original_symlink, &new_name)?;
                     }
                     None
                 } else if let Some(ref n) = from {
                     if to.is_some() {
-                        let original_symlink = tempdirs.join(n);
+                        let original_symlink = manager.resolve(n)?;
                         if !original_symlink.exists() {
-                            eprintln!("{original_symlink:?} doesn't exist");
+                            error!("{original_symlink:?} doesn't exist");
+                            exit::set(Code::NotFound);
                         } else {
-                            let new_symlink = tempdirs.join(new_name);
-
-                            rename(&original_symlink, &new_symlink)?;
+                            rename_entry(&manager, &original_symlink, &new_name)?;
                         }
                         None
                     } else {
-                        eprintln!("not in a tempdir and no tempdir specified");
+                        error!("not in a tempdir and no tempdir specified");
+                        exit::set(Code::NotFound);
                         None
                     }
                 } else {
-                    eprintln!("not in a tempdir and no tempdir specified");
+                    error!("not in a tempdir and no tempdir specified");
+                    exit::set(Code::NotFound);
                     None
                 }
             } else {
-                eprintln!("you have to specify a new name");
+                error!("you have to specify a new name");
+                exit::set(Code::Error);
                 None
             }
         }
-        Some(CliCommand::Dl { name, r#move }) => {
-            let mut fallback_dl_dir = home.join("Downloads");
-            if !fallback_dl_dir.exists() {
-                fallback_dl_dir = home.join("dl");
-            }
-            let dl_dir = match std::env::var("XDG_DOWNLOAD_DIR") {
-                Ok(i) => PathBuf::from(i),
-                Err(VarError::NotPresent) => {
-                    fallback_dl_dir
+        Some(CliCommand::Dl { name, url: Some(url), no_cache, name_from_content, .. }) => {
+            let filename = downloads::file_name_from_url(&url);
+            let stem = Path::new(&filename)
+                .file_stem()
+                .map(|i| i.to_string_lossy().to_string())
+                .unwrap_or(filename);
+            let name = match name {
+                Some(name) => name,
+                None if config.name_template.is_some() => {
+                    naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), Some(&stem)))?
                 }
-                Err(e) => {
-                    return Err(e.into());
+                None => stem,
+            };
+            // --force might reuse it, and that one should stay if the download fails
+            let existing = manager.find(&name)?;
+            let Some(res) = created_entry(new_tempdir(&name).create()?) else {
+                return Ok(None);
+            };
+            let fetched = if no_cache {
+                downloads::fetch(&url, &res)
+            } else {
+                Cache::new(&home).fetch(&url, &res)
+            };
+            let file = match fetched {
+                Ok(file) => file,
+                // don't leave an empty tempdir behind, but keep the one --force reused
+                Err(e) if existing.as_ref() != Some(&res) => {
+                    discard(&res)?;
+                    return Err(e);
                 }
+                Err(e) => return Err(e),
             };
 
-            eprintln!("resolved download directory to {dl_dir:?}");
+            // we only know what's in it after downloading it
+            let res = match name_from_content.then(|| downloads::name_from_content(&file)).flatten() {
+                Some(better) if res.is_symlink() && better != name => {
+                    rename_entry(&manager, &res, &better)?.unwrap_or(res)
+                }
+                _ => res,
+            };
 
-            let mut max: Option<(SystemTime, PathBuf)> = None;
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Dl { name, r#move, last, glob, from, wait, choose, watch, timeout, url: None, name_from_content, .. }) => {
+            let dl_dir = downloads::downloads_dir(from.as_deref(), &config, &home)?;
 
-            for i in std::fs::read_dir(dl_dir)? {
-                let i = i?;
-                let Ok(meta) = i.metadata() else {
-                    eprintln!("couldn't read file metadata of {:?}; skipping", i.path());
-                    continue;
-                };
+            info!("resolved download directory to {dl_dir:?}");
 
-                if meta.is_file() {
-                    let Ok(created) = meta.created() else {
-                        eprintln!("couldn't read creation time of {:?}; skipping", i.path());
-                        continue;
-                    };
+            if watch {
+                let new = downloads::wait_for_new(&dl_dir, timeout)?;
+                info!("{new:?} finished downloading");
+            }
+            if wait {
+                downloads::wait_for_partial(&dl_dir, timeout)?;
+            }
 
-                    if let Some(ref mut max) = max {
-                        if created > max.0 {
-                            *max = (created, i.path());
-                        }
-                    } else {
-                        max = Some((created, i.path()));
-                    }
-                }
+            let pattern = glob
+                .as_deref()
+                .map(glob::Pattern::new)
+                .transpose()
+                .wrap_err("invalid glob pattern")?;
+
+            let mut candidates = downloads::recent_files(&dl_dir, config.dl_include_hidden, |path| {
+                pattern.as_ref().is_none_or(|i| i.matches(&path.file_name().unwrap_or_default().to_string_lossy()))
+            })?;
+            if choose && !choose_recent(&mut candidates, "which download?")? {
+                exit::set(Code::NothingToDo);
+                return Ok(None);
             }
 
-            let Some((_, most_recent_dl)) = max else {
-                eprintln!("no downloads");
-                exit(0);
+            // with only a glob we take every match, otherwise just the most recent one(s)
+            let count = match (last, &pattern) {
+                (Some(n), _) => n,
+                (None, Some(_)) => candidates.len(),
+                (None, None) => 1,
+            };
+            let downloads: Vec<PathBuf> = candidates
+                .into_iter()
+                .take(count)
+                .map(|(_, path)| path)
+                .collect();
+
+            let Some(most_recent_dl) = downloads.first() else {
+                error!("no downloads");
+                exit::set(Code::NotFound);
+                return Ok(None);
             };
 
-            eprintln!("most recently downloaded file: {:?}", most_recent_dl);
+            for i in &downloads {
+                info!("selected download: {:?}", i);
+            }
 
-            let filename = most_recent_dl.file_stem().expect("download has filename");
-            let name = name.unwrap_or_else(|| filename.to_string_lossy().to_string());
-            let res = create_tempdir(&tempdirs, name.as_ref(), &cwd, pwd.as_deref(), true)?;
+            let filename = most_recent_dl.file_stem().expect("download has filename").to_string_lossy().to_string();
+            let name = match name.or_else(|| name_from_content.then(|| downloads::name_from_content(most_recent_dl)).flatten()) {
+                Some(name) => name,
+                None if config.name_template.is_some() => {
+                    naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), Some(&filename)))?
+                }
+                None => filename,
+            };
+            let backing = backing_for_download(&backing, &downloads, &config, &home)?;
+            let Some(res) = created_entry(new_tempdir(&name).backing(&backing).seed(downloads, r#move).create()?) else {
+                return Ok(None);
+            };
 
-            if r#move{
-                fs_extra::move_items(
-                    &[most_recent_dl],
-                    &res,
-                    &CopyOptions::default(),
-                ).wrap_err("move file to tempdir")?;
-            } else {
-                fs_extra::copy_items(
-                    &[most_recent_dl],
-                    &res,
-                    &CopyOptions::default(),
-                ).wrap_err("move file to tempdir")?;
+            pipeline.run(&tempdirs, &config.hooks, res)?
+        }
+        Some(CliCommand::Shot { name, r#move, from, choose }) => {
+            let shots_dir = downloads::screenshots_dir(from.as_deref(), &config, &home)?;
+            info!("resolved screenshot directory to {shots_dir:?}");
+
+            let mut candidates = downloads::recent_files(&shots_dir, false, downloads::is_screenshot)?;
+            if choose && !choose_recent(&mut candidates, "which screenshot?")? {
+                exit::set(Code::NothingToDo);
+                return Ok(None);
             }
+            let Some((_, shot)) = candidates.into_iter().next() else {
+                error!("no screenshots in {shots_dir:?}");
+                exit::set(Code::NotFound);
+                return Ok(None);
+            };
+            info!("selected screenshot: {shot:?}");
+
+            // screenshots are called things like `Screenshot from 2024-05-01 12-00-00.png`
+            let stem = shot.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let name = match name {
+                Some(name) => name,
+                None if config.name_template.is_some() => {
+                    naming::new_name(&tempdirs, layout, &trash, &config, &naming_vars(&cwd, pwd.as_deref(), Some(&stem)))?
+                }
+                None => downloads::sanitize_name(&stem).unwrap_or(stem),
+            };
+            let Some(res) = created_entry(new_tempdir(&name).seed(vec![shot], r#move).create()?) else {
+                return Ok(None);
+            };
 
-            Some(res)
+            pipeline.run(&tempdirs, &config.hooks, res)?
         }
     };
 
-    // the path printed here is where we will cd to after
-    if let Some(i) = go_to {
-        println!("\n\n{}", i.to_string_lossy());
-    } else {
-        println!("\n\n{}", orig.to_string_lossy());
-    }
-    exit(0)
-}
-
-fn shell(tempdirs: &PathBuf, name: &String, cwd: &PathBuf, pwd: &Option<PathBuf>) -> Result<()> {
-    let res = create_tempdir(&tempdirs, &name, &cwd, pwd.as_deref(), true)?;
-    let mut shell = std::env::var("SHELL").wrap_err("shell envvar")?;
-    if shell.is_empty() && Path::new("/bin/zsh").exists() {
-        shell = "/bin/zsh".to_string();
-    }
-
-    if shell.is_empty() && Path::new("/bin/bash").exists() {
-        shell = "/bin/bash".to_string();
+    if let Some(entry) = go_to.as_deref().and_then(|i| layout.entry_containing(&tempdirs, i)) {
+        meta::touch(&entry)?;
+        zoxide::add(&entry);
     }
 
-    let mut cmd = Command::new(shell);
-    // this only sets the cd path which resolves symlinks
-    cmd.current_dir(&res);
-    // but most shells actually show what path you're in based on `pwd` and PWD
-    // so we also set that
-    cmd.env("PWD", &res);
-    let mut child = cmd.spawn().wrap_err("spawn shell")?;
-    child.wait().wrap_err("wait for child")?;
-
-    if res.is_symlink() {
-        // find the symlink target
-        let target = std::fs::read_link(&res).wrap_err("read link")?;
-        // unlink the link so only the /tmp/... remains
-        symlink::remove_symlink_auto(&res).wrap_err("unlink")?;
-        // remove the /tmp/... dir too
-        std::fs::remove_dir_all(&target).wrap_err("remove dir")?;
+    if config.usage_summary && !read_only {
+        info!("{}", usage::summary(&tempdirs, &layout.entries(&tempdirs)?)?);
     }
 
-    Ok(())
+    Ok(go_to)
 }
 
-pub fn rename(old: &Path, new: &Path) -> Result<bool> {
-    if new.exists() {
-        eprintln!("can't rename to {new:?} because it already exists");
-        return Ok(false);
-    }
+/// The name for a tempdir that's made from a directory that exists already, so not by [`TempdirBuilder`],
+/// following `on_collision` like it does. `None` if the name is taken and it should stay that way
+fn free_name(tempdirs: &Path, layout: Layout, name: String, on_collision: OnCollision) -> Result<Option<String>> {
+    let Some(existing) = layout.find(tempdirs, &name)? else {
+        return Ok(Some(name));
+    };
 
-    if !old.is_symlink() {
-        // if it's a folder, rename normally
-        eprintln!("renaming persistent tempdir {old:?} to {new:?}");
-        std::fs::rename(old, new).wrap_err("rename")?;
-    } else {
-        eprintln!("renaming tempdir {old:?} to {new:?}");
-        // else unlink and create a new link
-        let target = std::fs::read_link(old).wrap_err("read link")?;
-        symlink::remove_symlink_auto(old).wrap_err("unlink old")?;
-        symlink::symlink_auto(target, new).wrap_err("symlink new")?;
+    match on_collision {
+        OnCollision::Fail => {
+            error!("{existing:?} already exists (specify a different name, or use --unique or --force)");
+            exit::set(Code::AlreadyExists);
+            Ok(None)
+        }
+        OnCollision::Unique => {
+            let unique = builder::unique_name(tempdirs, layout, &name)?;
+            info!("{name:?} is taken, using {unique:?} instead");
+            Ok(Some(unique))
+        }
+        OnCollision::Reuse => color_eyre::eyre::bail!(
+            "{existing:?} already exists and --force can't put another directory there, use --unique or a different name"
+        ),
     }
-    Ok(true)
 }
 
-pub fn delete(path: &Path) -> Result<()> {
-    if path.is_symlink() {
-        eprintln!("deleting {:?}", path);
-        symlink::remove_symlink_auto(path).wrap_err(format!("remove symlink {:?}", path))?;
-    } else {
-        eprintln!("deleting {:?} (persistent)", path);
-        std::fs::remove_dir_all(path)?;
+/// Let the user pick one of the 10 most recent `candidates`, leaving only that one.
+/// Returns whether they picked one. There's nothing to pick from when there are no candidates, that's not a no
+fn choose_recent(candidates: &mut Vec<(SystemTime, PathBuf)>, question: &str) -> Result<bool> {
+    if candidates.is_empty() {
+        return Ok(true);
     }
-
-    Ok(())
+    candidates.truncate(10);
+    let labels: Vec<_> = candidates
+        .iter()
+        .map(|(created, path)| {
+            let ago = SystemTime::now().duration_since(*created).unwrap_or_default();
+            let size = std::fs::metadata(path).map(|i| i.len()).unwrap_or_default();
+            format!("{} ({}, {} ago)", path.file_name().unwrap_or_default().to_string_lossy(), usage::human_size(size), age::human(ago))
+        })
+        .collect();
+    let Some(chosen) = prompt::select(question, &labels, 0)? else {
+        return Ok(false);
+    };
+    *candidates = vec![candidates.swap_remove(chosen)];
+    Ok(true)
 }
 
-pub fn active_tempdirs(tempdirs: &Path) -> Result<()> {
-    let mut first = true;
-    for i in std::fs::read_dir(tempdirs).wrap_err(format!("read {tempdirs:?}"))? {
-        let i = i.wrap_err("read direntry")?;
-        if first {
-            eprintln!("active tempdirs:");
-            first = false;
+/// Pin or unpin the named or current tempdir
+fn set_pinned(tempdirs: &Path, layout: Layout, name: Option<String>, cwd: &Path, pwd: Option<&Path>, pin: bool) -> Result<()> {
+    match named_or_current(tempdirs, layout, name, cwd, pwd)? {
+        None => {
+            error!("not in a tempdir and no tempdir specified");
+            exit::set(Code::NotFound);
         }
-
-        if i.path().is_symlink() {
-            eprintln!("{}", i.path().to_string_lossy());
-        } else {
-            eprintln!("{} (persistent)", i.path().to_string_lossy());
+        Some(entry) if !entry.exists() => {
+            error!("{entry:?} doesn't exist");
+            exit::set(Code::NotFound);
         }
-    }
-
-    if first {
-        eprintln!("no active tempdirs");
-    }
-
-    Ok(())
-}
-
-pub fn in_tempdir(tempdirs: &Path, cwd: &Path, pwd: Option<&Path>) -> Result<Option<PathBuf>> {
-    let tmp = std::env::temp_dir();
-
-    fn find_parent(path: &Path, tmp: &Path, tempdirs: &Path) -> Result<Option<PathBuf>> {
-        if let Some(i) = path.parent() {
-            if i == tmp || i == tempdirs {
-                Ok(Some(path.to_path_buf()))
+        Some(entry) => {
+            let mut meta = Meta::load(&entry)?;
+            meta.pinned = pin;
+            meta.save(&entry)?;
+            if pin {
+                info!("pinned {entry:?}, bulk deletes will leave it alone");
             } else {
-                find_parent(i, tmp, tempdirs)
+                info!("unpinned {entry:?}");
             }
-        } else {
-            Ok(None)
         }
     }
 
-    if let Some(pwd) = pwd {
-        for part in &pwd.canonicalize().wrap_err("canonicalize pwd")? {
-            if part.to_string_lossy().starts_with(TEMPDIR_PREFIX) {
-                return find_parent(pwd, &tmp, tempdirs);
-            }
-        }
+    Ok(())
+}
 
+/// Warn when the tempdirs use more than 80% of `max` bytes,
+/// and refuse to make new ones when they're over it unless the user says otherwise
+fn check_quota(used: u64, max: u64, creating: bool) -> Result<()> {
+    let usage = format!("tempdirs use {} of max_total_size {}", usage::human_size(used), usage::human_size(max));
 
-        if pwd.starts_with(tempdirs) {
-            if let Some(first) = pwd.strip_prefix(tempdirs).wrap_err("strip prefix")?.iter().next() {
-                return Ok(Some(tempdirs.join(first)));
-            }
+    if used <= max {
+        if used * 5 > max * 4 {
+            warn(Kind::Quota, format!("{usage}, consider `t gc`"));
         }
+        return Ok(());
+    }
+    if !creating {
+        warn(Kind::Quota, format!("{usage}, free up space with `t gc`"));
+        return Ok(());
     }
 
-    for part in cwd {
-        if part.to_string_lossy().starts_with(TEMPDIR_PREFIX) {
-            return find_parent(cwd, &tmp, tempdirs);
-        }
+    if prompt::confirm(&format!("{usage}, create a new one anyway?"), false)? {
+        return Ok(());
     }
 
-    Ok(None)
+    color_eyre::eyre::bail!("{usage}, free up space with `t gc` first")
 }
 
-pub fn new_name(path: &Path) -> Result<String> {
-    let mut highest_unnamed = 0;
-    for i in std::fs::read_dir(path).wrap_err(format!("read {path:?}"))? {
-        let i = i.wrap_err("read direntry")?;
-        if let Some(rest) = i.file_name().to_string_lossy().strip_prefix("unnamed_") {
-            if let Ok(i) = rest.parse::<usize>() {
-                highest_unnamed = highest_unnamed.max(i);
-            }
-        }
+/// List `entries` and ask whether to delete them, unless `yes` says not to ask
+fn confirm_delete(tempdirs: &Path, entries: &[PathBuf], yes: bool) -> Result<bool> {
+    if yes || entries.is_empty() {
+        return Ok(true);
     }
 
-    loop {
-        let name = format!("unnamed_{}", highest_unnamed + 1);
-        if path.join(&name).exists() {
-            highest_unnamed += 1;
-            continue;
-        }
-
-        return Ok(name);
+    for i in entries {
+        eprintln!("    {}", i.strip_prefix(tempdirs).unwrap_or(i).to_string_lossy());
     }
+    let question = match entries.len() {
+        1 => "delete this tempdir?".to_string(),
+        n => format!("delete these {n} tempdirs?"),
+    };
+    prompt::confirm(&question, false)
 }
 
-pub fn delete_all(tempdirs: &Path) -> Result<PathBuf> {
-    for i in std::fs::read_dir(tempdirs).wrap_err(format!("read {tempdirs:?}"))? {
-        let i = i.wrap_err("read direntry")?;
-        if i.metadata().wrap_err("get direntry metadata")?.is_symlink() {
-            symlink::remove_symlink_auto(i.path()).wrap_err(format!("remove symlink {:?}", i.path()))?;
-            eprintln!("deleting {:?}", i.path());
-        }
+/// The tempdir to cd into, or `None` after saying that its name was taken
+fn created_entry(created: Created) -> Option<PathBuf> {
+    if let Created::Taken(ref existing) = created {
+        error!("{existing:?} already exists (specify a different name, or use --unique or --force)");
+        exit::set(Code::AlreadyExists);
     }
+    created.entry()
+}
 
-
-    Ok(tempdirs.to_path_buf())
+/// Persist the tempdir at `entry`, or say it already was
+fn persist_entry(manager: &TempdirManager, entry: &Path, gitignore: bool, only: &[glob::Pattern]) -> Result<()> {
+    if !manager.persist(entry, gitignore, only)? {
+        info!("{entry:?} was already persistent");
+        exit::set(Code::NothingToDo);
+    }
+    Ok(())
 }
 
-pub fn create_tempdir(tempdirs: &Path, name: &str, cwd: &Path, pwd: Option<&Path>, symlink: bool) -> Result<PathBuf> {
-    let symlink_path = tempdirs.join(name);
+/// Rename the tempdir at `entry` to `to`, returning its new entry, or `None` after saying that name is taken
+fn rename_entry(manager: &TempdirManager, entry: &Path, to: &str) -> Result<Option<PathBuf>> {
+    let res = manager.rename(entry, to)?;
+    if res.is_none() {
+        error!("can't rename to {:?} because it already exists", entry.with_file_name(to));
+        exit::set(Code::AlreadyExists);
+    }
+    Ok(res)
+}
 
-    if symlink_path.exists() {
-        eprintln!("{:?} already exists (specify a different name)", symlink_path);
-        return Ok(pwd.unwrap_or(cwd).to_path_buf());
+/// The variables for name templates, with `download` the name of the file for `t dl`
+fn naming_vars(cwd: &Path, pwd: Option<&Path>, download: Option<&str>) -> naming::Vars {
+    naming::Vars {
+        slug: download.and_then(downloads::sanitize_name),
+        origin: pwd.unwrap_or(cwd).file_name().map(|i| i.to_string_lossy().to_string()),
     }
+}
 
-    let dir = tempdir::TempDir::new(TEMPDIR_PREFIX).wrap_err("create temp dir")?.into_path();
+/// Where to put a tempdir for `downloads`: big downloads don't go on a tmpfs, where they'd fill up memory
+fn backing_for_download(backing: &Backing, downloads: &[PathBuf], config: &Config, home: &Path) -> Result<Backing> {
+    #[cfg(target_os = "linux")]
+    let on_tmpfs = backing.tmpfs || tmpfs::is_tmpfs(&backing.dir);
+    #[cfg(not(target_os = "linux"))]
+    let on_tmpfs = backing.tmpfs;
+    if !on_tmpfs {
+        return Ok(backing.clone());
+    }
 
-    Ok(if symlink {
-        eprintln!("cding into {symlink_path:?}");
-        symlink::symlink_auto(dir, &symlink_path).wrap_err("create symlink")?;
+    let size: u64 = downloads.iter().filter_map(|i| i.metadata().ok()).map(|i| i.len()).sum();
+    if size <= config.dl_tmpfs_limit()? {
+        return Ok(backing.clone());
+    }
 
-        symlink_path
+    let disk = config.disk_temp_dir(home);
+    // `--tmpfs` asks for a tmpfs explicitly, so only ask instead of overriding it
+    let use_disk = if backing.tmpfs {
+        prompt::confirm(&format!("the download is {}, put it in {disk:?} instead of on a tmpfs?", usage::human_size(size)), true)?
     } else {
-        eprintln!("cding into {dir:?}");
+        info!("the download is {}, putting it in {disk:?} instead of on a tmpfs", usage::human_size(size));
+        true
+    };
 
-        dir
+    Ok(if use_disk {
+        Backing { dir: disk, tmpfs: false, size: None, mode: backing.mode, secret: None }
+    } else {
+        backing.clone()
     })
-}
\ No newline at end of file
+}
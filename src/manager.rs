@@ -0,0 +1,206 @@
+use std::path::{Path, PathBuf};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::builder::{Created, TempdirBuilder};
+use crate::config::Config;
+use crate::hooks::{Hook, Hooks};
+use crate::journal::Journal;
+use crate::layout::Layout;
+use crate::meta::Meta;
+use crate::trash::Trash;
+use crate::{delete, persist, rename, resolve_name, Backing};
+
+/// The operations behind the `t` command, for other tools that want to manage tempdirs.
+/// They return what they did instead of printing it, though progress still goes to stderr.
+pub struct TempdirManager {
+    tempdirs: PathBuf,
+    layout: Layout,
+    backing: Backing,
+    trash: Trash,
+    journal: Journal,
+    hooks: Hooks,
+}
+
+/// A tempdir in the tempdirs root, as returned by [`TempdirManager::list`]
+#[derive(Debug, Clone)]
+pub struct Tempdir {
+    pub name: String,
+    /// its entry in the tempdirs root
+    pub entry: PathBuf,
+    /// where its files are, which is the entry itself for persistent tempdirs
+    pub path: PathBuf,
+    pub persistent: bool,
+    pub meta: Meta,
+}
+
+impl TempdirManager {
+    /// Manage the tempdirs in `tempdirs`, with everything about them known already, like in the `t` command
+    pub fn new(tempdirs: PathBuf, layout: Layout, backing: Backing, trash: Trash, hooks: Hooks) -> Self {
+        Self {
+            journal: Journal::new(&tempdirs),
+            tempdirs,
+            layout,
+            backing,
+            trash,
+            hooks,
+        }
+    }
+
+    /// The tempdirs of `profile` (or the default ones), set up like the config in `home` says
+    pub fn open(home: &Path, profile: Option<&str>) -> Result<Self> {
+        let config = Config::load(home)?;
+        let tempdirs = config.tempdirs(home, profile);
+        std::fs::create_dir_all(&tempdirs).wrap_err(format!("create tempdirs ({tempdirs:?})"))?;
+
+        let backing = Backing {
+            dir: config.backing_dir(home, profile),
+            tmpfs: false,
+            size: None,
+            mode: config.backing_mode()?,
+            secret: None,
+        };
+        let trash = Trash::new(home, &config)?;
+        Ok(Self::new(tempdirs, config.layout, backing, trash, config.hooks))
+    }
+
+    pub fn tempdirs(&self) -> &Path {
+        &self.tempdirs
+    }
+
+    /// Start making a tempdir called `name`, for more control than [`create`](Self::create) gives
+    pub fn new_tempdir(&self, name: &str) -> TempdirBuilder<'_> {
+        TempdirBuilder::new(&self.tempdirs, self.layout, name, &self.backing).hooks(&self.hooks)
+    }
+
+    /// Create a tempdir called `name`. If the name is taken, that's [`Created::Taken`]
+    pub fn create(&self, name: &str) -> Result<Created> {
+        self.new_tempdir(name).origin(&self.tempdirs).create()
+    }
+
+    /// The entry of the tempdir called `name`, if there is one
+    pub fn find(&self, name: &str) -> Result<Option<PathBuf>> {
+        self.layout.find(&self.tempdirs, name)
+    }
+
+    /// The entry of the tempdir called `name`, or the only one it's a prefix or fuzzy match of.
+    /// If there's none, that's where it would be, which doesn't exist
+    pub fn resolve(&self, name: &str) -> Result<PathBuf> {
+        resolve_name(&self.tempdirs, self.layout, name)
+    }
+
+    /// Every tempdir in the tempdirs root, sorted by name
+    pub fn list(&self) -> Result<Vec<Tempdir>> {
+        let mut res = self.layout
+            .entries(&self.tempdirs)?
+            .into_iter()
+            .map(|entry| {
+                let meta = Meta::load(&entry)?;
+                Ok(Tempdir {
+                    name: entry.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                    path: std::fs::read_link(&entry).unwrap_or_else(|_| entry.clone()),
+                    persistent: meta.persistent(&entry),
+                    entry,
+                    meta,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        res.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(res)
+    }
+
+    /// Persist the tempdir at `entry`, so it survives reboots. With `gitignore` or `only`, just the files that
+    /// aren't ignored or do match are kept. Returns `false` if it was persistent already
+    pub fn persist(&self, entry: &Path, gitignore: bool, only: &[glob::Pattern]) -> Result<bool> {
+        if !persist(entry, gitignore, only, &self.journal)? {
+            return Ok(false);
+        }
+        self.hooks.run(Hook::Persist, entry)?;
+
+        Ok(true)
+    }
+
+    /// Rename the tempdir at `entry` to `to`, returning its new entry, or `None` if that name is taken
+    pub fn rename(&self, entry: &Path, to: &str) -> Result<Option<PathBuf>> {
+        let new = entry.with_file_name(to);
+        Ok(rename(entry, &new, &self.journal)?.then_some(new))
+    }
+
+    /// Delete the tempdir at `entry`, moving it to the trash
+    pub fn delete(&self, entry: &Path) -> Result<()> {
+        self.hooks.run(Hook::Delete, entry)?;
+        delete(entry, &self.trash, &self.journal)?;
+        self.layout.tidy(&self.tempdirs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(root: &Path) -> TempdirManager {
+        let tempdirs = root.join("tempdirs");
+        std::fs::create_dir_all(&tempdirs).unwrap();
+        let backing = Backing { dir: root.join("backing"), tmpfs: false, size: None, mode: 0o700, secret: None };
+        TempdirManager::new(tempdirs, Layout::Flat, backing, Trash::new(root, &Config::default()).unwrap(), Hooks::default())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lists_tempdirs() {
+        let dir = tempdir::TempDir::new("t-rs-test-manager").unwrap();
+        let root = dir.path();
+        let manager = manager(root);
+        let tempdirs = manager.tempdirs().to_path_buf();
+        let backing = root.join("backing");
+        std::fs::create_dir_all(tempdirs.join("kept")).unwrap();
+        std::fs::create_dir_all(&backing).unwrap();
+        std::os::unix::fs::symlink(&backing, tempdirs.join("scratch")).unwrap();
+
+        let listed = manager.list().unwrap();
+        assert_eq!(listed.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), ["kept", "scratch"]);
+        assert!(listed[0].persistent);
+        assert_eq!(listed[1].path, backing);
+        assert_eq!(manager.find("scratch").unwrap(), Some(tempdirs.join("scratch")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn creates_and_renames() {
+        let dir = tempdir::TempDir::new("t-rs-test-manager").unwrap();
+        let manager = manager(dir.path());
+
+        let Created::New(foo) = manager.create("foo").unwrap() else {
+            panic!("foo wasn't created");
+        };
+        assert!(foo.is_symlink());
+        assert_eq!(manager.create("foo").unwrap(), Created::Taken(foo.clone()));
+
+        manager.create("bar").unwrap();
+        assert_eq!(manager.rename(&foo, "bar").unwrap(), None);
+        let baz = manager.rename(&foo, "baz").unwrap().unwrap();
+        assert_eq!(manager.find("foo").unwrap(), None);
+        assert_eq!(manager.resolve("baz").unwrap(), baz);
+        // `ba` could be bar or baz
+        assert!(manager.resolve("ba").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn persists_and_deletes() {
+        let dir = tempdir::TempDir::new("t-rs-test-manager").unwrap();
+        let manager = manager(dir.path());
+        let foo = manager.create("foo").unwrap().entry().unwrap();
+        let bar = manager.create("bar").unwrap().entry().unwrap();
+        std::fs::write(foo.join("notes.txt"), "hi").unwrap();
+
+        assert!(manager.persist(&foo, false, &[]).unwrap());
+        assert!(!foo.is_symlink());
+        assert_eq!(std::fs::read_to_string(foo.join("notes.txt")).unwrap(), "hi");
+        assert!(!manager.persist(&foo, false, &[]).unwrap());
+
+        manager.delete(&bar).unwrap();
+        assert_eq!(manager.find("bar").unwrap(), None);
+        assert_eq!(manager.list().unwrap().iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), ["foo"]);
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+use clap::Command;
+use clap_complete::Shell;
+use clap_mangen::Man;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::info;
+
+/// Write man pages for `cmd` and each of its subcommands (like `t-rs-dl.1`), and static completion scripts
+/// for every shell clap can complete, into `dir`. For packaging, the `t` wrapper has its own completion
+pub fn generate(mut cmd: Command, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+    let bin_name = cmd.get_name().to_string();
+
+    write_man(cmd.clone(), &dir.join(format!("{bin_name}.1")))?;
+    for sub in cmd.get_subcommands().filter(|i| !i.is_hide_set()) {
+        let name = format!("{bin_name}-{}", sub.get_name());
+        write_man(sub.clone().name(name.clone()), &dir.join(format!("{name}.1")))?;
+    }
+
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell, Shell::Elvish] {
+        let path = clap_complete::generate_to(shell, &mut cmd, &bin_name, dir).wrap_err(format!("write {shell:?} completions"))?;
+        info!("wrote {path:?}");
+    }
+    Ok(())
+}
+
+fn write_man(cmd: Command, path: &Path) -> Result<()> {
+    let mut page = Vec::new();
+    Man::new(cmd).render(&mut page).wrap_err(format!("render {path:?}"))?;
+    std::fs::write(path, page).wrap_err(format!("write {path:?}"))?;
+    info!("wrote {path:?}");
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use color_eyre::eyre::{Context, ContextCompat};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::warnings::{warn, Kind};
+use crate::STATE_DIR;
+
+const META: &str = "meta";
+
+/// Things we remember about a tempdir that can't be derived from the filesystem.
+///
+/// Stored in `<tempdirs>/.t-rs/meta/<name>.toml`, and moved or removed together with the tempdir.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Meta {
+    /// free-form description set with `t note`
+    pub note: Option<String>,
+    /// when the tempdir was created, in seconds since the unix epoch
+    pub created: Option<u64>,
+    /// set with `t tag`
+    pub tags: Vec<String>,
+    /// the directory `t` was run from when the tempdir was created
+    pub origin: Option<PathBuf>,
+    /// pinned tempdirs are left alone by bulk deletes like `t gc`
+    pub pinned: bool,
+    /// set with `t env set`, and set in shells started in the tempdir
+    pub env: BTreeMap<String, String>,
+    /// whether the tempdir was persisted. Without this, persistent tempdirs are the ones that aren't symlinks
+    pub persistent: Option<bool>,
+    /// when the tempdir was last entered or operated on, in seconds since the unix epoch
+    pub last_used: Option<u64>,
+    /// the machine the tempdir was created on, for tempdirs roots that are synced between machines
+    pub host: Option<String>,
+    /// the last task run with `t task`, which runs again when no task is given
+    pub task: Option<String>,
+    /// where the tempdir was last copied with `t push`, which pushes there again when no target is given
+    pub pushed_to: Option<String>,
+    /// when `t gc` may delete the tempdir whatever its age, set with `--ttl`. In seconds since the unix epoch
+    pub expires: Option<u64>,
+    /// how many bytes the tempdir should hold at most, set with `--max-size`
+    pub max_size: Option<u64>,
+}
+
+/// Where the metadata of the tempdir at `entry` (a path in the tempdirs root) is stored
+fn meta_path(entry: &Path) -> Result<PathBuf> {
+    let tempdirs = entry.parent().wrap_err(format!("{entry:?} has no parent"))?;
+    let name = entry.file_name().wrap_err(format!("{entry:?} has no name"))?;
+
+    let mut file_name = name.to_os_string();
+    file_name.push(".toml");
+    Ok(tempdirs.join(STATE_DIR).join(META).join(file_name))
+}
+
+impl Meta {
+    /// Load the metadata for the tempdir at `entry`.
+    /// Tempdirs without metadata, or with metadata we can't make sense of, get the default.
+    pub fn load(entry: &Path) -> Result<Self> {
+        let path = meta_path(entry)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path).wrap_err(format!("read metadata {path:?}"))?;
+        match toml::from_str(&contents) {
+            Ok(meta) => Ok(meta),
+            Err(_) => {
+                warn(Kind::Unreadable, format!("metadata {path:?}"));
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Whether the tempdir at `entry` is persistent, going by the metadata and otherwise by whether it's a symlink
+    pub fn persistent(&self, entry: &Path) -> bool {
+        self.persistent.unwrap_or_else(|| !entry.is_symlink())
+    }
+
+    /// Whether the tempdir was created on another machine.
+    /// Its backing directory is on that machine, so it's not ours to clean up.
+    pub fn foreign(&self) -> bool {
+        self.host.as_ref().is_some_and(|host| Some(host) != hostname().as_ref())
+    }
+
+    /// Whether the `--ttl` of the tempdir has run out
+    pub fn expired(&self) -> bool {
+        self.expires.is_some_and(|i| i <= crate::age::now())
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|i| i == tag)
+    }
+
+    pub fn save(&self, entry: &Path) -> Result<()> {
+        let path = meta_path(entry)?;
+        let dir = path.parent().wrap_err("metadata path has no parent")?;
+        std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+
+        let contents = toml::to_string(self).wrap_err("serialize metadata")?;
+        std::fs::write(&path, contents).wrap_err(format!("write metadata {path:?}"))
+    }
+}
+
+/// The name of this machine, if we can find out
+pub fn hostname() -> Option<String> {
+    static HOSTNAME: OnceLock<Option<String>> = OnceLock::new();
+    HOSTNAME
+        .get_or_init(|| {
+            let output = Command::new("hostname").output().ok()?;
+            let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (output.status.success() && !name.is_empty()).then_some(name)
+        })
+        .clone()
+}
+
+/// Remember that the tempdir at `entry` was just used
+pub fn touch(entry: &Path) -> Result<()> {
+    // don't leave metadata behind for tempdirs that don't exist (anymore)
+    if entry.symlink_metadata().is_err() {
+        return Ok(());
+    }
+
+    let mut meta = Meta::load(entry)?;
+    meta.last_used = Some(crate::age::now());
+    meta.save(entry)
+}
+
+/// Move the metadata along when a tempdir is renamed
+pub fn rename(old: &Path, new: &Path) -> Result<()> {
+    let old = meta_path(old)?;
+    if old.exists() {
+        std::fs::rename(&old, meta_path(new)?).wrap_err(format!("move metadata {old:?}"))?;
+    }
+
+    Ok(())
+}
+
+/// Whether there's metadata for `entry`, so that t-rs knows it as a tempdir
+pub fn exists(entry: &Path) -> bool {
+    meta_path(entry).is_ok_and(|i| i.exists())
+}
+
+/// Forget everything about a tempdir that's being deleted
+pub fn remove(entry: &Path) -> Result<()> {
+    let path = meta_path(entry)?;
+    if path.exists() {
+        std::fs::remove_file(&path).wrap_err(format!("remove metadata {path:?}"))?;
+    }
+
+    Ok(())
+}
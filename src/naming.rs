@@ -0,0 +1,168 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use serde::Deserialize;
+use crate::config::Config;
+use crate::layout::Layout;
+use crate::trash::Trash;
+
+/// How tempdirs created without `--name` are named, set with `naming` in the config
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Naming {
+    /// `unnamed_1`, `unnamed_2`, ...
+    #[default]
+    Numeric,
+    /// like `brave-otter`
+    Words,
+    /// `2024-06-01`, then `2024-06-01-2`, ...
+    Date,
+}
+
+const ADJECTIVES: &[&str] = &[
+    "agile", "bold", "brave", "bright", "calm", "clever", "cosy", "crisp", "curious", "daring",
+    "eager", "fancy", "fuzzy", "gentle", "happy", "jolly", "keen", "lively", "lucky", "mellow",
+    "merry", "nimble", "plucky", "proud", "quick", "quiet", "shiny", "sleepy", "snappy", "sunny",
+    "swift", "tidy", "witty", "zesty",
+];
+
+const NOUNS: &[&str] = &[
+    "badger", "beaver", "bison", "crane", "dingo", "falcon", "ferret", "finch", "gecko", "heron",
+    "ibis", "koala", "lemur", "lynx", "marmot", "mole", "newt", "ocelot", "otter", "owl",
+    "panda", "puffin", "quokka", "raven", "robin", "salmon", "seal", "stoat", "tapir", "toucan",
+    "walrus", "wombat", "yak", "zebra",
+];
+
+/// What a `name_template` can refer to, besides `{date}` and `{counter}`
+#[derive(Default)]
+pub struct Vars {
+    /// `{slug}`: the name of the download for `t dl`, otherwise `unnamed-<counter>`
+    pub slug: Option<String>,
+    /// `{origin}`: the name of the directory `t` was run from
+    pub origin: Option<String>,
+}
+
+/// How many random word pairs to try before adding a number to one
+const WORD_ATTEMPTS: usize = 32;
+
+/// A name for a new tempdir that isn't taken, by a tempdir in any group or one in the trash.
+///
+/// It comes from `name_template` in the config if that's set, and is picked according to `naming` otherwise.
+pub fn new_name(tempdirs: &Path, layout: Layout, trash: &Trash, config: &Config, vars: &Vars) -> Result<String> {
+    let trashed = trash.list()?;
+    let taken = |name: &str| -> Result<bool> {
+        Ok(layout.find(tempdirs, name)?.is_some() || trashed.iter().any(|i| i.entry.file_name().is_some_and(|i| *i == *name)))
+    };
+
+    if let Some(ref template) = config.name_template {
+        // templates without a counter still need one when the name is taken
+        let uses_counter = expand(template, vars, 1)? != expand(template, vars, 2)?;
+        for counter in 1.. {
+            let name = expand(template, vars, counter)?;
+            let name = if uses_counter || counter == 1 { name } else { format!("{name}-{counter}") };
+            if !taken(&name)? {
+                return Ok(name);
+            }
+        }
+    }
+
+    match config.naming {
+        Naming::Numeric => {
+            let mut highest_unnamed = 0;
+            for i in layout.entries(tempdirs)? {
+                if let Some(rest) = i.file_name().unwrap_or_default().to_string_lossy().strip_prefix("unnamed_") {
+                    if let Ok(i) = rest.parse::<usize>() {
+                        highest_unnamed = highest_unnamed.max(i);
+                    }
+                }
+            }
+
+            first_free((highest_unnamed + 1..).map(|i| format!("unnamed_{i}")), taken)
+        }
+        Naming::Words => {
+            let word = |words: &[&'static str]| words[random() as usize % words.len()];
+            let pair = || format!("{}-{}", word(ADJECTIVES), word(NOUNS));
+
+            let attempts = std::iter::repeat_with(pair).take(WORD_ATTEMPTS);
+            let base = pair();
+            let numbered = (2..).map(move |i| format!("{base}-{i}"));
+            first_free(attempts.chain(numbered), taken)
+        }
+        Naming::Date => {
+            let base = chrono::Local::now().format("%Y-%m-%d").to_string();
+            let numbered = (2..).map(|i| format!("{base}-{i}"));
+            first_free(std::iter::once(base.clone()).chain(numbered), taken)
+        }
+    }
+}
+
+/// Fill in the variables in a name template like `{date}-{slug}`
+fn expand(template: &str, vars: &Vars, counter: usize) -> Result<String> {
+    let mut res = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        res.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            bail!("unclosed {{ in name template {template:?}");
+        };
+
+        let value = match &rest[start + 1..start + len] {
+            "date" => chrono::Local::now().format("%Y-%m-%d").to_string(),
+            "counter" => counter.to_string(),
+            "slug" => vars.slug.clone().unwrap_or_else(|| format!("unnamed-{counter}")),
+            "origin" => vars.origin.clone().unwrap_or_else(|| "root".to_string()),
+            other => bail!("unknown variable {{{other}}} in name template {template:?}, expected date, counter, slug or origin"),
+        };
+        res.push_str(&value);
+        rest = &rest[start + len + 1..];
+    }
+    res.push_str(rest);
+
+    if res.is_empty() || res.contains('/') {
+        bail!("name template {template:?} gives {res:?}, which can't be a name");
+    }
+    Ok(res)
+}
+
+/// The first of `candidates` that isn't `taken`
+fn first_free(candidates: impl Iterator<Item = String>, taken: impl Fn(&str) -> Result<bool>) -> Result<String> {
+    for name in candidates {
+        if !taken(&name)? {
+            return Ok(name);
+        }
+    }
+
+    unreachable!("there are infinitely many candidates")
+}
+
+/// A random number, good enough to pick words with. Every `RandomState` is seeded differently.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_templates() {
+        let vars = Vars { slug: Some("report".to_string()), origin: Some("src".to_string()) };
+        assert_eq!(expand("{slug}-{counter}", &vars, 3).unwrap(), "report-3");
+        assert_eq!(expand("from-{origin}", &vars, 1).unwrap(), "from-src");
+        assert_eq!(expand("{slug}", &Vars::default(), 2).unwrap(), "unnamed-2");
+        assert!(expand("{nope}", &vars, 1).is_err());
+        assert!(expand("{slug", &vars, 1).is_err());
+        assert!(expand("", &vars, 1).is_err());
+    }
+
+    #[test]
+    fn skips_taken_names() {
+        let candidates = ["a", "b", "c"].map(String::from).into_iter();
+        assert_eq!(first_free(candidates, |i| Ok(i != "c")).unwrap(), "c");
+
+        let numbered = (1..).map(|i| format!("unnamed_{i}"));
+        assert_eq!(first_free(numbered, |i| Ok(i == "unnamed_1" || i == "unnamed_2")).unwrap(), "unnamed_3");
+    }
+}
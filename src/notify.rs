@@ -0,0 +1,22 @@
+use std::path::{Path, PathBuf};
+use notify_rust::Notification;
+
+/// Show a desktop notification.
+/// Notifications are a nicety, so not being able to show one is never an error
+pub fn send(summary: &str, body: &str) {
+    let _ = Notification::new().appname("t-rs").summary(summary).body(body).show();
+}
+
+/// Tell the user about tempdirs that were removed without them deleting them, saying `why` and how to get them back
+pub fn removed(tempdirs: &Path, entries: &[PathBuf], why: &str, restore: &str) {
+    if entries.is_empty() {
+        return;
+    }
+
+    let names: Vec<_> = entries.iter().map(|i| i.strip_prefix(tempdirs).unwrap_or(i).to_string_lossy()).collect();
+    let summary = match entries.len() {
+        1 => "t-rs removed a tempdir".to_string(),
+        n => format!("t-rs removed {n} tempdirs"),
+    };
+    send(&summary, &format!("{} ({why}). {restore}", names.join(", ")));
+}
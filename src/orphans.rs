@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::journal::{Journal, Op};
+use crate::layout::Layout;
+use crate::meta::{self, Meta};
+use crate::registry::Registry;
+use crate::{age, info, output, platform, TEMPDIR_PREFIX};
+
+/// Backing directories in `locations` that nothing points to anymore, usually because their symlink was removed by hand.
+/// They'd stay around unseen until the next reboot, or forever on disk
+pub fn find(tempdirs: &Path, layout: Layout, home: &Path, locations: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut known: HashSet<PathBuf> = layout.entries(tempdirs)?.iter().filter_map(|i| std::fs::read_link(i).ok()).collect();
+    // the registry also knows about hidden tempdirs and the ones of other profiles
+    known.extend(Registry::new(home).load()?.tempdirs.into_iter().filter_map(|i| i.backing));
+
+    Ok(unlinked(locations, &known)
+        .into_iter()
+        .filter(|i| platform::same_owner(i, home))
+        .collect())
+}
+
+/// The backing directories directly in `locations` that aren't in `known`
+fn unlinked(locations: &[PathBuf], known: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let canonical = |i: &Path| i.canonicalize().unwrap_or_else(|_| i.to_path_buf());
+    let known: HashSet<_> = known.iter().map(|i| canonical(i)).collect();
+
+    let mut res = Vec::new();
+    for location in locations {
+        let Ok(entries) = std::fs::read_dir(location) else {
+            continue;
+        };
+        for i in entries.flatten() {
+            let path = i.path();
+            let is_backing_dir = i.file_name().to_string_lossy().starts_with(TEMPDIR_PREFIX) && i.file_type().is_ok_and(|t| t.is_dir());
+            if is_backing_dir && !known.contains(&canonical(&path)) {
+                res.push(path);
+            }
+        }
+    }
+    res.sort();
+    res
+}
+
+/// Make a tempdir called `name` out of the orphaned backing directory `orphan` again
+pub fn relink(tempdirs: &Path, layout: Layout, orphan: &Path, name: &str) -> Result<PathBuf> {
+    let parent = layout.dir(tempdirs, None);
+    std::fs::create_dir_all(&parent).wrap_err(format!("create {parent:?}"))?;
+    let entry = parent.join(name);
+
+    platform::link_dir(orphan, &entry).wrap_err("create symlink")?;
+    Meta {
+        created: age::created(orphan).ok().map(age::to_unix),
+        host: meta::hostname(),
+        ..Default::default()
+    }.save(&entry)?;
+    Journal::new(tempdirs).record(Op::Create { entry: entry.clone(), origin: None })?;
+
+    info!("relinked {orphan:?} as {entry:?}");
+    output::record(&["relinked", &entry.to_string_lossy(), &orphan.to_string_lossy()]);
+    Ok(entry)
+}
+
+/// Delete the orphaned backing directory `orphan`. There's no entry to put in the trash, so it's gone for good
+pub fn purge(orphan: &Path) -> Result<()> {
+    std::fs::remove_dir_all(orphan).wrap_err(format!("delete {orphan:?}"))?;
+    info!("deleted {orphan:?}");
+    output::record(&["deleted", "", &orphan.to_string_lossy()]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_unlinked_backing_dirs() {
+        let dir = tempdir::TempDir::new("t-rs-test-orphans").unwrap();
+        let root = dir.path();
+        for i in ["T-RS-TEMPDIR.linked", "T-RS-TEMPDIR.orphan", "something-else"] {
+            std::fs::create_dir_all(root.join(i)).unwrap();
+        }
+        std::fs::write(root.join("T-RS-TEMPDIR.file"), "").unwrap();
+
+        let known = HashSet::from([root.join("T-RS-TEMPDIR.linked")]);
+        assert_eq!(unlinked(&[root.to_path_buf(), root.join("missing")], &known), vec![root.join("T-RS-TEMPDIR.orphan")]);
+    }
+}
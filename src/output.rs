@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::FilterFn;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// How much t-rs says about what it's doing, set once with `--quiet` or `--porcelain`
+static MODE: OnceLock<Mode> = OnceLock::new();
+/// How much detail to add: 1 with `-v` and 2 with `-vv`
+static VERBOSITY: OnceLock<u8> = OnceLock::new();
+/// Where messages are also written, with `log_file = true` in the config
+static LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// The log file is moved to `t-rs.log.1` when it gets bigger than this, and the older ones to `t-rs.log.2` and so on
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// How many old log files to keep around
+const OLD_LOGS: usize = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// say what's happening on stderr
+    #[default]
+    Normal,
+    /// only print what was asked for, warnings and errors
+    Quiet,
+    /// like quiet, and print tab-separated records on stdout for status, create and delete
+    Porcelain,
+}
+
+pub fn set_mode(mode: Mode) {
+    let _ = MODE.set(mode);
+}
+
+fn mode() -> Mode {
+    MODE.get().copied().unwrap_or_default()
+}
+
+/// Whether to leave out messages about what's happening
+pub fn quiet() -> bool {
+    mode() != Mode::Normal
+}
+
+pub fn porcelain() -> bool {
+    mode() == Mode::Porcelain
+}
+
+pub fn set_verbosity(verbosity: u8) {
+    let _ = VERBOSITY.set(verbosity);
+}
+
+fn verbosity() -> u8 {
+    VERBOSITY.get().copied().unwrap_or_default()
+}
+
+/// The target of messages that only go to the log file, like warnings that are summarized at the end anyway
+pub const LOG_ONLY: &str = "t_rs::log_only";
+
+/// Send messages to stderr and, once [`open_log`] was called, the log file. Everything about what t-rs does,
+/// or why it doesn't, goes through [`tracing`] so it ends up in the log file. Only output that was asked for,
+/// like the table of `t status`, is printed directly
+pub fn init() {
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .event_format(Format { file: false })
+        .with_filter(FilterFn::new(|meta| meta.target() != LOG_ONLY && *meta.level() <= stderr_level()));
+    let file = tracing_subscriber::fmt::layer()
+        .with_writer(|| LogFile)
+        .event_format(Format { file: true })
+        .with_filter(FilterFn::new(|meta| *meta.level() <= if verbosity() >= 2 { Level::TRACE } else { Level::DEBUG }));
+    let _ = tracing_subscriber::registry().with(stderr).with(file).try_init();
+}
+
+/// The most detailed messages to show on stderr. Errors and notices are always shown, what's happening
+/// unless `--quiet`, why with `-v` and every step along the way with `-vv`
+fn stderr_level() -> Level {
+    match verbosity() {
+        0 if quiet() => Level::WARN,
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// How messages look: just the message on stderr (with what it is for debug and trace ones),
+/// and with the time, pid and level in the log file
+struct Format {
+    file: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for Format
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> std::fmt::Result {
+        let level = *event.metadata().level();
+        if self.file {
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+            write!(writer, "{time} [{}] {level}: ", std::process::id())?;
+        } else if level == Level::DEBUG {
+            write!(writer, "debug: ")?;
+        } else if level == Level::TRACE {
+            write!(writer, "trace: ")?;
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Writes to the log file if there is one, and nowhere otherwise
+struct LogFile;
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match LOG.get() {
+            Some(file) => file.lock().unwrap_or_else(|e| e.into_inner()).write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match LOG.get() {
+            Some(file) => file.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/t-rs/t-rs.log`, falling back to `~/.local/state`
+pub fn log_path(home: &Path) -> PathBuf {
+    let state = match std::env::var_os("XDG_STATE_HOME") {
+        Some(i) if !i.is_empty() => PathBuf::from(i),
+        _ => home.join(".local").join("state"),
+    };
+    state.join("t-rs").join("t-rs.log")
+}
+
+/// Also write every message (up to debug, or trace with `-vv`) to the log file from now on, starting a new one when it's too big
+pub fn open_log(home: &Path) -> Result<()> {
+    let path = log_path(home);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+    }
+    if std::fs::metadata(&path).is_ok_and(|i| i.len() > MAX_LOG_SIZE) {
+        rotate(&path)?;
+    }
+
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(&path).wrap_err(format!("open {path:?}"))?;
+    let _ = LOG.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Move `t-rs.log` to `t-rs.log.1`, `t-rs.log.1` to `t-rs.log.2` and so on, dropping the oldest
+fn rotate(path: &Path) -> Result<()> {
+    let old = |n: usize| path.with_extension(format!("log.{n}"));
+    let _ = std::fs::remove_file(old(OLD_LOGS));
+    for n in (1..OLD_LOGS).rev() {
+        if old(n).exists() {
+            std::fs::rename(old(n), old(n + 1)).wrap_err(format!("rotate {:?}", old(n)))?;
+        }
+    }
+    std::fs::rename(path, old(1)).wrap_err(format!("rotate {path:?}"))
+}
+
+/// Print why something couldn't be done on stderr
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::tracing::error!($($arg)*)
+    };
+}
+
+/// Print a line that should be seen even with `--quiet`, like what a `--dry-run` would do
+#[macro_export]
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        $crate::tracing::warn!($($arg)*)
+    };
+}
+
+/// Print a line about what's happening on stderr, unless `--quiet` or `--porcelain` was passed
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::tracing::info!($($arg)*)
+    };
+}
+
+/// Print why something happens with `-v`
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::tracing::debug!($($arg)*)
+    };
+}
+
+/// Print every step along the way with `-vv`
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::tracing::trace!($($arg)*)
+    };
+}
+
+/// Write a message to the log file only, if there is one
+#[macro_export]
+macro_rules! log_only {
+    ($($arg:tt)*) => {
+        $crate::tracing::info!(target: $crate::output::LOG_ONLY, $($arg)*)
+    };
+}
+
+/// Print a record for `--porcelain`, like `deleted\t<entry>\t<backing>`. The first field says what it's about
+/// and fields are only ever added at the end, so scripts can rely on their order
+pub fn record(fields: &[&str]) {
+    if porcelain() {
+        println!("{}", line(fields));
+    }
+}
+
+fn line(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|i| i.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n"))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_logs() {
+        let dir = tempdir::TempDir::new("t-rs-test-log").unwrap();
+        let path = dir.path().join("t-rs.log");
+        for i in 0..=OLD_LOGS + 1 {
+            std::fs::write(&path, i.to_string()).unwrap();
+            rotate(&path).unwrap();
+        }
+
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(path.with_extension("log.1")).unwrap(), (OLD_LOGS + 1).to_string());
+        assert_eq!(std::fs::read_to_string(path.with_extension(format!("log.{OLD_LOGS}"))).unwrap(), "2");
+        assert!(!path.with_extension(format!("log.{}", OLD_LOGS + 1)).exists());
+    }
+
+    #[test]
+    fn escapes_fields() {
+        assert_eq!(line(&["deleted", "/home/me/tempdirs/a\tb", ""]), "deleted\t/home/me/tempdirs/a\\tb\t");
+        assert_eq!(line(&["note", "two\nlines \\o/"]), "note\ttwo\\nlines \\\\o/");
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use clap::Args;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use fs_extra::dir::CopyOptions;
+use crate::{config, info};
+use crate::hooks::Hooks;
+
+/// Archives `--extract` knows how to unpack. `tar` detects the compression by itself.
+pub const TAR_EXTENSIONS: &[&str] = &[".tar", ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2", ".tar.zst"];
+
+/// What to do with a tempdir after it's been created (and filled, by `t dl` for example).
+///
+/// These combine with each other and with every command that creates a tempdir,
+/// like `t dl --extract --template python --in-shell`. They run in the order they're listed here.
+#[derive(Args, Debug)]
+pub struct Pipeline {
+    /// copy the template with this name from `~/.config/t-rs/templates` into the new tempdir.
+    /// This happens when it's created, before anything else goes in
+    #[arg(long, global = true)]
+    pub template: Option<String>,
+
+    /// run the command from `[scaffolds]` in the config with this name in the new tempdir, like `cargo init`.
+    /// This happens right after the template is copied
+    #[arg(long, global = true)]
+    pub scaffold: Option<String>,
+
+    /// extract archives (zip and tar) in the new tempdir
+    #[arg(long, global = true)]
+    pub extract: bool,
+
+    /// start a shell in the new tempdir, deleting it when you leave the shell (unless you use `t persist`).
+    /// Like `t shell`, whose `--shell` picks the shell to start
+    #[arg(long = "in-shell", global = true)]
+    pub shell: bool,
+
+    /// how to start that shell, set by `t shell`
+    #[arg(skip)]
+    pub shell_options: ShellOptions,
+}
+
+/// Which shell to start, and what to run in it
+#[derive(Args, Debug, Default, Clone)]
+pub struct ShellOptions {
+    /// the shell to start instead of `$SHELL`, like `/bin/fish`
+    #[arg(long = "shell")]
+    pub program: Option<PathBuf>,
+
+    /// run this command in the shell instead of starting it interactively
+    #[arg(short = 'c')]
+    pub command: Option<String>,
+}
+
+impl Pipeline {
+    /// Run the steps after the template on the new tempdir `dir`, returning the directory to cd into
+    pub fn run(&self, tempdirs: &Path, hooks: &Hooks, dir: PathBuf) -> Result<Option<PathBuf>> {
+        if self.extract {
+            extract(&dir)?;
+        }
+
+        if self.shell {
+            crate::shell(tempdirs, &dir, hooks, &self.shell_options)?;
+            Ok(None)
+        } else {
+            Ok(Some(dir))
+        }
+    }
+}
+
+/// Run the scaffold `name`, which is `command`, in the new tempdir `dir`
+pub fn run_scaffold(dir: &Path, name: &str, command: &str) -> Result<()> {
+    info!("running scaffold {name:?}: {command}");
+    let status = crate::platform::script(command)
+        .current_dir(dir)
+        .env("PWD", dir)
+        .env("T_NAME", dir.file_name().unwrap_or_default())
+        .env("T_DIR", dir)
+        // stdout is where the wrapper looks for the directory to cd into
+        .stdout(std::io::stderr())
+        .status()
+        .wrap_err(format!("run scaffold {name:?}"))?;
+
+    if !status.success() {
+        bail!("scaffold {name:?} failed ({status}), the tempdir is still at {dir:?}");
+    }
+    Ok(())
+}
+
+/// Extract all archives directly in `dir` into it, leaving the archives themselves
+pub fn extract(dir: &Path) -> Result<()> {
+    for i in std::fs::read_dir(dir).wrap_err(format!("read {dir:?}"))? {
+        let i = i.wrap_err("read direntry")?;
+        let path = i.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let name = i.file_name().to_string_lossy().to_lowercase();
+        let mut cmd = if name.ends_with(".zip") {
+            let mut cmd = Command::new("unzip");
+            cmd.arg("-q").arg(&path).arg("-d").arg(dir);
+            cmd
+        } else if TAR_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) {
+            let mut cmd = Command::new("tar");
+            cmd.arg("-xf").arg(&path).arg("-C").arg(dir);
+            cmd
+        } else {
+            continue;
+        };
+
+        info!("extracting {path:?}");
+        let status = cmd.status().wrap_err(format!("run {:?}", cmd.get_program()))?;
+        if !status.success() {
+            bail!("extracting {path:?} failed ({status})");
+        }
+    }
+
+    Ok(())
+}
+
+/// Where templates for `--template` live
+pub fn templates_dir(home: &Path) -> PathBuf {
+    config::config_dir(home).join("t-rs").join("templates")
+}
+
+/// Copy the contents of the template called `name` into `dir`
+pub fn apply_template(dir: &Path, name: &str, home: &Path) -> Result<()> {
+    let template = templates_dir(home).join(name);
+    if !template.is_dir() {
+        bail!("there's no template called {name:?} (looked for {template:?})");
+    }
+
+    info!("copying template {template:?}");
+    fs_extra::dir::copy(&template, dir, &CopyOptions {
+        content_only: true,
+        overwrite: true,
+        ..Default::default()
+    }).wrap_err(format!("copy template {template:?}"))?;
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Link `link` to the directory `target`. On Windows, creating symlinks needs developer mode or admin rights,
+/// so without those this makes a directory junction, which works the same for tempdirs
+pub fn link_dir(target: impl AsRef<Path>, link: impl AsRef<Path>) -> io::Result<()> {
+    let (target, link) = (target.as_ref(), link.as_ref());
+    match symlink::symlink_auto(target, link) {
+        Err(e) if cfg!(windows) && e.kind() == io::ErrorKind::PermissionDenied => junction(target, link),
+        res => res,
+    }
+}
+
+fn junction(target: &Path, link: &Path) -> io::Result<()> {
+    let status = Command::new("cmd").arg("/C").arg("mklink").arg("/J").arg(link).arg(target).output()?.status;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("mklink /J {link:?} {target:?} failed ({status})")))
+    }
+}
+
+/// The shell to start when none is given: `$SHELL`, or the first of zsh, bash and sh that exists.
+/// On Windows, PowerShell 7 (`pwsh`) if it's installed and otherwise `%COMSPEC%` (cmd)
+pub fn default_shell() -> PathBuf {
+    if let Some(shell) = std::env::var_os("SHELL").filter(|i| !i.is_empty()) {
+        return PathBuf::from(shell);
+    }
+
+    if cfg!(windows) {
+        if Command::new("pwsh").args(["-NoProfile", "-Command", "exit"]).output().is_ok_and(|i| i.status.success()) {
+            return PathBuf::from("pwsh");
+        }
+        return std::env::var_os("COMSPEC").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("cmd"));
+    }
+
+    ["/bin/zsh", "/bin/bash"]
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|i| i.exists())
+        .unwrap_or_else(|| PathBuf::from("/bin/sh"))
+}
+
+/// The flag that makes `shell` run a command instead of starting interactively
+pub fn command_flag(shell: &OsStr) -> &'static str {
+    // split by hand, so Windows paths are understood everywhere
+    let shell = shell.to_string_lossy().to_lowercase();
+    let name = shell.rsplit(['/', '\\']).next().unwrap_or_default();
+    match name.strip_suffix(".exe").unwrap_or(name) {
+        "cmd" => "/C",
+        "pwsh" | "powershell" => "-Command",
+        _ => "-c",
+    }
+}
+
+/// A command that runs `script` with the system shell: `sh` or, on Windows, `cmd`
+pub fn script(script: &str) -> Command {
+    let mut cmd = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    cmd.arg(command_flag(cmd.get_program())).arg(script);
+    cmd
+}
+
+/// The command that opens a directory in the file manager: `opener` from the config split on whitespace,
+/// or xdg-open, open on macOS or explorer on Windows
+pub fn opener(opener: Option<&str>) -> Command {
+    match opener.map(|i| i.split_whitespace().collect::<Vec<_>>()) {
+        Some(parts) if !parts.is_empty() => {
+            let mut cmd = Command::new(parts[0]);
+            cmd.args(&parts[1..]);
+            cmd
+        }
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => Command::new("explorer"),
+        _ => Command::new("xdg-open"),
+    }
+}
+
+/// The command that edits a directory: `editor` from the config, `$VISUAL` or `$EDITOR`, split on whitespace
+pub fn editor(editor: Option<&str>) -> Option<Command> {
+    let editor = match editor {
+        Some(i) => i.to_string(),
+        None => ["VISUAL", "EDITOR"].iter().find_map(|i| std::env::var(i).ok().filter(|i| !i.trim().is_empty()))?,
+    };
+    let mut parts = editor.split_whitespace();
+    let mut cmd = Command::new(parts.next()?);
+    cmd.args(parts);
+    Some(cmd)
+}
+
+/// The system's temp dir, with symlinks resolved so paths in it compare equal to the current dir.
+/// On macOS that's the per-user `$TMPDIR` under `/var/folders`, which is really `/private/var/folders`
+pub fn temp_dir() -> PathBuf {
+    if cfg!(windows) {
+        // canonical paths on Windows start with `\\?\`, which cmd doesn't understand
+        return std::env::temp_dir();
+    }
+
+    // an empty `$TMPDIR` means it isn't set, not the current dir
+    let dir = std::env::var_os("TMPDIR")
+        .filter(|i| !i.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    dir.canonicalize().unwrap_or(dir)
+}
+
+/// What's left of `path` after `base`, also when only one of them goes through a symlink,
+/// like `/tmp` and `/private/tmp` on macOS
+pub fn strip_prefix<'a>(path: &'a Path, base: &Path) -> Option<&'a Path> {
+    if let Ok(rest) = path.strip_prefix(base) {
+        return Some(rest);
+    }
+
+    // only resolve the ancestors, since the rest of `path` can be a symlink to somewhere else entirely
+    let base = base.canonicalize().ok()?;
+    path.ancestors()
+        .find(|i| i.canonicalize().is_ok_and(|i| i == base))
+        .and_then(|i| path.strip_prefix(i).ok())
+}
+
+/// Parse a file mode in octal, like `700` or `0750`
+pub fn parse_mode(s: &str) -> Result<u32, String> {
+    let mode = u32::from_str_radix(s.trim().trim_start_matches("0o"), 8)
+        .map_err(|_| format!("expected a mode in octal like `700`, got `{s}`"))?;
+    if mode > 0o777 {
+        return Err(format!("`{s}` isn't a mode, those go up to `777`"));
+    }
+    Ok(mode)
+}
+
+/// Set the permissions of `dir` to `mode`. Windows has no modes, there directories in the temp dir are private already
+pub fn set_mode(dir: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (dir, mode);
+        Ok(())
+    }
+}
+
+/// The mode of `dir` if other users can read or enter it
+pub fn loose_mode(dir: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir).ok()?.permissions().mode() & 0o777;
+        (mode & 0o077 != 0).then_some(mode)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+        None
+    }
+}
+
+/// Whether `path` belongs to the same user as `other`. Always true where files have no owner
+pub fn same_owner(path: &Path, other: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::symlink_metadata(path), std::fs::metadata(other)) {
+            (Ok(a), Ok(b)) => a.uid() == b.uid(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, other);
+        true
+    }
+}
+
+/// How many bytes are free on the filesystem `dir` is on, if `df` can tell
+pub fn free_space(dir: &Path) -> Option<u64> {
+    if cfg!(windows) {
+        return None;
+    }
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    // `Filesystem 1024-blocks Used Available Capacity Mounted on`, then a line for the filesystem
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let kib: u64 = stdout.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// The Downloads known folder on Windows, which can be moved away from `~/Downloads`
+pub fn known_downloads_dir() -> Option<PathBuf> {
+    known_folder("Downloads")
+}
+
+/// The Pictures known folder on Windows, where the Screenshots folder is
+pub fn known_pictures_dir() -> Option<PathBuf> {
+    known_folder("My Pictures")
+}
+
+fn known_folder(name: &str) -> Option<PathBuf> {
+    if !cfg!(windows) {
+        return None;
+    }
+
+    let script = format!("(New-Object -ComObject Shell.Application).NameSpace('shell:{name}').Self.Path");
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .output()
+        .ok()
+        .filter(|i| i.status.success())?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_flags() {
+        assert_eq!(command_flag(OsStr::new("/bin/bash")), "-c");
+        assert_eq!(command_flag(OsStr::new("C:\\Windows\\System32\\cmd.exe")), "/C");
+        assert_eq!(command_flag(OsStr::new("pwsh")), "-Command");
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(parse_mode("700"), Ok(0o700));
+        assert_eq!(parse_mode("0750"), Ok(0o750));
+        assert!(parse_mode("800").is_err());
+        assert!(parse_mode("1777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn strips_through_symlinks() {
+        let dir = tempdir::TempDir::new("t-rs-test-platform").unwrap();
+        let root = dir.path();
+        let real = root.join("private/tmp");
+        std::fs::create_dir_all(real.join("foo/bar")).unwrap();
+        std::os::unix::fs::symlink(&real, root.join("tmp")).unwrap();
+
+        assert_eq!(strip_prefix(&root.join("tmp/foo/bar"), &real), Some(Path::new("foo/bar")));
+        assert_eq!(strip_prefix(&real.join("foo"), &root.join("tmp")), Some(Path::new("foo")));
+        assert_eq!(strip_prefix(&root.join("elsewhere"), &real), None);
+    }
+}
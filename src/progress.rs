@@ -0,0 +1,29 @@
+use std::io::IsTerminal;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// A progress bar on stderr for moving `total` bytes.
+/// Hidden when stderr isn't a terminal, so logs don't fill up with redraws
+pub fn bytes(total: u64) -> Result<ProgressBar> {
+    if !std::io::stderr().is_terminal() {
+        return Ok(ProgressBar::hidden());
+    }
+
+    Ok(ProgressBar::new(total).with_style(
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+            .wrap_err("progress bar template")?,
+    ))
+}
+
+/// Like [`bytes`], for when it's not known how many bytes there will be
+pub fn spinner() -> Result<ProgressBar> {
+    if !std::io::stderr().is_terminal() {
+        return Ok(ProgressBar::hidden());
+    }
+
+    Ok(ProgressBar::new_spinner().with_style(
+        ProgressStyle::with_template("{spinner} {bytes} ({bytes_per_sec})")
+            .wrap_err("progress bar template")?,
+    ))
+}
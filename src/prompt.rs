@@ -0,0 +1,100 @@
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use clap::ValueEnum;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use dialoguer::theme::ColorfulTheme;
+use crate::notice;
+
+/// How prompts are answered when nobody's there to answer them, set once with `--assume`
+static ASSUME: OnceLock<Assume> = OnceLock::new();
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Assume {
+    /// say yes, or pick the default choice
+    Yes,
+    /// say no, or don't pick anything
+    #[default]
+    No,
+    /// fail, for scripts that should never get to a prompt
+    Fail,
+}
+
+pub fn set_assume(assume: Assume) {
+    let _ = ASSUME.set(assume);
+}
+
+/// Whether prompts can actually be shown and answered
+pub fn can_ask() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// The answer `--assume` gives to `question` when we can't ask, or `None` if we can
+fn assumed(question: &str) -> Result<Option<bool>> {
+    if can_ask() {
+        return Ok(None);
+    }
+
+    match ASSUME.get().copied().unwrap_or_default() {
+        Assume::Yes => {
+            notice!("{question} yes (--assume yes)");
+            Ok(Some(true))
+        }
+        Assume::No => Ok(Some(false)),
+        Assume::Fail => bail!("can't ask {question:?}, stdin isn't a terminal (use --assume yes or --assume no)"),
+    }
+}
+
+/// Ask a yes/no question
+pub fn confirm(question: &str, default: bool) -> Result<bool> {
+    if let Some(answer) = assumed(question)? {
+        return Ok(answer);
+    }
+
+    dialoguer::Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .default(default)
+        .interact()
+        .wrap_err(format!("ask {question:?}"))
+}
+
+/// Ask for a password, twice to catch typos. There's nothing to assume for these
+pub fn password(question: &str) -> Result<String> {
+    if !can_ask() {
+        bail!("can't ask for a {question}, stdin isn't a terminal");
+    }
+
+    dialoguer::Password::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .with_confirmation("again", "those didn't match")
+        .interact()
+        .wrap_err(format!("ask {question:?}"))
+}
+
+/// Pick one of `items`, or nothing if the prompt is cancelled
+pub fn select<T: ToString>(question: &str, items: &[T], default: usize) -> Result<Option<usize>> {
+    if let Some(answer) = assumed(question)? {
+        return Ok(answer.then_some(default));
+    }
+
+    dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .items(items)
+        .default(default)
+        .interact_opt()
+        .wrap_err(format!("ask {question:?}"))
+}
+
+/// Like [`select`], but items can be searched by typing
+pub fn fuzzy_select<T: ToString>(question: &str, items: &[T], default: usize) -> Result<Option<usize>> {
+    if let Some(answer) = assumed(question)? {
+        return Ok(answer.then_some(default));
+    }
+
+    dialoguer::FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .items(items)
+        .default(default)
+        .interact_opt()
+        .wrap_err(format!("ask {question:?}"))
+}
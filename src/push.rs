@@ -0,0 +1,64 @@
+use std::path::Path;
+use std::process::Command;
+use serde::Deserialize;
+
+/// What `t push` copies tempdirs to other machines with
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tool {
+    /// only sends what changed since the last push
+    #[default]
+    Rsync,
+    /// for machines without rsync
+    Scp,
+}
+
+impl Tool {
+    /// The command that copies the contents of `dir` to `<target>/<name>`
+    pub fn command(self, dir: &Path, name: &str, target: &str) -> Command {
+        let dest = join(target, name);
+        match self {
+            Tool::Rsync => {
+                let mut cmd = Command::new("rsync");
+                // the trailing slashes copy the contents, even when `dir` is the symlink of a tempdir
+                cmd.args(["-a", "--info=progress2"])
+                    .arg(format!("{}/", dir.display()))
+                    .arg(format!("{dest}/"));
+                cmd
+            }
+            Tool::Scp => {
+                let mut cmd = Command::new("scp");
+                cmd.arg("-r").arg(dir).arg(dest);
+                cmd
+            }
+        }
+    }
+}
+
+/// Whether `s` looks like somewhere to push to (`host:path`) rather than the name of a tempdir
+pub fn is_target(s: &str) -> bool {
+    s.contains(':')
+}
+
+/// `target/name`, where `target` is like `user@host:path`, `host:` or `host:path/`
+fn join(target: &str, name: &str) -> String {
+    if target.ends_with(':') || target.ends_with('/') {
+        format!("{target}{name}")
+    } else {
+        format!("{target}/{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_targets() {
+        assert_eq!(join("me@host:", "foo"), "me@host:foo");
+        assert_eq!(join("host:/srv/", "foo"), "host:/srv/foo");
+        assert_eq!(join("host:/srv", "foo"), "host:/srv/foo");
+        assert!(is_target("host:"));
+        assert!(!is_target("foo"));
+    }
+}
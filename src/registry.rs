@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::journal::Op;
+use crate::warnings::{warn, Kind};
+use crate::{age, trash};
+
+/// Every tempdir t-rs knows about, across all tempdirs roots, including hidden ones that have no entry.
+///
+/// Stored in `~/.local/share/t-rs/registry.json` and updated with every operation in the journal.
+/// The filesystem is still the source of truth: [`Registry::reconcile`] brings it back in line with it.
+pub struct Registry {
+    path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Records {
+    pub tempdirs: Vec<Record>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    /// the tempdirs root it belongs to
+    pub root: PathBuf,
+    /// its entry in the tempdirs root, `None` for hidden tempdirs
+    pub entry: Option<PathBuf>,
+    /// where its files are, for non-persistent and hidden tempdirs
+    pub backing: Option<PathBuf>,
+    /// in seconds since the unix epoch
+    pub created: u64,
+    /// when its `--ttl` runs out, for hidden tempdirs which have no metadata to keep it in
+    #[serde(default)]
+    pub expires: Option<u64>,
+}
+
+impl Record {
+    /// Whether the tempdir is still there
+    fn exists(&self) -> bool {
+        match (&self.entry, &self.backing) {
+            (Some(entry), _) => entry.exists() || entry.is_symlink(),
+            (None, Some(backing)) => backing.exists(),
+            (None, None) => false,
+        }
+    }
+}
+
+impl Registry {
+    pub fn new(home: &Path) -> Self {
+        Self {
+            path: trash::data_dir(home).join("t-rs").join("registry.json"),
+        }
+    }
+
+    pub fn load(&self) -> Result<Records> {
+        if !self.path.exists() {
+            return Ok(Records::default());
+        }
+
+        // it's rebuilt from the filesystem by `reconcile`, so a broken registry shouldn't stop anything
+        let contents = std::fs::read_to_string(&self.path).wrap_err(format!("read registry {:?}", self.path))?;
+        match serde_json::from_str(&contents) {
+            Ok(records) => Ok(records),
+            Err(_) => {
+                warn(Kind::Unreadable, format!("registry {:?}", self.path));
+                Ok(Records::default())
+            }
+        }
+    }
+
+    fn save(&self, records: &Records) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+        }
+
+        let contents = serde_json::to_string_pretty(records).wrap_err("serialize registry")?;
+        std::fs::write(&self.path, contents).wrap_err(format!("write registry {:?}", self.path))
+    }
+
+    /// Record what `op` did to the tempdirs root `root`
+    pub fn apply(&self, root: &Path, op: &Op) -> Result<()> {
+        let mut records = self.load()?;
+        records.apply(root, op);
+        self.save(&records)
+    }
+
+    /// Remember a tempdir that isn't linked from any tempdirs root, like the ones `t hidden` makes
+    pub fn add_hidden(&self, root: &Path, backing: &Path, expires: Option<u64>) -> Result<()> {
+        let mut records = self.load()?;
+        records.tempdirs.push(Record {
+            root: root.to_path_buf(),
+            entry: None,
+            backing: Some(backing.to_path_buf()),
+            created: age::now(),
+            expires,
+        });
+        self.save(&records)
+    }
+
+    /// Forget the hidden tempdir at `backing`, once it's deleted
+    pub fn forget_hidden(&self, backing: &Path) -> Result<()> {
+        let mut records = self.load()?;
+        records.tempdirs.retain(|i| i.entry.is_some() || i.backing.as_deref() != Some(backing));
+        self.save(&records)
+    }
+
+    /// The hidden tempdirs made for the tempdirs root `root` that are still there
+    pub fn hidden(&self, root: &Path) -> Result<Vec<Record>> {
+        Ok(self.load()?
+            .tempdirs
+            .into_iter()
+            .filter(|i| i.root == root && i.entry.is_none() && i.exists())
+            .collect())
+    }
+
+    /// Bring the records of the tempdirs root `root` in line with its `entries`:
+    /// forget tempdirs that are gone and add the ones that were made without us noticing
+    pub fn reconcile(&self, root: &Path, entries: &[PathBuf]) -> Result<()> {
+        let before = self.load()?;
+        let mut records = before.clone();
+
+        records.tempdirs.retain(|i| i.root != root || i.exists());
+        for entry in entries {
+            let backing = std::fs::read_link(entry).ok();
+            match records.tempdirs.iter_mut().find(|i| i.entry.as_ref() == Some(entry)) {
+                Some(record) => record.backing = backing,
+                None => records.tempdirs.push(Record {
+                    root: root.to_path_buf(),
+                    entry: Some(entry.clone()),
+                    backing,
+                    created: age::created(entry).map(age::to_unix).unwrap_or_else(|_| age::now()),
+                    expires: None,
+                }),
+            }
+        }
+
+        // most runs change nothing, so don't rewrite the file every time
+        if records != before {
+            self.save(&records)?;
+        }
+        Ok(())
+    }
+}
+
+impl Records {
+    pub fn apply(&mut self, root: &Path, op: &Op) {
+        match op {
+            Op::Create { entry, .. } => {
+                self.tempdirs.retain(|i| i.entry.as_ref() != Some(entry));
+                self.tempdirs.push(Record {
+                    root: root.to_path_buf(),
+                    entry: Some(entry.clone()),
+                    backing: std::fs::read_link(entry).ok(),
+                    created: age::now(),
+                    expires: None,
+                });
+            }
+            Op::Rename { from, to } => {
+                for i in self.tempdirs.iter_mut().filter(|i| i.entry.as_ref() == Some(from)) {
+                    i.entry = Some(to.clone());
+                }
+            }
+            Op::Persist { entry, backing } => {
+                // persisting a hidden tempdir moves it into the root, so it's not hidden anymore
+                self.tempdirs.retain(|i| i.entry.is_some() || i.backing.as_ref() != Some(backing));
+                match self.tempdirs.iter_mut().find(|i| i.entry.as_ref() == Some(entry)) {
+                    Some(record) => record.backing = None,
+                    None => self.tempdirs.push(Record {
+                        root: root.to_path_buf(),
+                        entry: Some(entry.clone()),
+                        backing: None,
+                        created: age::now(),
+                        expires: None,
+                    }),
+                }
+            }
+            Op::Delete { entry, .. } | Op::Promote { entry, .. } => {
+                self.tempdirs.retain(|i| i.entry.as_ref() != Some(entry));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_operations() {
+        let root = Path::new("/home/me/tempdirs");
+        let mut records = Records::default();
+
+        records.apply(root, &Op::Create { entry: root.join("a"), origin: None });
+        records.apply(root, &Op::Rename { from: root.join("a"), to: root.join("b") });
+        records.apply(root, &Op::Persist { entry: root.join("b"), backing: PathBuf::from("/tmp/T-RS-TEMPDIR.x") });
+        assert_eq!(records.tempdirs.len(), 1);
+        assert_eq!(records.tempdirs[0].entry, Some(root.join("b")));
+        assert_eq!(records.tempdirs[0].backing, None);
+
+        records.apply(root, &Op::Delete { entry: root.join("b"), backing: None });
+        assert!(records.tempdirs.is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use crate::{info, platform, prompt};
+
+/// Prefix of the directories holding an encrypted store, with the encrypted files in `cipher`
+/// and the store mounted on `plain`, which contains a single backing directory
+pub const SECRET_PREFIX: &str = "T-RS-SECRET";
+const CIPHER: &str = "cipher";
+const PLAIN: &str = "plain";
+
+/// What the files of a secret tempdir are encrypted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// a random key that's forgotten right after mounting, so nobody can ever decrypt the files again once it's unmounted
+    Generated,
+    /// a passphrase that's asked for, to be able to get at the files after a reboot
+    Passphrase,
+}
+
+/// Make a new encrypted store with gocryptfs in `parent` and mount it, returning where its decrypted files show up.
+/// gocryptfs runs on FUSE, so unlike `--tmpfs` this doesn't need root
+pub fn mount(parent: &Path, key: Key) -> Result<PathBuf> {
+    std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+    let dir = tempdir::TempDir::new_in(parent, SECRET_PREFIX).wrap_err("create encrypted store")?.into_path();
+    platform::set_mode(&dir, 0o700).wrap_err(format!("set permissions of {dir:?}"))?;
+
+    let res = init_and_mount(&dir, key);
+    if res.is_err() {
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    res
+}
+
+fn init_and_mount(dir: &Path, key: Key) -> Result<PathBuf> {
+    let (cipher, plain) = (dir.join(CIPHER), dir.join(PLAIN));
+    for i in [&cipher, &plain] {
+        std::fs::create_dir(i).wrap_err(format!("create {i:?}"))?;
+    }
+
+    let password = match key {
+        Key::Generated => generate_key()?,
+        Key::Passphrase => prompt::password("passphrase for the secret tempdir")?,
+    };
+
+    gocryptfs(Command::new("gocryptfs").args(["-init", "-q"]).arg(&cipher), &password)?;
+    info!("mounting an encrypted store on {plain:?}");
+    gocryptfs(Command::new("gocryptfs").arg("-q").arg(&cipher).arg(&plain), &password)?;
+    Ok(plain)
+}
+
+/// Run gocryptfs, which reads the password from stdin when that isn't a terminal
+fn gocryptfs(cmd: &mut Command, password: &str) -> Result<()> {
+    let mut child = match cmd.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            bail!("secret tempdirs need gocryptfs, install it from your package manager or https://github.com/rfjakob/gocryptfs")
+        }
+        Err(e) => return Err(e).wrap_err("run gocryptfs"),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{password}").wrap_err("give gocryptfs the key")?;
+    }
+
+    let output = child.wait_with_output().wrap_err("wait for gocryptfs")?;
+    if !output.status.success() {
+        bail!("gocryptfs failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// 256 random bits, hex encoded
+fn generate_key() -> Result<String> {
+    let mut bytes = [0; 32];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut i| std::io::Read::read_exact(&mut i, &mut bytes))
+        .wrap_err("generate a key")?;
+    Ok(bytes.iter().map(|i| format!("{i:02x}")).collect())
+}
+
+/// The directory of the encrypted store `backing` is in, if it's in one made by [`mount`]
+fn store(backing: &Path) -> Option<&Path> {
+    let plain = backing.parent().filter(|i| i.file_name().is_some_and(|i| i == PLAIN))?;
+    plain.parent().filter(|i| i.file_name().is_some_and(|i| i.to_string_lossy().starts_with(SECRET_PREFIX)))
+}
+
+/// Whether `backing` is the backing directory of a secret tempdir, whose files shouldn't go to the trash
+pub fn is_secret(backing: &Path) -> bool {
+    store(backing).is_some()
+}
+
+/// Wipe what's left of the secret tempdir `backing`, unmount its store and wipe the encrypted files too.
+/// Does nothing for other backing directories
+pub fn release(backing: &Path) -> Result<()> {
+    let Some(dir) = store(backing) else {
+        return Ok(());
+    };
+    let plain = dir.join(PLAIN);
+
+    if backing.exists() {
+        wipe(backing)?;
+        info!("wiped {backing:?}");
+    }
+    if is_mount_point(&plain) {
+        let mut cmd = if cfg!(target_os = "macos") { Command::new("umount") } else { Command::new("fusermount") };
+        if !cfg!(target_os = "macos") {
+            cmd.arg("-u");
+        }
+        let output = cmd.arg(&plain).output().wrap_err(format!("run {:?}", cmd.get_program()))?;
+        if !output.status.success() {
+            bail!("unmounting {plain:?} failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+    }
+    wipe(dir)
+}
+
+/// Overwrite every file in `dir` with zeroes before removing it all. On SSDs and copy-on-write
+/// filesystems the old blocks might survive this, which is what the encryption is for
+pub fn wipe(dir: &Path) -> Result<()> {
+    overwrite(dir)?;
+    std::fs::remove_dir_all(dir).wrap_err(format!("remove {dir:?}"))
+}
+
+fn overwrite(path: &Path) -> Result<()> {
+    let meta = std::fs::symlink_metadata(path).wrap_err(format!("get metadata of {path:?}"))?;
+    if meta.is_dir() {
+        for i in std::fs::read_dir(path).wrap_err(format!("read {path:?}"))? {
+            overwrite(&i.wrap_err("read direntry")?.path())?;
+        }
+    } else if meta.is_file() {
+        let mut file = std::fs::OpenOptions::new().write(true).open(path).wrap_err(format!("open {path:?}"))?;
+        let zeroes = [0; 64 * 1024];
+        let mut left = meta.len();
+        while left > 0 {
+            let n = left.min(zeroes.len() as u64) as usize;
+            file.write_all(&zeroes[..n]).wrap_err(format!("overwrite {path:?}"))?;
+            left -= n as u64;
+        }
+        file.sync_all().wrap_err(format!("sync {path:?}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_mount_point(dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let (Ok(meta), Some(Ok(parent))) = (std::fs::metadata(dir), dir.parent().map(std::fs::metadata)) else {
+        return false;
+    };
+    meta.dev() != parent.dev()
+}
+
+/// gocryptfs doesn't run on Windows, so nothing's ever mounted there
+#[cfg(not(unix))]
+fn is_mount_point(_dir: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wipes_and_recognizes_stores() {
+        let dir = tempdir::TempDir::new("t-rs-test-secret").unwrap();
+        let root = dir.path();
+        let backing = root.join(format!("{SECRET_PREFIX}.abc")).join(PLAIN).join("T-RS-TEMPDIR.def");
+        std::fs::create_dir_all(backing.join("sub")).unwrap();
+        std::fs::write(backing.join("sub").join("key.pem"), "secret").unwrap();
+
+        assert!(is_secret(&backing));
+        assert!(!is_secret(&root.join("T-RS-TEMPDIR.def")));
+
+        release(&backing).unwrap();
+        assert!(!root.join(format!("{SECRET_PREFIX}.abc")).exists());
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use crate::warnings::{warn, Kind};
+use crate::{info, meta, STATE_DIR};
+
+const SESSIONS: &str = "sessions";
+/// Set in `t shell` and `t run` sessions to the pid of the t-rs waiting for them.
+/// `$T_DIR` doesn't say we're in one, `eval "$(t env export)"` sets it in any shell
+pub const SESSION_PID: &str = "T_SHELL_PID";
+
+/// A `t shell` session that still has to clean up after itself.
+///
+/// This is written to disk before the shell is started, so that if t-rs gets killed
+/// while the shell is running, the next invocation can still remove the tempdir.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    /// pid of the t-rs process waiting for the shell
+    pub pid: u32,
+    /// pid of the shell itself, once it has been started
+    pub shell_pid: Option<u32>,
+    /// the symlink in the tempdirs root
+    pub symlink: PathBuf,
+    /// the directory the symlink points to
+    pub backing: PathBuf,
+
+    #[serde(skip)]
+    record: PathBuf,
+}
+
+fn sessions_dir(tempdirs: &Path) -> PathBuf {
+    tempdirs.join(STATE_DIR).join(SESSIONS)
+}
+
+impl Session {
+    pub fn register(tempdirs: &Path, symlink: &Path, backing: &Path) -> Result<Self> {
+        let dir = sessions_dir(tempdirs);
+        std::fs::create_dir_all(&dir).wrap_err(format!("create {dir:?}"))?;
+
+        let pid = std::process::id();
+        let session = Self {
+            pid,
+            shell_pid: None,
+            symlink: symlink.to_path_buf(),
+            backing: backing.to_path_buf(),
+            record: dir.join(format!("{pid}.toml")),
+        };
+        session.write()?;
+
+        Ok(session)
+    }
+
+    pub fn set_shell_pid(&mut self, pid: u32) -> Result<()> {
+        self.shell_pid = Some(pid);
+        self.write()
+    }
+
+    /// The session ended normally, forget about it
+    pub fn finish(self) -> Result<()> {
+        std::fs::remove_file(&self.record).wrap_err(format!("remove session record {:?}", self.record))
+    }
+
+    fn write(&self) -> Result<()> {
+        let contents = toml::to_string(self).wrap_err("serialize session")?;
+        std::fs::write(&self.record, contents).wrap_err(format!("write session record {:?}", self.record))
+    }
+
+    fn alive(&self) -> bool {
+        process_alive(self.pid) || self.shell_pid.is_some_and(process_alive)
+    }
+}
+
+/// The tempdir of the `t shell` or `t run` session we're running in, if any
+pub fn current() -> Option<PathBuf> {
+    let pid = std::env::var(SESSION_PID).ok()?.parse().ok()?;
+    // a shell that outlived its t-rs isn't a session anymore
+    process_alive(pid).then(|| std::env::var_os("T_DIR").map(PathBuf::from).unwrap_or_default())
+}
+
+/// All recorded sessions, dead or alive
+fn all(tempdirs: &Path) -> Result<Vec<Session>> {
+    let mut res = Vec::new();
+    let dir = sessions_dir(tempdirs);
+    if !dir.exists() {
+        return Ok(res);
+    }
+
+    for i in std::fs::read_dir(&dir).wrap_err(format!("read {dir:?}"))? {
+        let i = i.wrap_err("read direntry")?;
+
+        let Ok(contents) = std::fs::read_to_string(i.path()) else {
+            warn(Kind::Unreadable, format!("session record {:?}", i.path()));
+            continue;
+        };
+        let Ok(mut session) = toml::from_str::<Session>(&contents) else {
+            warn(Kind::Unreadable, format!("session record {:?}", i.path()));
+            continue;
+        };
+        session.record = i.path();
+        res.push(session);
+    }
+
+    Ok(res)
+}
+
+/// The symlinks of tempdirs that a `t shell` session is still running in
+pub fn live(tempdirs: &Path) -> Result<Vec<PathBuf>> {
+    Ok(all(tempdirs)?
+        .into_iter()
+        .filter(Session::alive)
+        .map(|i| i.symlink)
+        .collect())
+}
+
+/// Remove the tempdirs of `t shell` sessions whose t-rs process and shell are both gone
+pub fn cleanup_dead(tempdirs: &Path) -> Result<()> {
+    for session in all(tempdirs)? {
+        if session.alive() {
+            continue;
+        }
+
+        info!("cleaning up after interrupted shell session in {:?}", session.symlink);
+        // if the session was persisted in the meantime, the symlink is now a real directory
+        // and the backing dir has been moved, so there's nothing left to remove.
+        if session.symlink.is_symlink() {
+            symlink::remove_symlink_auto(&session.symlink).wrap_err("unlink")?;
+            meta::remove(&session.symlink)?;
+        }
+        crate::release_backing(&session.backing)?;
+        if session.backing.exists() {
+            std::fs::remove_dir_all(&session.backing).wrap_err("remove dir")?;
+        }
+        session.finish()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub(crate) fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .arg("-0")
+        .arg(pid.to_string())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|i| i.success())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn process_alive(_pid: u32) -> bool {
+    // we can't tell, so better not delete anything
+    true
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::path::Path;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use crate::{config, notice, prompt};
+use crate::integration::{self, Shell};
+
+/// Whether t-rs hasn't been used before: there's no config and no tempdirs root yet
+pub fn first_run(home: &Path, tempdirs: &Path) -> bool {
+    !config::config_path(home).exists() && !tempdirs.exists()
+}
+
+/// Walk through setting up t-rs: creating the tempdirs root, picking how tempdirs are named,
+/// installing the `t` wrapper function and writing a config file
+pub fn run(home: &Path, tempdirs: &Path) -> Result<()> {
+    notice!("setting up t-rs (run `t-rs setup` to do this again)");
+
+    if !tempdirs.exists() {
+        let create = prompt::confirm(&format!("keep tempdirs in {}?", config::abbreviate_home(tempdirs, home).to_string_lossy()), true)?;
+        if !create {
+            bail!("use --tempdirs or a profile to keep them somewhere else");
+        }
+        std::fs::create_dir_all(tempdirs).wrap_err(format!("create tempdirs ({tempdirs:?})"))?;
+    }
+
+    let config_path = config::config_path(home);
+    if config_path.exists() {
+        notice!("keeping your config in {config_path:?}");
+    } else {
+        let schemes = ["numeric", "words", "date"];
+        let naming = prompt::select(
+            "how should tempdirs you don't name be called?",
+            &["unnamed_1, unnamed_2, ...", "brave-otter, calm-heron, ...", "2024-06-01, 2024-06-01-2, ..."],
+            0,
+        )?.unwrap_or(0);
+
+        if let Some(dir) = config_path.parent() {
+            std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+        }
+        std::fs::write(&config_path, format!("naming = \"{}\"\n", schemes[naming]))
+            .wrap_err(format!("write config {config_path:?}"))?;
+        notice!("wrote {config_path:?}");
+    }
+
+    match Shell::detect() {
+        Some(shell) => {
+            let install = prompt::confirm(&format!("add the `t` function to {}?", shell.rc_file()), true)?;
+            if install {
+                integration::install(shell, home)?;
+            }
+        }
+        None => notice!("couldn't tell which shell you use, see `t-rs check-integration` to set up the `t` function"),
+    }
+
+    Ok(())
+}
+
+/// Append `snippet` to the file at `path`, unless it's already in there
+pub fn append_once(path: &Path, marker: &str, snippet: &str) -> Result<bool> {
+    let existing = std::fs::read_to_string(path).unwrap_or_default();
+    if existing.contains(marker) {
+        return Ok(false);
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err(format!("open {path:?}"))?;
+    writeln!(file, "\n{snippet}").wrap_err(format!("write {path:?}"))?;
+    Ok(true)
+}
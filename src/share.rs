@@ -0,0 +1,204 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::{error, notice};
+
+/// Serve the files in `dir` over HTTP until t-rs is stopped with ctrl-c.
+/// With `upload`, files can be added by `PUT`ing them, like `curl -T file <url>`
+pub fn serve(dir: &Path, port: u16, upload: bool) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).wrap_err(format!("listen on port {port}"))?;
+    let port = listener.local_addr().wrap_err("get local address")?.port();
+    let url = format!("http://{}:{port}/", local_ip().unwrap_or_else(|| "localhost".to_string()));
+
+    notice!("sharing {dir:?} on {url} (ctrl-c to stop)");
+    if upload {
+        notice!("upload files with `curl -T <file> {url}`");
+    }
+    // a qr code is handy for phones, if qrencode is installed
+    let _ = Command::new("qrencode").args(["-t", "ansiutf8", &url]).stdout(std::io::stderr()).status();
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let dir = dir.to_path_buf();
+        std::thread::spawn(move || {
+            if let Err(e) = handle(stream, &dir, upload) {
+                error!("{e}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// The address other machines on the network can reach us on.
+/// Connecting a UDP socket doesn't send anything, it just picks the interface
+fn local_ip() -> Option<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+fn handle(stream: TcpStream, dir: &Path, upload: bool) -> Result<()> {
+    let peer = stream.peer_addr().wrap_err("get peer address")?;
+    let mut reader = BufReader::new(stream.try_clone().wrap_err("clone stream")?);
+    let mut stream = stream;
+
+    let mut request = String::new();
+    reader.read_line(&mut request).wrap_err("read request")?;
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).wrap_err("read header")? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let Some(path) = resolve(dir, target) else {
+        return respond(&mut stream, "400 Bad Request", "text/plain", b"bad path");
+    };
+    notice!("{peer} {method} {target}");
+
+    match method {
+        "GET" | "HEAD" if path.is_dir() => {
+            let listing = listing(&path, target)?;
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", listing.as_bytes())
+        }
+        "GET" | "HEAD" if path.is_file() => {
+            let mut file = std::fs::File::open(&path).wrap_err(format!("open {path:?}"))?;
+            let len = file.metadata().wrap_err(format!("get metadata of {path:?}"))?.len();
+            write!(stream, "HTTP/1.0 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {len}\r\n\r\n")
+                .wrap_err("write response")?;
+            if method == "GET" {
+                std::io::copy(&mut file, &mut stream).wrap_err(format!("send {path:?}"))?;
+            }
+            Ok(())
+        }
+        "PUT" if upload => {
+            // `curl -T file http://host/` puts to `/file`, a directory means the name is missing
+            if path.is_dir() || !path.parent().is_some_and(Path::is_dir) {
+                return respond(&mut stream, "400 Bad Request", "text/plain", b"put to a file in an existing directory");
+            }
+            let mut file = std::fs::File::create(&path).wrap_err(format!("create {path:?}"))?;
+            std::io::copy(&mut reader.by_ref().take(content_length), &mut file).wrap_err(format!("write {path:?}"))?;
+            notice!("received {path:?}");
+            respond(&mut stream, "201 Created", "text/plain", b"ok")
+        }
+        "GET" | "HEAD" => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+        _ => respond(&mut stream, "405 Method Not Allowed", "text/plain", b"method not allowed"),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> Result<()> {
+    write!(stream, "HTTP/1.0 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n", body.len())
+        .and_then(|_| stream.write_all(body))
+        .wrap_err("write response")
+}
+
+/// An html page linking to everything in `dir`
+fn listing(dir: &Path, target: &str) -> Result<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .wrap_err(format!("read {dir:?}"))?
+        .filter_map(|i| i.ok())
+        .map(|i| {
+            let name = i.file_name().to_string_lossy().to_string();
+            if i.path().is_dir() { format!("{name}/") } else { name }
+        })
+        .collect();
+    names.sort();
+
+    // the target is whatever the client sent, only what it decodes to goes back into the page
+    let path = decode(target.split(['?', '#']).next().unwrap_or_default());
+    let base = if path.ends_with('/') { encode(&path) } else { format!("{}/", encode(&path)) };
+    let mut res = format!("<!doctype html><title>{}</title><ul>", escape(&path));
+    for name in names {
+        res.push_str(&format!("<li><a href=\"{base}{}\">{}</a></li>", encode(&name), escape(&name)));
+    }
+    res.push_str("</ul>");
+    Ok(res)
+}
+
+/// The file in `dir` that `target` (the path of a request) refers to, unless it points outside of `dir`
+fn resolve(dir: &Path, target: &str) -> Option<PathBuf> {
+    let path = decode(target.split(['?', '#']).next().unwrap_or_default());
+    let mut res = dir.to_path_buf();
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Normal(i) => res.push(i),
+            Component::RootDir | Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(res)
+}
+
+/// Undo the percent-encoding of a url path
+pub(crate) fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|i| std::str::from_utf8(i).ok()).and_then(|i| u8::from_str_radix(i, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                res.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                res.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&res).to_string()
+}
+
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|i| match i {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (i as char).to_string(),
+            _ => format!("%{i:02X}"),
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_paths() {
+        let dir = Path::new("/tmp/foo");
+        assert_eq!(resolve(dir, "/a%20b/c.txt?x=1"), Some(dir.join("a b/c.txt")));
+        assert_eq!(resolve(dir, "/"), Some(dir.to_path_buf()));
+        assert_eq!(resolve(dir, "/../etc/passwd"), None);
+        assert_eq!(resolve(dir, "/a/%2e%2e/%2e%2e/etc"), None);
+        assert_eq!(decode(&encode("ä b&c/")), "ä b&c/");
+    }
+
+    #[test]
+    fn listing_escapes_the_target() {
+        let dir = tempdir::TempDir::new("t-rs-test-share").unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+
+        let page = listing(dir.path(), "/%22%3E%3Cscript%3E?x=\"><script>").unwrap();
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("href=\"/%22%3E%3Cscript%3E/a.txt\""));
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::Path;
+use color_eyre::Result;
+use serde::Serialize;
+use crate::history::Event;
+use crate::journal::{Journal, Op};
+use crate::layout::Layout;
+use crate::meta::Meta;
+use crate::registry::Registry;
+use crate::trash::Trash;
+use crate::{age, usage};
+
+/// How many weeks back `t stats` counts created tempdirs
+const WEEKS: usize = 8;
+const WEEK: u64 = 60 * 60 * 24 * 7;
+
+/// Totals across all tempdirs, for `t stats`
+#[derive(Serialize, Debug, Default)]
+pub struct Stats {
+    pub ephemeral: usize,
+    pub persistent: usize,
+    pub hidden: usize,
+    pub pinned: usize,
+    /// in bytes, hidden tempdirs included
+    pub size: u64,
+    /// the name and age in seconds of the oldest tempdir
+    pub oldest: Option<(String, u64)>,
+    /// the name and size in bytes of the biggest tempdir
+    pub largest: Option<(String, u64)>,
+    /// how many tempdirs were created in each of the last weeks, this week first
+    pub created_per_week: Vec<usize>,
+    pub trash_items: usize,
+    /// in bytes
+    pub trash_size: u64,
+}
+
+pub fn collect(tempdirs: &Path, layout: Layout, home: &Path, trash: &Trash) -> Result<Stats> {
+    let mut res = Stats::default();
+
+    let entries = layout.entries(tempdirs)?;
+    let sizes = usage::sizes(&entries);
+    for (i, size) in entries.iter().zip(sizes) {
+        let meta = Meta::load(i)?;
+        let name = i.strip_prefix(tempdirs).unwrap_or(i).to_string_lossy().to_string();
+        if meta.persistent(i) {
+            res.persistent += 1;
+        } else {
+            res.ephemeral += 1;
+        }
+        if meta.pinned {
+            res.pinned += 1;
+        }
+
+        res.size += size;
+        if res.largest.as_ref().is_none_or(|(_, largest)| size > *largest) {
+            res.largest = Some((name.clone(), size));
+        }
+        let age = age::age(i)?.as_secs();
+        if res.oldest.as_ref().is_none_or(|(_, oldest)| age > *oldest) {
+            res.oldest = Some((name, age));
+        }
+    }
+
+    for i in Registry::new(home).hidden(tempdirs)? {
+        res.hidden += 1;
+        res.size += i.backing.as_deref().map(usage::size).unwrap_or_default();
+    }
+
+    res.created_per_week = per_week(&Journal::new(tempdirs).history()?, age::now());
+    res.trash_items = trash.list()?.len();
+    res.trash_size = trash.size();
+    Ok(res)
+}
+
+/// How many tempdirs were created in each of the [`WEEKS`] weeks before `now`, the most recent first
+fn per_week(events: &[Event], now: u64) -> Vec<usize> {
+    let mut res = vec![0; WEEKS];
+    for i in events {
+        if let Op::Create { .. } = i.op {
+            let week = (now.saturating_sub(i.time) / WEEK) as usize;
+            if let Some(count) = res.get_mut(week) {
+                *count += 1;
+            }
+        }
+    }
+    res
+}
+
+pub fn print(stats: &Stats) {
+    let total = stats.ephemeral + stats.persistent + stats.hidden;
+    eprintln!("{total} tempdirs: {} ephemeral, {} persistent, {} hidden ({} pinned)", stats.ephemeral, stats.persistent, stats.hidden, stats.pinned);
+    eprintln!("using {}", usage::human_size(stats.size));
+    if let Some((ref name, age)) = stats.oldest {
+        eprintln!("oldest: {name}, created {} ago", age::human(std::time::Duration::from_secs(age)));
+    }
+    if let Some((ref name, size)) = stats.largest {
+        eprintln!("largest: {name}, {}", usage::human_size(size));
+    }
+
+    let weeks: Vec<_> = stats.created_per_week.iter().rev().map(usize::to_string).collect();
+    eprintln!("created per week, the last {} weeks up to this one: {}", weeks.len(), weeks.join(" "));
+    eprintln!("in the trash: {} ({})", stats.trash_items, usage::human_size(stats.trash_size));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn counts_per_week() {
+        let event = |time: u64, op: Op| Event { time, command: "t-rs".to_string(), op };
+        let create = || Op::Create { entry: PathBuf::from("a"), origin: None };
+        let now = 100 * WEEK;
+        let events = [
+            event(now - 10, create()),
+            event(now - 10, Op::Delete { entry: PathBuf::from("a"), backing: None }),
+            event(now - WEEK - 10, create()),
+            event(now - WEEK - 20, create()),
+            event(now - 20 * WEEK, create()),
+        ];
+        assert_eq!(per_week(&events, now), vec![1, 2, 0, 0, 0, 0, 0, 0]);
+    }
+}
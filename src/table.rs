@@ -0,0 +1,105 @@
+use std::io::IsTerminal;
+
+/// How a row of a [`Table`] stands out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Dim,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Blue => "34",
+            Color::Dim => "2",
+        }
+    }
+}
+
+/// Whether to color what goes to stderr: only for a terminal, and not when `$NO_COLOR` is set (see no-color.org)
+pub fn colors() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|i| i.is_empty())
+}
+
+/// Rows of text in aligned columns, for showing on stderr
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<(Vec<String>, Option<Color>)>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self { headers: headers.to_vec(), rows: Vec::new() }
+    }
+
+    pub fn push(&mut self, cells: Vec<String>, color: Option<Color>) {
+        self.rows.push((cells, color));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The lines of the table, leaving out columns that are empty in every row
+    pub fn render(&self, colors: bool) -> Vec<String> {
+        let used: Vec<usize> = (0..self.headers.len())
+            .filter(|&c| self.rows.iter().any(|(cells, _)| cells.get(c).is_some_and(|i| !i.is_empty())))
+            .collect();
+        let widths: Vec<usize> = used
+            .iter()
+            .map(|&c| {
+                let cells = self.rows.iter().filter_map(|(cells, _)| cells.get(c));
+                cells.map(|i| i.chars().count()).chain([self.headers[c].len()]).max().unwrap_or_default()
+            })
+            .collect();
+
+        let line = |cells: &[&str]| -> String {
+            let padded: Vec<_> = cells.iter().zip(&widths).map(|(cell, width)| format!("{cell:width$}")).collect();
+            padded.join("  ").trim_end().to_string()
+        };
+
+        let header: Vec<_> = used.iter().map(|&c| self.headers[c]).collect();
+        let mut res = vec![line(&header)];
+        for (cells, color) in &self.rows {
+            let cells: Vec<_> = used.iter().map(|&c| cells.get(c).map(String::as_str).unwrap_or_default()).collect();
+            let text = line(&cells);
+            res.push(match color {
+                Some(color) if colors => format!("\x1b[{}m{text}\x1b[0m", color.code()),
+                _ => text,
+            });
+        }
+        res
+    }
+}
+
+/// `text` cut off at `max` characters, with `…` at the end if anything was cut off
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut res: String = text.chars().take(max.saturating_sub(1)).collect();
+    res.push('…');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_columns() {
+        let mut table = Table::new(&["name", "type", "note"]);
+        table.push(vec!["a".into(), "persistent".into(), String::new()], Some(Color::Blue));
+        table.push(vec!["longer".into(), "ephemeral".into(), String::new()], None);
+
+        assert_eq!(table.render(false), vec!["name    type", "a       persistent", "longer  ephemeral"]);
+        assert_eq!(table.render(true)[1], "\x1b[34ma       persistent\x1b[0m");
+        assert_eq!(truncate("a long note", 6), "a lon…");
+    }
+}
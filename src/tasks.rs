@@ -0,0 +1,63 @@
+use std::path::Path;
+use std::process::Command;
+
+/// A task runner a scratch project can have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runner {
+    Just,
+    Make,
+    Npm,
+}
+
+impl Runner {
+    /// The task runner for the project in `dir`, going by the files in it
+    pub fn detect(dir: &Path) -> Option<Self> {
+        let has = |names: &[&str]| names.iter().any(|i| dir.join(i).is_file());
+
+        if has(&["justfile", "Justfile", ".justfile"]) {
+            Some(Runner::Just)
+        } else if has(&["Makefile", "makefile", "GNUmakefile"]) {
+            Some(Runner::Make)
+        } else if has(&["package.json"]) {
+            Some(Runner::Npm)
+        } else {
+            None
+        }
+    }
+
+    /// A command that runs `task`, or the default task without one (npm doesn't have one)
+    pub fn command(self, task: Option<&str>, args: &[String]) -> Option<Command> {
+        let mut cmd = match (self, task) {
+            (Runner::Just, _) => Command::new("just"),
+            (Runner::Make, _) => Command::new("make"),
+            (Runner::Npm, Some(_)) => {
+                let mut cmd = Command::new("npm");
+                cmd.arg("run");
+                cmd
+            }
+            (Runner::Npm, None) => return None,
+        };
+        cmd.args(task).args(args);
+        Some(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_runners() {
+        let dir = tempdir::TempDir::new("t-rs-test-tasks").unwrap();
+        let root = dir.path();
+        assert_eq!(Runner::detect(root), None);
+
+        std::fs::write(root.join("package.json"), "{}").unwrap();
+        assert_eq!(Runner::detect(root), Some(Runner::Npm));
+        // a justfile or makefile is more likely to be what you run things with
+        std::fs::write(root.join("Makefile"), "").unwrap();
+        assert_eq!(Runner::detect(root), Some(Runner::Make));
+        std::fs::write(root.join("justfile"), "").unwrap();
+        assert_eq!(Runner::detect(root), Some(Runner::Just));
+    }
+}
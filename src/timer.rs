@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use crate::{config, info};
+
+const NAME: &str = "t-rs-gc";
+const LAUNCHD_LABEL: &str = "nl.jdonszelmann.t-rs.gc";
+
+/// Files that make the OS run `exe args` every `every`: a systemd user service and timer,
+/// or a launchd agent on macOS
+fn files(home: &Path, exe: &Path, args: &[String], every: Duration) -> Vec<(PathBuf, String)> {
+    if cfg!(target_os = "macos") {
+        let dir = home.join("Library").join("LaunchAgents");
+        vec![(dir.join(format!("{LAUNCHD_LABEL}.plist")), launchd_plist(exe, args, every))]
+    } else {
+        let dir = config::config_dir(home).join("systemd").join("user");
+        let (service, timer) = systemd_units(exe, args, every);
+        vec![(dir.join(format!("{NAME}.service")), service), (dir.join(format!("{NAME}.timer")), timer)]
+    }
+}
+
+fn systemd_units(exe: &Path, args: &[String], every: Duration) -> (String, String) {
+    let command: Vec<String> = std::iter::once(exe.to_string_lossy().to_string())
+        .chain(args.iter().cloned())
+        .map(|i| if i.contains(char::is_whitespace) { format!("\"{i}\"") } else { i })
+        .collect();
+
+    let service = format!(
+        "[Unit]\nDescription=Clean up old tempdirs of t-rs\n\n[Service]\nType=oneshot\nExecStart={}\n",
+        command.join(" "),
+    );
+    let timer = format!(
+        "[Unit]\nDescription=Clean up old tempdirs of t-rs regularly\n\n[Timer]\nOnBootSec=15min\nOnUnitActiveSec={}s\n\n[Install]\nWantedBy=timers.target\n",
+        every.as_secs(),
+    );
+    (service, timer)
+}
+
+fn launchd_plist(exe: &Path, args: &[String], every: Duration) -> String {
+    let arguments: String = std::iter::once(exe.to_string_lossy().to_string())
+        .chain(args.iter().cloned())
+        .map(|i| format!("        <string>{}</string>\n", i.replace('&', "&amp;").replace('<', "&lt;")))
+        .collect();
+
+    format!(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n",
+        "<dict>\n",
+        "    <key>Label</key>\n",
+        "    <string>{label}</string>\n",
+        "    <key>ProgramArguments</key>\n",
+        "    <array>\n",
+        "{arguments}",
+        "    </array>\n",
+        "    <key>StartInterval</key>\n",
+        "    <integer>{every}</integer>\n",
+        "    <key>RunAtLoad</key>\n",
+        "    <true/>\n",
+        "</dict>\n",
+        "</plist>\n",
+    ), label = LAUNCHD_LABEL, arguments = arguments, every = every.as_secs())
+}
+
+/// Have the OS run `t-rs <args>` every `every`
+pub fn install(home: &Path, args: &[String], every: Duration) -> Result<()> {
+    if cfg!(windows) {
+        bail!("scheduling cleanups isn't supported on Windows yet, use the Task Scheduler to run `t-rs gc`");
+    }
+
+    let exe = std::env::current_exe().wrap_err("find the t-rs executable")?;
+    let files = files(home, &exe, args, every);
+    for (path, contents) in &files {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+        }
+        std::fs::write(path, contents).wrap_err(format!("write {path:?}"))?;
+        info!("wrote {path:?}");
+    }
+
+    if cfg!(target_os = "macos") {
+        let plist = &files[0].0;
+        // loading it again doesn't pick up changes
+        let _ = Command::new("launchctl").arg("unload").arg(plist).output();
+        run(Command::new("launchctl").arg("load").arg("-w").arg(plist))?;
+    } else {
+        run(Command::new("systemctl").args(["--user", "daemon-reload"]))?;
+        run(Command::new("systemctl").args(["--user", "enable", "--now", &format!("{NAME}.timer")]))?;
+    }
+
+    info!("t-rs will run `t-rs {}` every {}", args.join(" "), crate::age::human(every));
+    Ok(())
+}
+
+/// Undo [`install`], returning `false` if there was no timer installed
+pub fn uninstall(home: &Path) -> Result<bool> {
+    let paths: Vec<PathBuf> = files(home, Path::new(""), &[], Duration::ZERO).into_iter().map(|(path, _)| path).collect();
+    if !paths.iter().any(|i| i.exists()) {
+        return Ok(false);
+    }
+
+    if cfg!(target_os = "macos") {
+        run(Command::new("launchctl").arg("unload").arg("-w").arg(&paths[0]))?;
+    } else {
+        run(Command::new("systemctl").args(["--user", "disable", "--now", &format!("{NAME}.timer")]))?;
+    }
+
+    for path in paths.iter().filter(|i| i.exists()) {
+        std::fs::remove_file(path).wrap_err(format!("remove {path:?}"))?;
+        info!("removed {path:?}");
+    }
+
+    if !cfg!(target_os = "macos") {
+        run(Command::new("systemctl").args(["--user", "daemon-reload"]))?;
+    }
+    Ok(true)
+}
+
+fn run(cmd: &mut Command) -> Result<()> {
+    let output = cmd.output().wrap_err(format!("run {:?}", cmd.get_program()))?;
+    if !output.status.success() {
+        bail!("{cmd:?} failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_units() {
+        let args = ["gc", "--older-than", "604800s"].map(String::from);
+        let (service, timer) = systemd_units(Path::new("/usr/bin/t-rs"), &args, Duration::from_secs(86_400));
+        assert!(service.contains("ExecStart=/usr/bin/t-rs gc --older-than 604800s\n"));
+        assert!(timer.contains("OnUnitActiveSec=86400s\n"));
+
+        let plist = launchd_plist(Path::new("/usr/bin/t-rs"), &args, Duration::from_secs(3600));
+        assert!(plist.contains("<string>--older-than</string>"));
+        assert!(plist.contains("<integer>3600</integer>"));
+    }
+}
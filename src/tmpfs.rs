@@ -0,0 +1,106 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use crate::info;
+
+/// Prefix of the directories tmpfses are mounted on, which contain a single backing directory
+pub const TMPFS_PREFIX: &str = "T-RS-TMPFS";
+
+/// Mount a new tmpfs of at most `size` (like `2G` or `50%`, the kernel's default is half of the memory)
+/// in `parent`, returning where it's mounted. Needs root, so this goes through `sudo` for other users.
+pub fn mount(parent: &Path, size: Option<&str>) -> Result<PathBuf> {
+    std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+    let dir = tempdir::TempDir::new_in(parent, TMPFS_PREFIX).wrap_err("create mount point")?.into_path();
+
+    // so whoever runs t-rs can use it, not just root
+    let meta = std::fs::metadata(&dir).wrap_err(format!("get metadata of {dir:?}"))?;
+    let mut options = format!("mode=0700,uid={},gid={}", meta.uid(), meta.gid());
+    if let Some(size) = size {
+        options.push_str(&format!(",size={size}"));
+    }
+
+    info!("mounting a tmpfs on {dir:?}");
+    let status = privileged(meta.uid(), "mount")
+        .args(["-t", "tmpfs", "-o", &options, "tmpfs"])
+        .arg(&dir)
+        .status()
+        .wrap_err("run mount")?;
+    if !status.success() {
+        let _ = std::fs::remove_dir(&dir);
+        bail!("mounting a tmpfs on {dir:?} failed ({status})");
+    }
+
+    Ok(dir)
+}
+
+/// Unmount the tmpfs `backing` was in, if it was made by [`mount`], after `backing` itself is gone
+pub fn release(backing: &Path) -> Result<()> {
+    let Some(dir) = backing.parent() else {
+        return Ok(());
+    };
+    if !dir.file_name().is_some_and(|i| i.to_string_lossy().starts_with(TMPFS_PREFIX)) || !is_mount_point(dir) {
+        return Ok(());
+    }
+
+    let uid = std::fs::metadata(dir).wrap_err(format!("get metadata of {dir:?}"))?.uid();
+    let status = privileged(uid, "umount").arg(dir).status().wrap_err("run umount")?;
+    if !status.success() {
+        bail!("unmounting the tmpfs on {dir:?} failed ({status})");
+    }
+    std::fs::remove_dir(dir).wrap_err(format!("remove {dir:?}"))
+}
+
+fn is_mount_point(dir: &Path) -> bool {
+    let (Ok(meta), Some(Ok(parent))) = (std::fs::metadata(dir), dir.parent().map(std::fs::metadata)) else {
+        return false;
+    };
+    meta.dev() != parent.dev()
+}
+
+fn privileged(uid: u32, program: &str) -> Command {
+    if uid == 0 {
+        Command::new(program)
+    } else {
+        let mut cmd = Command::new("sudo");
+        cmd.arg(program);
+        cmd
+    }
+}
+
+/// Whether `dir` is on a tmpfs, so whatever goes in it takes up memory
+pub fn is_tmpfs(dir: &Path) -> bool {
+    let (Ok(dir), Ok(mounts)) = (dir.canonicalize(), std::fs::read_to_string("/proc/mounts")) else {
+        return false;
+    };
+    fs_type(&mounts, &dir) == Some("tmpfs")
+}
+
+/// The type of the filesystem `dir` is on, according to `mounts` (in the format of `/proc/mounts`)
+fn fs_type<'a>(mounts: &'a str, dir: &Path) -> Option<&'a str> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, mount_point, fs_type) = (fields.next()?, fields.next()?, fields.next()?);
+            Some((Path::new(mount_point), fs_type))
+        })
+        .filter(|(mount_point, _)| dir.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fs_type)| fs_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_fs_types() {
+        let mounts = "/dev/sda1 / ext4 rw 0 0\ntmpfs /tmp tmpfs rw 0 0\n/dev/sdb1 /tmp/disk xfs rw 0 0\n";
+        assert_eq!(fs_type(mounts, Path::new("/tmp/T-RS-TEMPDIR.x")), Some("tmpfs"));
+        assert_eq!(fs_type(mounts, Path::new("/tmp/disk/a")), Some("xfs"));
+        assert_eq!(fs_type(mounts, Path::new("/var/tmp")), Some("ext4"));
+        assert_eq!(fs_type(mounts, Path::new("/tmpfoo")), Some("ext4"));
+    }
+}
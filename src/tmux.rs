@@ -0,0 +1,88 @@
+use std::path::Path;
+use std::process::Command;
+use clap::ValueEnum;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use serde::Deserialize;
+use crate::info;
+
+/// What `t tmux` opens a tempdir in when it's run inside tmux. Outside tmux it always starts a new session
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Target {
+    /// a new window in the current session
+    #[default]
+    Window,
+    /// a new session, which tmux switches to
+    Session,
+}
+
+/// Whether we're running inside tmux
+pub fn inside() -> bool {
+    std::env::var_os("TMUX").is_some_and(|i| !i.is_empty())
+}
+
+/// The name tmux gives a session called `name`, which can't have `.` or `:` in it
+fn session_name(name: &str) -> String {
+    name.replace(['.', ':'], "_")
+}
+
+/// Open the tempdir at `dir` in a new tmux window or session named after it, with the same environment as `t shell`.
+/// If there's a session with that name already, go to that instead
+pub fn open(dir: &Path, target: Target) -> Result<()> {
+    let name = session_name(&dir.file_name().unwrap_or_default().to_string_lossy());
+    let inside = inside();
+    // `=` so tmux only takes the exact name, not one starting with it
+    let exact = format!("={name}");
+
+    let exists = Command::new("tmux")
+        .args(["has-session", "-t", exact.as_str()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|i| i.success());
+    if exists && (target == Target::Session || !inside) {
+        info!("tmux session {name:?} exists already, going there");
+        return tmux(&[if inside { "switch-client" } else { "attach-session" }, "-t", exact.as_str()]);
+    }
+
+    let mut args: Vec<String> = match (inside, target) {
+        (true, Target::Window) => vec!["new-window".into(), "-n".into(), name.clone()],
+        (true, Target::Session) => vec!["new-session".into(), "-d".into(), "-s".into(), name.clone()],
+        (false, _) => vec!["new-session".into(), "-s".into(), name.clone()],
+    };
+    args.extend(["-c".into(), dir.to_string_lossy().to_string()]);
+    // `$T_NAME`, `$T_DIR` and the rest, like in `t shell`
+    for (key, value) in crate::tempdir_env(dir)? {
+        args.extend(["-e".into(), format!("{key}={value}")]);
+    }
+    tmux(&args[..])?;
+
+    if (inside, target) == (true, Target::Session) {
+        tmux(&["switch-client", "-t", exact.as_str()])?;
+    }
+    Ok(())
+}
+
+fn tmux(args: &[impl AsRef<std::ffi::OsStr>]) -> Result<()> {
+    let status = Command::new("tmux")
+        .args(args)
+        // stdout is where the wrapper looks for the directory to cd into
+        .stdout(std::io::stderr())
+        .status()
+        .wrap_err("run tmux, is it installed?")?;
+    if !status.success() {
+        bail!("tmux failed ({status})");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_sessions() {
+        assert_eq!(session_name("foo.bar:baz"), "foo_bar_baz");
+        assert_eq!(session_name("quiet-otter"), "quiet-otter");
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::Stderr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+use crate::journal::Journal;
+use crate::layout::Layout;
+use crate::meta::Meta;
+use crate::trash::Trash;
+use crate::ui::{enter, leave, Term};
+use crate::usage::{human_size, sizes};
+use crate::{age, delete, notice};
+
+const HELP: &str = "space: mark  a: mark all  s: sort  x: delete marked  q: quit";
+
+/// A row in the table, with everything needed to decide whether it can go
+struct Entry {
+    path: PathBuf,
+    name: String,
+    size: u64,
+    age: Duration,
+    persistent: bool,
+    marked: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Sort {
+    Size,
+    Age,
+    Name,
+}
+
+impl Sort {
+    fn next(self) -> Self {
+        match self {
+            Sort::Size => Sort::Age,
+            Sort::Age => Sort::Name,
+            Sort::Name => Sort::Size,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Sort::Size => "size",
+            Sort::Age => "age",
+            Sort::Name => "name",
+        }
+    }
+}
+
+struct Top {
+    journal: Journal,
+    entries: Vec<Entry>,
+    state: TableState,
+    sort: Sort,
+    confirming: bool,
+    message: Option<String>,
+}
+
+/// Show all tempdirs by how much space they take up, and delete the ones marked for deletion.
+///
+/// Unlike `t delete`, this also removes the backing directories of non-persistent tempdirs,
+/// since the point is to free up space. Persistent tempdirs still go to the trash.
+pub fn run(tempdirs: &Path, layout: Layout, trash: &Trash) -> Result<()> {
+    let paths = layout.entries(tempdirs)?;
+    let sizes = sizes(&paths);
+
+    let mut entries = Vec::new();
+    for (path, size) in paths.into_iter().zip(sizes) {
+        entries.push(Entry {
+            name: path.strip_prefix(tempdirs).unwrap_or(&path).to_string_lossy().to_string(),
+            size,
+            age: age::age(&path)?,
+            persistent: Meta::load(&path)?.persistent(&path),
+            marked: false,
+            path,
+        });
+    }
+
+    if entries.is_empty() {
+        notice!("no active tempdirs");
+        return Ok(());
+    }
+
+    let mut top = Top {
+        journal: Journal::new(tempdirs),
+        entries,
+        state: TableState::default(),
+        sort: Sort::Size,
+        confirming: false,
+        message: None,
+    };
+    top.sort();
+    top.state.select(Some(0));
+
+    let mut terminal = enter()?;
+    let res = top.event_loop(&mut terminal, trash);
+    leave(&mut terminal)?;
+
+    res
+}
+
+impl Top {
+    fn sort(&mut self) {
+        match self.sort {
+            Sort::Size => self.entries.sort_by_key(|i| std::cmp::Reverse(i.size)),
+            Sort::Age => self.entries.sort_by_key(|i| std::cmp::Reverse(i.age)),
+            Sort::Name => self.entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+    }
+
+    fn marked(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|i| i.marked)
+    }
+
+    fn event_loop(&mut self, terminal: &mut Term, trash: &Trash) -> Result<()> {
+        loop {
+            terminal.draw(|f| self.draw(f)).wrap_err("draw")?;
+
+            let Event::Key(key) = crossterm::event::read().wrap_err("read terminal event")? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.confirming {
+                self.confirming = false;
+                if key.code == KeyCode::Char('y') {
+                    self.delete_marked(trash)?;
+                    terminal.clear().wrap_err("clear terminal")?;
+                }
+                continue;
+            }
+
+            let selected = self.state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') if selected + 1 < self.entries.len() => {
+                    self.state.select(Some(selected + 1));
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.state.select(Some(selected.saturating_sub(1))),
+                KeyCode::Char(' ') => {
+                    if let Some(i) = self.entries.get_mut(selected) {
+                        i.marked = !i.marked;
+                    }
+                }
+                KeyCode::Char('a') => {
+                    let mark = !self.entries.iter().all(|i| i.marked);
+                    self.entries.iter_mut().for_each(|i| i.marked = mark);
+                }
+                KeyCode::Char('s') => {
+                    self.sort = self.sort.next();
+                    self.sort();
+                }
+                KeyCode::Char('x') if self.marked().next().is_some() => self.confirming = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn delete_marked(&mut self, trash: &Trash) -> Result<()> {
+        let mut freed = 0;
+        let mut count = 0;
+        for i in self.entries.iter().filter(|i| i.marked) {
+            let backing = if i.persistent || !i.path.is_symlink() {
+                None
+            } else {
+                Some(std::fs::read_link(&i.path).wrap_err("read link")?)
+            };
+
+            delete(&i.path, trash, &self.journal)?;
+            if let Some(backing) = backing.filter(|i| i.exists()) {
+                std::fs::remove_dir_all(&backing).wrap_err(format!("remove {backing:?}"))?;
+            }
+
+            freed += i.size;
+            count += 1;
+        }
+
+        self.entries.retain(|i| !i.marked);
+        let selected = self.state.selected().unwrap_or(0).min(self.entries.len().saturating_sub(1));
+        self.state.select(Some(selected));
+        self.message = Some(format!("deleted {count} tempdirs ({})", human_size(freed)));
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame<CrosstermBackend<Stderr>>) {
+        let chunks = ratatui::layout::Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.size());
+
+        let rows = self.entries.iter().map(|i| {
+            Row::new(vec![
+                if i.marked { "x".to_string() } else { String::new() },
+                human_size(i.size),
+                age::human(i.age),
+                if i.persistent { "persistent".to_string() } else { String::new() },
+                i.name.clone(),
+            ])
+        });
+        let widths = [
+            Constraint::Length(1),
+            Constraint::Length(10),
+            Constraint::Length(20),
+            Constraint::Length(10),
+            Constraint::Percentage(100),
+        ];
+        let total: u64 = self.entries.iter().map(|i| i.size).sum();
+        let table = Table::new(rows)
+            .header(Row::new(vec!["", "size", "age", "", "name"]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .widths(&widths)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "{} in tempdirs, sorted by {}",
+                human_size(total),
+                self.sort.name(),
+            )))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, chunks[0], &mut self.state);
+
+        let status = if self.confirming {
+            let (count, size) = self.marked().fold((0, 0), |(count, size), i| (count + 1, size + i.size));
+            format!("really delete {count} tempdirs ({})? (y/n)", human_size(size))
+        } else {
+            self.message.clone().unwrap_or_else(|| HELP.to_string())
+        };
+        frame.render_widget(Paragraph::new(status), chunks[1]);
+    }
+}
@@ -0,0 +1,397 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use color_eyre::eyre::{bail, Context, ContextCompat};
+use color_eyre::Result;
+use fs_extra::dir::{CopyOptions, TransitProcessResult};
+use serde::{Deserialize, Serialize};
+use crate::config::{self, Config};
+use crate::meta::Meta;
+use crate::warnings::{warn, Kind};
+use crate::{age, info, meta, secret};
+
+/// Where deleted tempdirs go, so `t restore` can bring them back.
+///
+/// Every trashed tempdir has a `<trash>/<name>-<time>.toml` describing where it came from,
+/// and its contents go to the trash of the OS or, with the portable backend, `<trash>/<name>-<time>`.
+pub struct Trash {
+    dir: PathBuf,
+    home: PathBuf,
+    backend: Backend,
+    /// also trash the backing directories of non-persistent tempdirs, instead of leaving them for the OS.
+    /// Durable ones are always trashed
+    backing_dirs: bool,
+    retention: Duration,
+}
+
+/// Where the contents of trashed tempdirs go
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// the native trash of the OS, or the portable one if there isn't any
+    #[default]
+    Auto,
+    /// the freedesktop.org trash in `~/.local/share/Trash`, used by Linux desktops
+    Freedesktop,
+    /// `~/.Trash`
+    Macos,
+    /// the Windows Recycle Bin. Tempdirs in it can't be restored with `t restore`
+    RecycleBin,
+    /// t-rs' own directory in `~/.local/share/t-rs/trash`, emptied by `t gc`
+    Portable,
+}
+
+impl Backend {
+    fn detect(home: &Path) -> Self {
+        if cfg!(windows) {
+            Backend::RecycleBin
+        } else if cfg!(target_os = "macos") && home.join(".Trash").is_dir() {
+            Backend::Macos
+        } else if cfg!(unix) && !cfg!(target_os = "macos") {
+            Backend::Freedesktop
+        } else {
+            Backend::Portable
+        }
+    }
+}
+
+/// What we need to know to put a trashed tempdir back
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Trashed {
+    /// the entry in the tempdirs root
+    pub entry: PathBuf,
+    /// for non-persistent tempdirs, where the symlink pointed to
+    pub backing: Option<PathBuf>,
+    /// when it was deleted, in seconds since the unix epoch
+    pub deleted: u64,
+    pub meta: Meta,
+    /// which trash the contents went to. Older records don't have this, they're all portable
+    #[serde(default)]
+    pub backend: Backend,
+
+    #[serde(skip)]
+    id: String,
+}
+
+
+/// `$XDG_DATA_HOME`, falling back to `~/.local/share`
+pub fn data_dir(home: &Path) -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(i) if !i.is_empty() => PathBuf::from(i),
+        _ => home.join(".local").join("share"),
+    }
+}
+
+impl Trash {
+    pub fn new(home: &Path, config: &Config) -> Result<Self> {
+        Ok(Self {
+            dir: data_dir(home).join("t-rs").join("trash"),
+            home: home.to_path_buf(),
+            backend: match config.trash_backend {
+                Backend::Auto => Backend::detect(home),
+                i => i,
+            },
+            backing_dirs: config.trash_backing_dirs,
+            retention: config.trash_retention()?,
+        })
+    }
+
+    /// Move the contents of the tempdir at `entry` to the trash and remove the entry
+    pub fn delete(&self, entry: &Path) -> Result<()> {
+        let meta = Meta::load(entry)?;
+
+        if entry.is_symlink() {
+            let backing = std::fs::read_link(entry).wrap_err("read link")?;
+            symlink::remove_symlink_auto(entry).wrap_err(format!("remove symlink {entry:?}"))?;
+
+            // the files of secret tempdirs are wiped instead, see `secret::release`.
+            // Durable ones don't go away on a reboot, so they always go to the trash
+            let trash_backing = self.backing_dirs || config::is_durable(&backing, &self.home);
+            if trash_backing && backing.exists() && !secret::is_secret(&backing) {
+                self.put(&backing, entry, Some(&backing), meta)?;
+            }
+        } else if !secret::is_secret(entry) {
+            self.put(entry, entry, None, meta)?;
+        }
+
+        meta::remove(entry)
+    }
+
+    fn put(&self, contents: &Path, entry: &Path, backing: Option<&Path>, meta: Meta) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).wrap_err(format!("create {:?}", self.dir))?;
+
+        let deleted = age::now();
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        let id = format!("{name}-{deleted}");
+
+        let trashed = Trashed {
+            entry: entry.to_path_buf(),
+            backing: backing.map(Path::to_path_buf),
+            deleted,
+            meta,
+            backend: self.backend,
+            id: id.clone(),
+        };
+
+        match self.backend {
+            Backend::Auto | Backend::Portable | Backend::Macos => {
+                move_dir(contents, &self.location(&trashed).wrap_err("trash has no location")?)?;
+            }
+            Backend::Freedesktop => {
+                let trash = data_dir(&self.home).join("Trash");
+                let info_dir = trash.join("info");
+                std::fs::create_dir_all(&info_dir).wrap_err(format!("create {info_dir:?}"))?;
+                std::fs::create_dir_all(trash.join("files")).wrap_err(format!("create {trash:?}"))?;
+
+                let date = chrono::DateTime::<chrono::Local>::from(age::from_unix(deleted)).format("%Y-%m-%dT%H:%M:%S");
+                let info = format!("[Trash Info]\nPath={}\nDeletionDate={date}\n", url_escape(entry));
+                std::fs::write(info_dir.join(format!("{id}.trashinfo")), info).wrap_err("write trashinfo")?;
+                move_dir(contents, &trash.join("files").join(&id))?;
+            }
+            Backend::RecycleBin => recycle(contents)?,
+        }
+
+        let info = toml::to_string(&trashed).wrap_err("serialize trash info")?;
+        std::fs::write(self.dir.join(format!("{id}.toml")), info).wrap_err("write trash info")?;
+
+        if self.backend == Backend::RecycleBin {
+            info!("moved {contents:?} to the Recycle Bin");
+        } else {
+            info!("moved {contents:?} to the trash (use `t restore {name}` to get it back)");
+        }
+
+        Ok(())
+    }
+
+    /// Where the contents of a trashed tempdir are, if we can get at them
+    fn location(&self, trashed: &Trashed) -> Option<PathBuf> {
+        match trashed.backend {
+            Backend::Auto | Backend::Portable => Some(self.dir.join(&trashed.id)),
+            Backend::Freedesktop => Some(data_dir(&self.home).join("Trash").join("files").join(&trashed.id)),
+            Backend::Macos => Some(self.home.join(".Trash").join(&trashed.id)),
+            Backend::RecycleBin => None,
+        }
+    }
+
+    /// Everything in the trash, most recently deleted first
+    pub fn list(&self) -> Result<Vec<Trashed>> {
+        let mut res = Vec::new();
+        if !self.dir.exists() {
+            return Ok(res);
+        }
+
+        for i in std::fs::read_dir(&self.dir).wrap_err(format!("read {:?}", self.dir))? {
+            let i = i.wrap_err("read direntry")?;
+            let path = i.path();
+            if path.extension().is_none_or(|i| i != "toml") {
+                continue;
+            }
+
+            let contents = std::fs::read_to_string(&path).wrap_err(format!("read {path:?}"))?;
+            let Ok(mut trashed) = toml::from_str::<Trashed>(&contents) else {
+                warn(Kind::Unreadable, format!("trash info {path:?}"));
+                continue;
+            };
+            trashed.id = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            res.push(trashed);
+        }
+
+        res.sort_by_key(|i| std::cmp::Reverse(i.deleted));
+        Ok(res)
+    }
+
+    /// The most recently deleted tempdir called `name`, if it's in the trash
+    pub fn find(&self, name: &str) -> Result<Option<Trashed>> {
+        Ok(self.list()?.into_iter().find(|i| i.entry.file_name().is_some_and(|i| i == name)))
+    }
+
+    /// Put the most recently deleted tempdir called `name` back where it was, returning its entry
+    pub fn restore(&self, name: &str) -> Result<PathBuf> {
+        let Some(trashed) = self.find(name)? else {
+            bail!("there's no tempdir called {name:?} in the trash");
+        };
+        if trashed.entry.exists() || trashed.entry.is_symlink() {
+            bail!("can't restore {name:?} because {:?} exists again", trashed.entry);
+        }
+
+        let Some(contents) = self.location(&trashed) else {
+            bail!("{name:?} is in the Recycle Bin, restore it from there");
+        };
+        if !contents.exists() {
+            bail!("{name:?} isn't in the trash anymore, it was emptied");
+        }
+        if let Some(parent) = trashed.entry.parent() {
+            std::fs::create_dir_all(parent).wrap_err(format!("create {parent:?}"))?;
+        }
+
+        match trashed.backing {
+            Some(ref backing) => {
+                move_dir(&contents, backing)?;
+                crate::platform::link_dir(backing, &trashed.entry).wrap_err("create symlink")?;
+            }
+            None => move_dir(&contents, &trashed.entry)?,
+        }
+        trashed.meta.save(&trashed.entry)?;
+        std::fs::remove_file(self.dir.join(format!("{}.toml", trashed.id))).wrap_err("remove trash info")?;
+        if trashed.backend == Backend::Freedesktop {
+            let info = self.trash_info(&trashed);
+            std::fs::remove_file(&info).wrap_err(format!("remove {info:?}"))?;
+        }
+
+        info!("restored {:?}", trashed.entry);
+        Ok(trashed.entry)
+    }
+
+    /// How many bytes the trash takes up here. The trash of the OS keeps the contents of tempdirs elsewhere,
+    /// so for that it's only the records
+    pub fn size(&self) -> u64 {
+        crate::usage::size(&self.dir)
+    }
+
+    /// Permanently delete everything that's been in the portable or freedesktop.org trash for longer than the retention window,
+    /// and forget about what was emptied from the trash of the OS
+    pub fn purge(&self) -> Result<()> {
+        let now = age::now();
+        for i in self.list()? {
+            let info = self.dir.join(format!("{}.toml", i.id));
+
+            let native = !matches!(i.backend, Backend::Auto | Backend::Portable);
+            if native && self.location(&i).is_some_and(|i| !i.exists()) {
+                // emptied by the OS or the user
+                std::fs::remove_file(&info).wrap_err("remove trash info")?;
+                continue;
+            }
+
+            let deleted = match i.backend {
+                Backend::Auto | Backend::Portable => i.deleted,
+                // what the file managers show, which is the same unless something changed it
+                Backend::Freedesktop => deletion_date(&self.trash_info(&i)).unwrap_or(i.deleted),
+                // the Finder and Windows empty those themselves
+                Backend::Macos | Backend::RecycleBin => continue,
+            };
+            if now.saturating_sub(deleted) < self.retention.as_secs() {
+                continue;
+            }
+
+            if let Some(contents) = self.location(&i).filter(|i| i.exists()) {
+                std::fs::remove_dir_all(&contents).wrap_err(format!("remove {contents:?}"))?;
+            }
+            if i.backend == Backend::Freedesktop {
+                let trash_info = self.trash_info(&i);
+                if trash_info.exists() {
+                    std::fs::remove_file(&trash_info).wrap_err(format!("remove {trash_info:?}"))?;
+                }
+            }
+            std::fs::remove_file(&info).wrap_err("remove trash info")?;
+            info!("emptied {:?} from the trash", i.entry);
+        }
+
+        Ok(())
+    }
+
+    /// The `.trashinfo` of a tempdir in the freedesktop.org trash
+    fn trash_info(&self, trashed: &Trashed) -> PathBuf {
+        data_dir(&self.home).join("Trash").join("info").join(format!("{}.trashinfo", trashed.id))
+    }
+}
+
+/// When a `.trashinfo` says its file was deleted, in seconds since the unix epoch
+fn deletion_date(trash_info: &Path) -> Option<u64> {
+    let contents = std::fs::read_to_string(trash_info).ok()?;
+    let date = contents.lines().find_map(|i| i.strip_prefix("DeletionDate="))?;
+    let date = chrono::NaiveDateTime::parse_from_str(date.trim(), "%Y-%m-%dT%H:%M:%S").ok()?;
+    let time = date.and_local_timezone(chrono::Local).earliest()?.timestamp();
+    u64::try_from(time).ok()
+}
+
+/// Send `path` to the Windows Recycle Bin
+fn recycle(path: &Path) -> Result<()> {
+    let path = path.to_string_lossy().replace('\'', "''");
+    let script = format!(
+        "Add-Type -AssemblyName Microsoft.VisualBasic; \
+         [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteDirectory('{path}', 'OnlyErrorDialogs', 'SendToRecycleBin')"
+    );
+
+    let status = Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .status()
+        .wrap_err("run powershell")?;
+    if !status.success() {
+        bail!("moving {path} to the Recycle Bin failed ({status})");
+    }
+
+    Ok(())
+}
+
+/// Escape a path like the freedesktop.org trash spec wants for `Path=` in `.trashinfo` files
+fn url_escape(path: &Path) -> String {
+    let mut res = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => res.push(byte as char),
+            _ => res.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    res
+}
+
+/// Move a directory, also when `to` is on another filesystem.
+///
+/// Across filesystems the contents are first copied next to `to`, checked, and only then moved into place
+/// and removed from `from`. If that goes wrong halfway, `from` is left alone and the error says where the partial copy is.
+pub fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    let name = to.file_name().wrap_err(format!("{to:?} has no name"))?;
+    let partial = to.with_file_name(format!(".{}.t-rs-partial", name.to_string_lossy()));
+    if partial.exists() {
+        // left behind by a move that was interrupted before
+        std::fs::remove_dir_all(&partial).wrap_err(format!("remove {partial:?}"))?;
+    }
+    let interrupted = || format!("move {from:?} to {to:?} (it's still in {from:?}, the partial copy is in {partial:?})");
+
+    copy_dir(from, &partial).wrap_err_with(interrupted)?;
+    std::fs::rename(&partial, to).wrap_err_with(interrupted)?;
+    std::fs::remove_dir_all(from).wrap_err(format!("remove {from:?} after copying it to {to:?}"))
+}
+
+/// Copy the directory `from` to `to` (which shouldn't exist yet) with a progress bar,
+/// checking that every file and byte made it
+pub fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    let total = fs_extra::dir::get_size(from).wrap_err(format!("get size of {from:?}"))?;
+    let progress = crate::progress::bytes(total)?;
+    let copied = fs_extra::dir::copy_with_progress(from, to, &CopyOptions {
+        copy_inside: true,
+        ..Default::default()
+    }, |i| {
+        progress.set_position(i.copied_bytes);
+        TransitProcessResult::ContinueOrAbort
+    }).wrap_err(format!("copy {from:?} to {to:?}"))?;
+    progress.finish_and_clear();
+
+    let (source, copy) = (contents(from)?, contents(to)?);
+    if copied != total || copy != source {
+        bail!("copied {} files ({} bytes) of {} files ({} bytes) from {from:?} to {to:?}", copy.0, copy.1, source.0, source.1);
+    }
+    Ok(())
+}
+
+/// How many files there are in `dir`, and how many bytes they take up together
+pub fn contents(dir: &Path) -> Result<(usize, u64)> {
+    let content = fs_extra::dir::get_dir_content(dir).wrap_err(format!("list {dir:?}"))?;
+    Ok((content.files.len(), content.dir_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_trashinfo_paths() {
+        assert_eq!(url_escape(Path::new("/home/t/tempdirs/foo")), "/home/t/tempdirs/foo");
+        assert_eq!(url_escape(Path::new("/home/t/my dir/ü")), "/home/t/my%20dir/%C3%BC");
+    }
+}
@@ -0,0 +1,271 @@
+use std::io::Stderr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+use crate::journal::Journal;
+use crate::layout::Layout;
+use crate::trash::Trash;
+use crate::meta::Meta;
+use crate::usage::{human_size, size};
+use crate::{age, delete, persist, rename, shell_command};
+
+// stdout is where the wrapper looks for the directory to cd into, so draw on stderr
+pub type Term = Terminal<CrosstermBackend<Stderr>>;
+
+const HELP: &str = "enter: cd  d: delete  r: rename  p: persist  s: shell  q: quit";
+
+/// A row in the list of tempdirs
+struct Entry {
+    path: PathBuf,
+    name: String,
+    size: u64,
+    age: Duration,
+    note: Option<String>,
+    persistent: bool,
+}
+
+enum Mode {
+    Normal,
+    /// typing a new name for the selected tempdir
+    Rename(String),
+    /// asked whether to really delete the selected tempdir
+    ConfirmDelete,
+}
+
+struct App<'a> {
+    tempdirs: &'a Path,
+    layout: Layout,
+    trash: &'a Trash,
+    journal: Journal,
+    gitignore: bool,
+    entries: Vec<Entry>,
+    state: TableState,
+    mode: Mode,
+    message: Option<String>,
+}
+
+/// Manage the tempdirs in a full-screen interface, returning the tempdir to cd into (if any)
+pub fn run(tempdirs: &Path, layout: Layout, trash: &Trash, gitignore: bool) -> Result<Option<PathBuf>> {
+    let mut app = App {
+        tempdirs,
+        layout,
+        trash,
+        journal: Journal::new(tempdirs),
+        gitignore,
+        entries: Vec::new(),
+        state: TableState::default(),
+        mode: Mode::Normal,
+        message: None,
+    };
+    app.reload()?;
+
+    let mut terminal = enter()?;
+    let res = app.event_loop(&mut terminal);
+    // restore the terminal even if something went wrong, so the error is readable
+    leave(&mut terminal)?;
+
+    res
+}
+
+pub fn enter() -> Result<Term> {
+    crossterm::terminal::enable_raw_mode().wrap_err("enable raw mode")?;
+    crossterm::execute!(std::io::stderr(), EnterAlternateScreen).wrap_err("enter alternate screen")?;
+    Terminal::new(CrosstermBackend::new(std::io::stderr())).wrap_err("create terminal")
+}
+
+pub fn leave(terminal: &mut Term) -> Result<()> {
+    crossterm::terminal::disable_raw_mode().wrap_err("disable raw mode")?;
+    crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen).wrap_err("leave alternate screen")?;
+    terminal.show_cursor().wrap_err("show cursor")
+}
+
+impl App<'_> {
+    fn reload(&mut self) -> Result<()> {
+        self.entries.clear();
+        for path in self.layout.entries(self.tempdirs)? {
+            let meta = Meta::load(&path)?;
+            self.entries.push(Entry {
+                name: path.strip_prefix(self.tempdirs).unwrap_or(&path).to_string_lossy().to_string(),
+                size: size(&path),
+                age: age::age(&path)?,
+                persistent: meta.persistent(&path),
+                note: meta.note,
+                path,
+            });
+        }
+
+        let selected = match self.state.selected() {
+            _ if self.entries.is_empty() => None,
+            Some(i) => Some(i.min(self.entries.len() - 1)),
+            None => Some(0),
+        };
+        self.state.select(selected);
+
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    fn event_loop(&mut self, terminal: &mut Term) -> Result<Option<PathBuf>> {
+        loop {
+            terminal.draw(|f| self.draw(f)).wrap_err("draw")?;
+
+            let Event::Key(key) = crossterm::event::read().wrap_err("read terminal event")? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match std::mem::replace(&mut self.mode, Mode::Normal) {
+                Mode::Rename(mut new_name) => match key.code {
+                    KeyCode::Enter => self.rename(&new_name)?,
+                    KeyCode::Esc => {}
+                    KeyCode::Backspace => {
+                        new_name.pop();
+                        self.mode = Mode::Rename(new_name);
+                    }
+                    KeyCode::Char(c) => {
+                        new_name.push(c);
+                        self.mode = Mode::Rename(new_name);
+                    }
+                    _ => self.mode = Mode::Rename(new_name),
+                },
+                Mode::ConfirmDelete => {
+                    if key.code == KeyCode::Char('y') {
+                        self.delete()?;
+                    }
+                }
+                Mode::Normal => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                    KeyCode::Enter => return Ok(self.selected().map(|i| i.path.clone())),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                    KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                    KeyCode::Char('d') if self.selected().is_some() => self.mode = Mode::ConfirmDelete,
+                    KeyCode::Char('r') if self.selected().is_some() => self.mode = Mode::Rename(String::new()),
+                    KeyCode::Char('p') => self.persist()?,
+                    KeyCode::Char('s') => self.shell(terminal)?,
+                    _ => {}
+                },
+            }
+
+            // the commands we run report what they do on stderr, right over our interface
+            terminal.clear().wrap_err("clear terminal")?;
+        }
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let current = self.state.selected().unwrap_or(0) as isize;
+        let new = (current + by).clamp(0, self.entries.len() as isize - 1);
+        self.state.select(Some(new as usize));
+    }
+
+    fn delete(&mut self) -> Result<()> {
+        let Some(Entry { path, name, .. }) = self.selected() else {
+            return Ok(());
+        };
+
+        delete(path, self.trash, &self.journal)?;
+        self.message = Some(format!("deleted {name}"));
+        self.reload()
+    }
+
+    fn rename(&mut self, new_name: &str) -> Result<()> {
+        let Some(Entry { path, name, .. }) = self.selected() else {
+            return Ok(());
+        };
+        if new_name.is_empty() {
+            return Ok(());
+        }
+
+        self.message = Some(if rename(path, &path.with_file_name(new_name), &self.journal)? {
+            format!("renamed {name} to {new_name}")
+        } else {
+            format!("{new_name} already exists")
+        });
+        self.reload()
+    }
+
+    fn persist(&mut self) -> Result<()> {
+        let Some(Entry { path, name, persistent, .. }) = self.selected() else {
+            return Ok(());
+        };
+
+        self.message = Some(if *persistent {
+            format!("{name} was already persistent")
+        } else {
+            persist(path, self.gitignore, &[], &self.journal)?;
+            format!("{name} is now persistent")
+        });
+        self.reload()
+    }
+
+    /// Suspend the interface and start a shell in the selected tempdir
+    fn shell(&mut self, terminal: &mut Term) -> Result<()> {
+        let Some(entry) = self.selected() else {
+            return Ok(());
+        };
+
+        let mut cmd = shell_command(&entry.path, None)?;
+        leave(terminal)?;
+        let status = cmd.status().wrap_err("run shell");
+        *terminal = enter()?;
+        status?;
+
+        self.reload()
+    }
+
+    fn draw(&mut self, frame: &mut Frame<CrosstermBackend<Stderr>>) {
+        let chunks = ratatui::layout::Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.size());
+
+        let rows = self.entries.iter().map(|i| {
+            Row::new(vec![
+                i.name.clone(),
+                human_size(i.size),
+                age::human(i.age),
+                if i.persistent { "persistent".to_string() } else { String::new() },
+                i.note.clone().unwrap_or_default(),
+            ])
+        });
+        let widths = [
+            Constraint::Percentage(30),
+            Constraint::Length(10),
+            Constraint::Length(20),
+            Constraint::Length(10),
+            Constraint::Percentage(40),
+        ];
+        let table = Table::new(rows)
+            .header(Row::new(vec!["name", "size", "age", "", "note"]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .widths(&widths)
+            .block(Block::default().borders(Borders::ALL).title(format!("tempdirs in {}", self.tempdirs.to_string_lossy())))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, chunks[0], &mut self.state);
+
+        let status = match &self.mode {
+            Mode::Rename(new_name) => format!("new name: {new_name}"),
+            Mode::ConfirmDelete => format!(
+                "really delete {}? (y/n)",
+                self.selected().map(|i| i.name.as_str()).unwrap_or_default(),
+            ),
+            Mode::Normal => self.message.clone().unwrap_or_else(|| HELP.to_string()),
+        };
+        frame.render_widget(Paragraph::new(status), chunks[1]);
+    }
+}
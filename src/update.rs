@@ -0,0 +1,152 @@
+use std::path::Path;
+use color_eyre::eyre::{bail, eyre, Context};
+use color_eyre::Result;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::{info, progress};
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/jdonszelmann/t-rs/releases/latest";
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|i| i.name == name)
+            .ok_or_else(|| eyre!("release {} has no {name}, there might be no build for this platform", self.tag_name))
+    }
+}
+
+/// The name of the release binary for this platform, like `t-rs-x86_64-linux`.
+/// Next to it is `<name>.sha256` with its checksum
+fn asset_name() -> String {
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    format!("t-rs-{}-{}{suffix}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// One dot-separated part of a pre-release like `rc.1`. Numbers sort before text, like semver says
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Identifier {
+    Number(u64),
+    Text(String),
+}
+
+/// Whether version `a` (like `0.4.1`, `v0.4.1` or `v0.5.0-rc1`) comes after `b`.
+/// A pre-release comes before the release it's for, so `0.5.0-rc1` is newer than `0.4.1` but not than `0.5.0`
+fn newer(a: &str, b: &str) -> bool {
+    let parts = |v: &str| {
+        // build metadata after `+` doesn't count
+        let v = v.trim_start_matches('v').split('+').next().unwrap_or_default();
+        let (release, pre) = v.split_once('-').map_or((v, None), |(release, pre)| (release, Some(pre)));
+        let release: Vec<u64> = release.split('.').map(|i| i.parse().unwrap_or_default()).collect();
+        let pre: Vec<Identifier> = pre
+            .unwrap_or_default()
+            .split('.')
+            .filter(|i| !i.is_empty())
+            .map(|i| i.parse().map_or_else(|_| Identifier::Text(i.to_string()), Identifier::Number))
+            .collect();
+        (release, pre.is_empty(), pre)
+    };
+    parts(a) > parts(b)
+}
+
+/// Replace the running executable with the latest release if there's a newer one, or with `check` only say so.
+/// Returns whether there was a newer one
+pub fn self_update(check: bool) -> Result<bool> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = serde_json::from_str(
+        &ureq::get(LATEST_RELEASE)
+            .set("User-Agent", "t-rs")
+            .call()
+            .wrap_err("get the latest release")?
+            .into_string()
+            .wrap_err("read the latest release")?,
+    )
+    .wrap_err("parse the latest release")?;
+
+    if !newer(&release.tag_name, current) {
+        info!("t-rs {current} is the latest version");
+        return Ok(false);
+    }
+    if check {
+        info!("t-rs {} is out, you have {current}. `t-rs self-update` installs it", release.tag_name);
+        return Ok(true);
+    }
+
+    let name = asset_name();
+    let binary = download(&release.asset(&name)?.browser_download_url)?;
+    let checksum = String::from_utf8_lossy(&download(&release.asset(&format!("{name}.sha256"))?.browser_download_url)?).to_string();
+    let expected = checksum.split_whitespace().next().unwrap_or_default();
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if !expected.eq_ignore_ascii_case(&actual) {
+        bail!("the checksum of {name} is {actual}, but should be {expected}. Not installing it");
+    }
+
+    let exe = std::env::current_exe().wrap_err("find the running executable")?;
+    replace(&exe, &binary)?;
+    info!("updated {exe:?} from {current} to {}", release.tag_name);
+    Ok(true)
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let response = ureq::get(url).call().wrap_err(format!("download {url}"))?;
+    let progress = progress::spinner()?;
+    let mut res = Vec::new();
+    std::io::copy(&mut progress.wrap_read(response.into_reader()), &mut res).wrap_err(format!("download {url}"))?;
+    progress.finish_and_clear();
+    Ok(res)
+}
+
+/// Put `binary` where `exe` is in one step, so there's never half an executable there
+fn replace(exe: &Path, binary: &[u8]) -> Result<()> {
+    let new = exe.with_extension("new");
+    std::fs::write(&new, binary).wrap_err(format!("write {new:?}"))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new, std::fs::Permissions::from_mode(0o755)).wrap_err(format!("make {new:?} executable"))?;
+    }
+
+    // a running executable can't be overwritten on Windows, but it can be moved out of the way
+    if cfg!(windows) {
+        let old = exe.with_extension("old");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old).wrap_err(format!("move {exe:?} out of the way"))?;
+    }
+    std::fs::rename(&new, exe).wrap_err(format!("replace {exe:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions() {
+        assert!(newer("v0.10.0", "0.9.3"));
+        assert!(newer("0.9.4", "0.9.3"));
+        assert!(!newer("v0.9.3", "0.9.3"));
+        assert!(!newer("0.8", "0.9.3"));
+    }
+
+    #[test]
+    fn compares_pre_releases() {
+        assert!(!newer("v1.0.0-rc1", "1.0.0"));
+        assert!(newer("1.0.0", "1.0.0-rc1"));
+        assert!(newer("v1.0.0-rc1", "0.9.3"));
+        assert!(newer("1.0.0-rc.2", "1.0.0-rc.1"));
+        assert!(newer("1.0.0-rc.10", "1.0.0-rc.2"));
+        assert!(newer("1.0.0-rc.1", "1.0.0-beta.3"));
+        assert!(!newer("v1.0.0+build.5", "1.0.0"));
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use crate::STATE_DIR;
+
+/// Where the sizes from the last usage summary are kept, to compare the next one against
+const SIZES: &str = "sizes.json";
+
+/// The total size of the files in `path` in bytes, following `path` itself if it's a symlink.
+/// Anything we can't read counts as empty, this is only for display.
+pub fn size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+
+    fn walk(dir: &Path) -> u64 {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return 0;
+        };
+
+        entries
+            .flatten()
+            .map(|i| match i.metadata() {
+                Ok(meta) if meta.is_dir() => walk(&i.path()),
+                Ok(meta) => meta.len(),
+                Err(_) => 0,
+            })
+            .sum()
+    }
+
+    walk(path)
+}
+
+/// The sizes of all `paths`, walking them in parallel since that's mostly waiting for the disk
+pub fn sizes(paths: &[PathBuf]) -> Vec<u64> {
+    if paths.is_empty() {
+        return Vec::new();
+    }
+
+    let threads = thread::available_parallelism().map(|i| i.get()).unwrap_or(4);
+    let chunk_size = paths.len().div_ceil(threads);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(|i| size(i)).collect::<Vec<_>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|i| i.join().expect("size thread panicked"))
+            .collect()
+    })
+}
+
+/// How much space all `paths` take up together
+pub fn total(paths: &[PathBuf]) -> u64 {
+    sizes(paths).into_iter().sum()
+}
+
+/// Parse sizes like `10G` or `500M`. Units are powers of 1024, and bytes without one
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a size like `10G` or `500M`, got `{s}`"))?;
+    let bytes: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("unknown unit `{other}`, expected one of K, M, G or T")),
+    };
+
+    Ok(amount * bytes)
+}
+
+/// Sizes like `12.3 MiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// A line like `+1 tempdir, +213.0 MiB, 4.2 GiB total in 7 tempdirs`, comparing the sizes of `entries`
+/// to what they were the last time this was called
+pub fn summary(tempdirs: &Path, entries: &[PathBuf]) -> Result<String> {
+    let path = tempdirs.join(STATE_DIR).join(SIZES);
+    let before: Option<BTreeMap<PathBuf, u64>> = match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).ok(),
+        Err(_) => None,
+    };
+
+    let after: BTreeMap<PathBuf, u64> = entries.iter().cloned().zip(sizes(entries)).collect();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).wrap_err(format!("create {dir:?}"))?;
+    }
+    let contents = serde_json::to_string(&after).wrap_err("serialize sizes")?;
+    std::fs::write(&path, contents).wrap_err(format!("write {path:?}"))?;
+
+    Ok(describe_change(before.as_ref(), &after))
+}
+
+fn describe_change(before: Option<&BTreeMap<PathBuf, u64>>, after: &BTreeMap<PathBuf, u64>) -> String {
+    let plural = |n: usize| if n == 1 { "tempdir" } else { "tempdirs" };
+    let total: u64 = after.values().sum();
+    let mut parts = Vec::new();
+
+    // the first time there's nothing to compare to
+    if let Some(before) = before {
+        let count = after.len() as i64 - before.len() as i64;
+        if count != 0 {
+            parts.push(format!("{count:+} {}", plural(count.unsigned_abs() as usize)));
+        }
+
+        let old_total: u64 = before.values().sum();
+        if total != old_total {
+            let sign = if total > old_total { "+" } else { "-" };
+            parts.push(format!("{sign}{}", human_size(total.abs_diff(old_total))));
+        }
+    }
+
+    parts.push(format!("{} total in {} {}", human_size(total), after.len(), plural(after.len())));
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4K"), Ok(4096));
+        assert_eq!(parse_size("500M"), Ok(500 * 1024 * 1024));
+        assert_eq!(parse_size("10G"), Ok(10 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("2 GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("3P").is_err());
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn sizes_of_trees() {
+        let dir = tempdir::TempDir::new("t-rs-test-usage").unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/nested")).unwrap();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("a/one"), [0; 10]).unwrap();
+        std::fs::write(root.join("a/nested/two"), [0; 20]).unwrap();
+
+        assert_eq!(sizes(&[root.join("a"), root.join("b"), root.join("missing")]), vec![30, 0, 0]);
+    }
+
+    #[test]
+    fn describes_changes() {
+        let before = BTreeMap::from([(PathBuf::from("/t/a"), 1024)]);
+        let after = BTreeMap::from([(PathBuf::from("/t/a"), 1024), (PathBuf::from("/t/b"), 2048)]);
+
+        assert_eq!(describe_change(None, &before), "1.0 KiB total in 1 tempdir");
+        assert_eq!(describe_change(Some(&before), &after), "+1 tempdir, +2.0 KiB, 3.0 KiB total in 2 tempdirs");
+        assert_eq!(describe_change(Some(&after), &before), "-1 tempdir, -2.0 KiB, 1.0 KiB total in 1 tempdir");
+        assert_eq!(describe_change(Some(&after), &after), "3.0 KiB total in 2 tempdirs");
+    }
+}
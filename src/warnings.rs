@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Non-fatal problems, collected while running a command and printed together at the end
+/// so they don't get lost between the normal output.
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// a symlink whose backing directory was gone, removed by the cleanup pass
+    StaleSymlink,
+    /// something a command decided not to touch
+    Skipped,
+    /// a file of our own bookkeeping that couldn't be read
+    Unreadable,
+    /// the tempdirs are (nearly) taking up more space than `max_total_size`
+    Quota,
+    /// a backing directory that other users can look into
+    Permissions,
+    /// a tempdir holding more than its `--max-size`
+    OverLimit,
+}
+
+impl Kind {
+    fn title(self) -> &'static str {
+        match self {
+            Kind::StaleSymlink => "cleaned up stale symlinks",
+            Kind::Skipped => "skipped",
+            Kind::Unreadable => "couldn't read",
+            Kind::Quota => "disk quota",
+            Kind::Permissions => "readable by other users",
+            Kind::OverLimit => "over their size limit",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Warning {
+    pub kind: Kind,
+    pub message: String,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn warn(kind: Kind, message: impl Into<String>) {
+    let warning = Warning { kind, message: message.into() };
+    crate::log_only!("warning: {}: {}", kind.title(), warning.message);
+    WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).push(warning);
+}
+
+/// Print all warnings so far, grouped by kind
+pub fn print_summary(format: LogFormat) {
+    let mut warnings = std::mem::take(&mut *WARNINGS.lock().unwrap_or_else(|e| e.into_inner()));
+    if warnings.is_empty() {
+        return;
+    }
+    warnings.sort_by_key(|i| i.kind);
+
+    match format {
+        LogFormat::Json => {
+            for i in &warnings {
+                if let Ok(line) = serde_json::to_string(i) {
+                    eprintln!("{line}");
+                }
+            }
+        }
+        LogFormat::Text => {
+            eprintln!("\nwarnings:");
+            for group in warnings.chunk_by(|a, b| a.kind == b.kind) {
+                eprintln!("  {} ({}):", group[0].kind.title(), group.len());
+                for i in group {
+                    eprintln!("    {}", i.message);
+                }
+            }
+        }
+    }
+}
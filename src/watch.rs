@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use color_eyre::Result;
+use ignore::WalkBuilder;
+use crate::notice;
+
+/// How often to look for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Every file under `dir` with its size and modification time, sorted by path.
+///
+/// Hidden files and files excluded by `.gitignore` don't count, so `.git` and build output
+/// don't make the command run again every time it runs.
+fn snapshot(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let mut res: Vec<_> = WalkBuilder::new(dir)
+        .require_git(false)
+        .build()
+        .filter_map(|i| i.ok())
+        .filter_map(|i| {
+            let meta = i.metadata().ok()?;
+            meta.is_file().then(|| (i.path().to_path_buf(), meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+        })
+        .collect();
+
+    res.sort();
+    res
+}
+
+/// Call `run`, and again every time files in `dir` change after it returns, until interrupted
+pub fn watch(dir: &Path, mut run: impl FnMut() -> Result<()>) -> Result<()> {
+    loop {
+        run()?;
+
+        // only what changed since the last run finished counts
+        let last = snapshot(dir);
+        notice!("waiting for changes in {dir:?} (ctrl-c to stop)");
+        while snapshot(dir) == last {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        // editors often write a file in a few steps, run once they're done
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,33 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// The zoxide executable to tell about tempdirs, set once from the config. Unset unless `zoxide = true`
+static COMMAND: OnceLock<String> = OnceLock::new();
+
+pub fn set_command(command: &str) {
+    let _ = COMMAND.set(command.to_string());
+}
+
+/// Let `z` jump to the tempdir at `entry`, after it's created or entered
+pub fn add(entry: &Path) {
+    run("add", entry);
+}
+
+/// Forget about the tempdir at `entry`, after it's deleted
+pub fn remove(entry: &Path) {
+    run("remove", entry);
+}
+
+/// zoxide is a nicety, so it not being installed or not knowing a path is never an error
+fn run(subcommand: &str, entry: &Path) {
+    let Some(command) = COMMAND.get() else {
+        return;
+    };
+    let _ = Command::new(command)
+        .arg(subcommand)
+        .arg(entry)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}